@@ -0,0 +1,1267 @@
+//! Portable JSON backups of the whole budget.
+//!
+//! Rows reference each other by `uuid` rather than by local integer ID, so
+//! a backup taken on one machine can be merged into another's database.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::commands::encryption;
+use crate::commands::tags::{ensure_tag, normalize_tag};
+use crate::error::AppError;
+use crate::{db, journal, migrations, settings};
+
+/// Bumped whenever the backup layout changes incompatibly.
+pub const BACKUP_SCHEMA_VERSION: u32 = 1;
+
+/// `settings` keys for the backup written on exit. They describe this
+/// machine, so backups leave them out.
+pub const AUTO_BACKUP_DIR: &str = "auto_backup.dir";
+pub const AUTO_BACKUP_KEEP: &str = "auto_backup.keep";
+pub const AUTO_BACKUP_ENABLED: &str = "auto_backup.enabled";
+const AUTO_BACKUP_PREFIX: &str = "budget-backup-";
+const AUTO_BACKUP_SETTINGS: &str = "auto_backup.";
+
+/// Set once [`backup_before_exit`] has started the backup written on exit.
+static EXIT_BACKUP_STARTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub schema_version: u32,
+    pub exported_at: String,
+    pub accounts: Vec<BackupAccount>,
+    pub categories: Vec<BackupCategory>,
+    pub transactions: Vec<BackupTransaction>,
+    pub budgets: Vec<BackupBudget>,
+    pub recurring_rules: Vec<BackupRecurringRule>,
+    #[serde(default)]
+    pub categorization_rules: Vec<BackupCategorizationRule>,
+    #[serde(default)]
+    pub loans: Vec<BackupLoan>,
+    #[serde(default)]
+    pub payees: Vec<BackupPayee>,
+    #[serde(default)]
+    pub payee_rules: Vec<BackupPayeeRule>,
+    #[serde(default)]
+    pub goals: Vec<BackupGoal>,
+    #[serde(default)]
+    pub receipts: Vec<BackupReceipt>,
+    #[serde(default)]
+    pub fx_rates: Vec<BackupFxRate>,
+    #[serde(default)]
+    pub settings: Vec<BackupSetting>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupAccount {
+    pub uuid: String,
+    pub name: String,
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    pub currency: String,
+    #[serde(default)]
+    pub opening_balance_minor: i64,
+    #[serde(default)]
+    pub opening_date: Option<String>,
+    #[serde(default)]
+    pub statement_balance_minor: Option<i64>,
+    #[serde(default)]
+    pub statement_date: Option<String>,
+    #[serde(default = "default_true")]
+    pub allow_overdraft: bool,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_kind() -> String {
+    "Bank".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupCategory {
+    pub uuid: String,
+    pub name: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupTransaction {
+    pub uuid: String,
+    pub account: String,
+    pub date: String,
+    pub description: String,
+    pub amount_minor: i64,
+    pub category: Option<String>,
+    pub notes: Option<String>,
+    pub import_hash: Option<String>,
+    pub deleted_at: Option<String>,
+    #[serde(default)]
+    pub cleared: bool,
+    #[serde(default)]
+    pub cleared_date: Option<String>,
+    /// UUID of the debit leg when this row is half of a transfer.
+    #[serde(default)]
+    pub transfer: Option<String>,
+    #[serde(default)]
+    pub splits: Vec<BackupSplit>,
+    /// Tag names, already normalized.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// `match_key` of the payee, which stays stable across machines even
+    /// when the payee was renamed.
+    #[serde(default)]
+    pub payee: Option<String>,
+    /// UUID of the purchase this row refunds.
+    #[serde(default)]
+    pub refund_of: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupSplit {
+    pub category: String,
+    pub amount_minor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupBudget {
+    pub uuid: String,
+    pub category: String,
+    pub period: String,
+    pub limit_minor: i64,
+    #[serde(default)]
+    pub rollover_enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupRecurringRule {
+    pub uuid: String,
+    pub account: String,
+    pub description: String,
+    pub amount_minor: i64,
+    pub category: Option<String>,
+    pub interval: String,
+    pub start_date: String,
+    pub next_run: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupCategorizationRule {
+    pub uuid: String,
+    pub match_field: String,
+    pub pattern: String,
+    pub category: String,
+    pub priority: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupLoan {
+    pub uuid: String,
+    pub name: String,
+    pub principal_minor: i64,
+    pub apr_micros: i64,
+    pub term_months: i64,
+    pub start_date: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupPayee {
+    pub uuid: String,
+    pub name: String,
+    pub match_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupPayeeRule {
+    pub uuid: String,
+    pub pattern: String,
+    pub payee_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupGoal {
+    pub uuid: String,
+    pub name: String,
+    pub target_minor: i64,
+    pub target_date: String,
+    pub account: Option<String>,
+    /// Tag name, already normalized.
+    pub tag: Option<String>,
+}
+
+/// A receipt row. The stored file itself isn't part of the backup; it is
+/// found again by `file_name` in the receipts directory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupReceipt {
+    pub uuid: String,
+    pub transaction: String,
+    pub file_name: String,
+    pub original_name: String,
+    pub hash: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupFxRate {
+    pub base: String,
+    pub quote: String,
+    pub date: String,
+    pub rate_micros: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupSetting {
+    pub key: String,
+    pub value: String,
+}
+
+/// What a backup file holds, as reported by [`validate_backup`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub schema_version: u32,
+    pub exported_at: String,
+    pub accounts: usize,
+    pub categories: usize,
+    pub transactions: usize,
+    pub budgets: usize,
+    pub recurring_rules: usize,
+    pub categorization_rules: usize,
+    pub loans: usize,
+    pub payees: usize,
+    pub payee_rules: usize,
+    pub goals: usize,
+    pub receipts: usize,
+    pub fx_rates: usize,
+    pub settings: usize,
+}
+
+#[derive(Deserialize)]
+struct BackupHeader {
+    schema_version: u32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum ImportMode {
+    /// Wipe the current data and load the backup as-is.
+    Replace,
+    /// Insert rows that are new and update rows whose UUID already exists.
+    Merge,
+}
+
+#[tauri::command(async)]
+pub fn export_backup(app: AppHandle, path: String) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| write_backup(conn, Path::new(&path)))
+}
+
+/// Loads a backup inside one transaction, so a file that fails partway
+/// leaves the database exactly as it was.
+#[tauri::command(async)]
+pub fn import_backup(app: AppHandle, path: String, mode: ImportMode) -> Result<(), AppError> {
+    let backup = read_backup(Path::new(&path))?;
+    db::with_conn(&app, |conn| {
+        let tx = conn.transaction()?;
+        restore(&tx, &backup, mode)?;
+        tx.commit().map_err(AppError::from)
+    })
+}
+
+/// Reads a backup and describes what importing it would load, without
+/// touching the database. The backup is loaded into an empty in-memory
+/// database, so it fails the same way importing it with
+/// [`ImportMode::Replace`] would. A merge may still resolve references to
+/// rows that only exist locally.
+#[tauri::command(async)]
+pub fn validate_backup(path: String) -> Result<BackupManifest, AppError> {
+    let backup = read_backup(Path::new(&path))?;
+    let mut scratch = Connection::open_in_memory()?;
+    migrations::run_migrations(&scratch)?;
+    let tx = scratch.transaction()?;
+    restore(&tx, &backup, ImportMode::Replace)?;
+    drop(tx);
+    Ok(BackupManifest {
+        schema_version: backup.schema_version,
+        exported_at: backup.exported_at,
+        accounts: backup.accounts.len(),
+        categories: backup.categories.len(),
+        transactions: backup.transactions.len(),
+        budgets: backup.budgets.len(),
+        recurring_rules: backup.recurring_rules.len(),
+        categorization_rules: backup.categorization_rules.len(),
+        loans: backup.loans.len(),
+        payees: backup.payees.len(),
+        payee_rules: backup.payee_rules.len(),
+        goals: backup.goals.len(),
+        receipts: backup.receipts.len(),
+        fx_rates: backup.fx_rates.len(),
+        settings: backup.settings.len(),
+    })
+}
+
+/// Turns the backup written on exit on or off. `dir` is created if needed
+/// and only the `keep` newest automatic backups in it are kept.
+///
+/// Backups are plain JSON, so an encrypted database is never backed up
+/// automatically: this refuses to turn the backup on, and an exit after the
+/// database was encrypted skips it. Exporting by hand still works.
+#[tauri::command(async)]
+pub fn configure_auto_backup(app: AppHandle, dir: String, keep: u32, enabled: bool) -> Result<(), AppError> {
+    if enabled && encryption::is_encrypted(&app)? {
+        return Err(unencrypted_backup());
+    }
+    if keep == 0 {
+        return Err(AppError::Validation("keep must be at least 1".into()));
+    }
+    if enabled && dir.trim().is_empty() {
+        return Err(AppError::Validation("a backup directory is required".into()));
+    }
+    db::with_conn(&app, |conn| {
+        let tx = conn.transaction()?;
+        settings::set(&tx, AUTO_BACKUP_DIR, &dir)?;
+        settings::set(&tx, AUTO_BACKUP_KEEP, &keep.to_string())?;
+        settings::set(&tx, AUTO_BACKUP_ENABLED, &enabled.to_string())?;
+        tx.commit().map_err(AppError::from)
+    })
+}
+
+/// Called on the first request to exit. Writes the automatic backup on a
+/// worker thread, keeping the event loop responsive while it waits for the
+/// database, then exits with `code`. Returns whether the caller should hold
+/// off the exit until then; the exit that follows goes straight through.
+pub fn backup_before_exit(app: &AppHandle, code: i32) -> bool {
+    if EXIT_BACKUP_STARTED.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let app = app.clone();
+    thread::spawn(move || {
+        run_auto_backup(&app);
+        app.exit(code);
+    });
+    true
+}
+
+/// Errors are logged, never returned, so a failed backup can't keep the
+/// app from closing.
+pub fn run_auto_backup(app: &AppHandle) {
+    if let Err(e) = auto_backup(app) {
+        eprintln!("auto-backup failed: {e}");
+    }
+}
+
+fn auto_backup(app: &AppHandle) -> Result<(), AppError> {
+    let encrypted = encryption::is_encrypted(app)?;
+    db::with_conn(app, |conn| {
+        if settings::get_bool(conn, AUTO_BACKUP_ENABLED)? != Some(true) {
+            return Ok(());
+        }
+        if encrypted {
+            return Err(unencrypted_backup());
+        }
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        write_rotated_backup(conn, &stamp)
+    })
+}
+
+/// Writes the backup named after `stamp` into the configured directory and
+/// deletes all but the newest `keep` automatic backups there.
+fn write_rotated_backup(conn: &Connection, stamp: &str) -> Result<(), AppError> {
+    let Some(dir) = settings::get(conn, AUTO_BACKUP_DIR)? else {
+        return Ok(());
+    };
+    let keep = settings::get_int(conn, AUTO_BACKUP_KEEP)?.map_or(1, |keep| keep.max(1) as usize);
+
+    let dir = Path::new(&dir);
+    fs::create_dir_all(dir).map_err(|e| AppError::Io(format!("{}: {e}", dir.display())))?;
+    write_backup(conn, &dir.join(format!("{AUTO_BACKUP_PREFIX}{stamp}.json")))?;
+
+    // Timestamps sort lexically, so the newest names come last.
+    let mut existing: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(AUTO_BACKUP_PREFIX) && name.ends_with(".json"))
+        .collect();
+    existing.sort();
+    let excess = existing.len().saturating_sub(keep);
+    for name in &existing[..excess] {
+        fs::remove_file(dir.join(name)).map_err(|e| AppError::Io(format!("{name}: {e}")))?;
+    }
+    Ok(())
+}
+
+fn unencrypted_backup() -> AppError {
+    AppError::Validation(
+        "the database is encrypted; an automatic backup would store it unencrypted".into(),
+    )
+}
+
+pub fn write_backup(conn: &Connection, path: &Path) -> Result<(), AppError> {
+    let backup = collect(conn)?;
+    let json = serde_json::to_string_pretty(&backup)?;
+    fs::write(path, json).map_err(|e| AppError::Io(format!("{}: {e}", path.display())))
+}
+
+pub fn read_backup(path: &Path) -> Result<Backup, AppError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| AppError::Io(format!("{}: {e}", path.display())))?;
+    // The version is checked before the rest of the layout, which a newer
+    // version may have changed.
+    let header: BackupHeader = serde_json::from_str(&text).map_err(|e| {
+        if e.is_eof() {
+            AppError::Validation(format!("backup is truncated: {e}"))
+        } else if e.is_syntax() {
+            AppError::Validation(format!("backup is not valid JSON: {e}"))
+        } else {
+            AppError::Validation(format!("invalid backup at schema_version: {e}"))
+        }
+    })?;
+    if header.schema_version > BACKUP_SCHEMA_VERSION {
+        return Err(AppError::Validation(format!(
+            "backup schema version {} is newer than supported version {BACKUP_SCHEMA_VERSION}",
+            header.schema_version
+        )));
+    }
+    // The JSON itself is known to be well formed here, so any error is a
+    // field with the wrong shape; name it, e.g. `transactions[3].amount_minor`.
+    let mut deserializer = serde_json::Deserializer::from_str(&text);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        AppError::Validation(format!("invalid backup at {}: {}", e.path(), e.inner()))
+    })
+}
+
+fn collect(conn: &Connection) -> Result<Backup, AppError> {
+    // Rows are exported by account UUID, so an orphan would silently vanish.
+    let orphans: i64 = conn
+        .query_row(
+            "SELECT (SELECT count(*) FROM transactions WHERE account_id NOT IN (SELECT id FROM accounts))
+                  + (SELECT count(*) FROM recurring_rules WHERE account_id NOT IN (SELECT id FROM accounts))",
+            [],
+            |row| row.get(0),
+        )?;
+    if orphans > 0 {
+        return Err(AppError::Conflict(format!(
+            "{orphans} rows reference a missing account; fix them before exporting"
+        )));
+    }
+
+    let accounts = query_all(
+        conn,
+        "SELECT uuid, name, kind, currency, opening_balance_minor, opening_date,
+                statement_balance_minor, statement_date, allow_overdraft, archived
+         FROM accounts ORDER BY id",
+        |row| {
+            Ok(BackupAccount {
+                uuid: row.get(0)?,
+                name: row.get(1)?,
+                kind: row.get(2)?,
+                currency: row.get(3)?,
+                opening_balance_minor: row.get(4)?,
+                opening_date: row.get(5)?,
+                statement_balance_minor: row.get(6)?,
+                statement_date: row.get(7)?,
+                allow_overdraft: row.get(8)?,
+                archived: row.get(9)?,
+            })
+        },
+    )?;
+    let categories = query_all(
+        conn,
+        "SELECT c.uuid, c.name, p.uuid
+         FROM categories c LEFT JOIN categories p ON p.id = c.parent_id
+         ORDER BY c.id",
+        |row| {
+            Ok(BackupCategory {
+                uuid: row.get(0)?,
+                name: row.get(1)?,
+                parent: row.get(2)?,
+            })
+        },
+    )?;
+    let mut transactions = query_all(
+        conn,
+        "SELECT t.uuid, a.uuid, t.date, t.description, t.amount_minor, c.uuid,
+                t.notes, t.import_hash, t.deleted_at, tr.uuid, t.cleared, t.cleared_date,
+                p.match_key, rf.uuid
+         FROM transactions t
+         JOIN accounts a ON a.id = t.account_id
+         LEFT JOIN categories c ON c.id = t.category_id
+         LEFT JOIN transactions tr ON tr.id = t.transfer_id
+         LEFT JOIN payees p ON p.id = t.payee_id
+         LEFT JOIN transactions rf ON rf.id = t.refund_of_id
+         ORDER BY t.id",
+        |row| {
+            Ok(BackupTransaction {
+                uuid: row.get(0)?,
+                account: row.get(1)?,
+                date: row.get(2)?,
+                description: row.get(3)?,
+                amount_minor: row.get(4)?,
+                category: row.get(5)?,
+                notes: row.get(6)?,
+                import_hash: row.get(7)?,
+                deleted_at: row.get(8)?,
+                transfer: row.get(9)?,
+                cleared: row.get(10)?,
+                cleared_date: row.get(11)?,
+                splits: Vec::new(),
+                tags: Vec::new(),
+                payee: row.get(12)?,
+                refund_of: row.get(13)?,
+            })
+        },
+    )?;
+    {
+        let mut stmt = conn
+            .prepare(
+                "SELECT c.uuid, s.amount_minor
+                 FROM transaction_splits s
+                 JOIN transactions t ON t.id = s.transaction_id
+                 JOIN categories c ON c.id = s.category_id
+                 WHERE t.uuid = ?1
+                 ORDER BY s.id",
+            )?;
+        for t in &mut transactions {
+            t.splits = stmt
+                .query_map([&t.uuid], |row| {
+                    Ok(BackupSplit {
+                        category: row.get(0)?,
+                        amount_minor: row.get(1)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        let mut stmt = conn
+            .prepare(
+                "SELECT g.name
+                 FROM transaction_tags tt
+                 JOIN transactions t ON t.id = tt.transaction_id
+                 JOIN tags g ON g.id = tt.tag_id
+                 WHERE t.uuid = ?1
+                 ORDER BY g.name",
+            )?;
+        for t in &mut transactions {
+            t.tags = stmt
+                .query_map([&t.uuid], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+    }
+    let budgets = query_all(
+        conn,
+        "SELECT b.uuid, c.uuid, b.period, b.limit_minor, b.rollover_enabled
+         FROM budgets b JOIN categories c ON c.id = b.category_id
+         ORDER BY b.id",
+        |row| {
+            Ok(BackupBudget {
+                uuid: row.get(0)?,
+                category: row.get(1)?,
+                period: row.get(2)?,
+                limit_minor: row.get(3)?,
+                rollover_enabled: row.get(4)?,
+            })
+        },
+    )?;
+    let recurring_rules = query_all(
+        conn,
+        "SELECT r.uuid, a.uuid, r.description, r.amount_minor, c.uuid,
+                r.interval, r.start_date, r.next_run
+         FROM recurring_rules r
+         JOIN accounts a ON a.id = r.account_id
+         LEFT JOIN categories c ON c.id = r.category_id
+         ORDER BY r.id",
+        |row| {
+            Ok(BackupRecurringRule {
+                uuid: row.get(0)?,
+                account: row.get(1)?,
+                description: row.get(2)?,
+                amount_minor: row.get(3)?,
+                category: row.get(4)?,
+                interval: row.get(5)?,
+                start_date: row.get(6)?,
+                next_run: row.get(7)?,
+            })
+        },
+    )?;
+    let categorization_rules = query_all(
+        conn,
+        "SELECT r.uuid, r.match_field, r.pattern, c.uuid, r.priority
+         FROM categorization_rules r JOIN categories c ON c.id = r.category_id
+         ORDER BY r.id",
+        |row| {
+            Ok(BackupCategorizationRule {
+                uuid: row.get(0)?,
+                match_field: row.get(1)?,
+                pattern: row.get(2)?,
+                category: row.get(3)?,
+                priority: row.get(4)?,
+            })
+        },
+    )?;
+    let loans = query_all(
+        conn,
+        "SELECT uuid, name, principal_minor, apr_micros, term_months, start_date
+         FROM loans ORDER BY id",
+        |row| {
+            Ok(BackupLoan {
+                uuid: row.get(0)?,
+                name: row.get(1)?,
+                principal_minor: row.get(2)?,
+                apr_micros: row.get(3)?,
+                term_months: row.get(4)?,
+                start_date: row.get(5)?,
+            })
+        },
+    )?;
+    let payees = query_all(
+        conn,
+        "SELECT uuid, name, match_key FROM payees ORDER BY id",
+        |row| {
+            Ok(BackupPayee {
+                uuid: row.get(0)?,
+                name: row.get(1)?,
+                match_key: row.get(2)?,
+            })
+        },
+    )?;
+    let payee_rules = query_all(
+        conn,
+        "SELECT uuid, pattern, payee_name FROM payee_rules ORDER BY id",
+        |row| {
+            Ok(BackupPayeeRule {
+                uuid: row.get(0)?,
+                pattern: row.get(1)?,
+                payee_name: row.get(2)?,
+            })
+        },
+    )?;
+    let goals = query_all(
+        conn,
+        "SELECT g.uuid, g.name, g.target_minor, g.target_date, a.uuid, tg.name
+         FROM goals g
+         LEFT JOIN accounts a ON a.id = g.linked_account_id
+         LEFT JOIN tags tg ON tg.id = g.linked_tag_id
+         ORDER BY g.id",
+        |row| {
+            Ok(BackupGoal {
+                uuid: row.get(0)?,
+                name: row.get(1)?,
+                target_minor: row.get(2)?,
+                target_date: row.get(3)?,
+                account: row.get(4)?,
+                tag: row.get(5)?,
+            })
+        },
+    )?;
+    let receipts = receipt_rows(conn)?;
+    let fx_rates = query_all(
+        conn,
+        "SELECT base, quote, date, rate_micros FROM fx_rates ORDER BY base, quote, date",
+        |row| {
+            Ok(BackupFxRate {
+                base: row.get(0)?,
+                quote: row.get(1)?,
+                date: row.get(2)?,
+                rate_micros: row.get(3)?,
+            })
+        },
+    )?;
+    let settings = query_all(conn, "SELECT key, value FROM settings ORDER BY key", |row| {
+        Ok(BackupSetting {
+            key: row.get(0)?,
+            value: row.get(1)?,
+        })
+    })?
+    .into_iter()
+    .filter(|s| !s.key.starts_with(AUTO_BACKUP_SETTINGS))
+    .collect();
+
+    Ok(Backup {
+        schema_version: BACKUP_SCHEMA_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        accounts,
+        categories,
+        transactions,
+        budgets,
+        recurring_rules,
+        categorization_rules,
+        loans,
+        payees,
+        payee_rules,
+        goals,
+        receipts,
+        fx_rates,
+        settings,
+    })
+}
+
+fn receipt_rows(conn: &Connection) -> Result<Vec<BackupReceipt>, AppError> {
+    query_all(
+        conn,
+        "SELECT r.uuid, t.uuid, r.file_name, r.original_name, r.hash, r.created_at
+         FROM receipts r JOIN transactions t ON t.id = r.transaction_id
+         ORDER BY r.id",
+        |row| {
+            Ok(BackupReceipt {
+                uuid: row.get(0)?,
+                transaction: row.get(1)?,
+                file_name: row.get(2)?,
+                original_name: row.get(3)?,
+                hash: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        },
+    )
+}
+
+/// Writes `backup` into the open transaction. The caller commits. The undo
+/// journal is cleared, since its entries describe rows this may replace.
+///
+/// Replacing keeps the current receipts whose transaction is in the
+/// backup, so a backup from before receipts were exported doesn't unlink
+/// them. Receipts of transactions that are gone are dropped; their files
+/// stay in the receipts directory.
+///
+/// A transaction whose import hash is already taken in its account, by a
+/// row with another UUID, isn't loaded. The local row stands in for it:
+/// transfers, refunds and receipts that point at it link to the local row,
+/// whose own splits, tags and links are left as they are.
+pub fn restore(tx: &Transaction, backup: &Backup, mode: ImportMode) -> Result<(), AppError> {
+    journal::clear(tx)?;
+    let mut kept_receipts = Vec::new();
+    if let ImportMode::Replace = mode {
+        kept_receipts = receipt_rows(tx)?;
+        tx.execute_batch(
+            "DELETE FROM reminders;
+             DELETE FROM transaction_splits;
+             DELETE FROM transaction_tags;
+             DELETE FROM receipts;
+             DELETE FROM tags;
+             DELETE FROM transactions;
+             DELETE FROM recurring_rules;
+             DELETE FROM categorization_rules;
+             DELETE FROM loans;
+             DELETE FROM payees;
+             DELETE FROM payee_rules;
+             DELETE FROM goals;
+             DELETE FROM budgets;
+             DELETE FROM categories;
+             DELETE FROM accounts;
+             DELETE FROM fx_rates;",
+        )?;
+        tx.execute(
+            "DELETE FROM settings WHERE key NOT LIKE ?1 || '%'",
+            [AUTO_BACKUP_SETTINGS],
+        )?;
+    }
+
+    for a in &backup.accounts {
+        tx.execute(
+            "INSERT INTO accounts
+                (uuid, name, kind, currency, opening_balance_minor, opening_date,
+                 statement_balance_minor, statement_date, allow_overdraft, archived)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (uuid) DO UPDATE SET
+                name = excluded.name, kind = excluded.kind, currency = excluded.currency,
+                opening_balance_minor = excluded.opening_balance_minor,
+                opening_date = excluded.opening_date,
+                statement_balance_minor = excluded.statement_balance_minor,
+                statement_date = excluded.statement_date,
+                allow_overdraft = excluded.allow_overdraft,
+                archived = excluded.archived",
+            params![
+                a.uuid,
+                a.name,
+                a.kind,
+                a.currency,
+                a.opening_balance_minor,
+                a.opening_date,
+                a.statement_balance_minor,
+                a.statement_date,
+                a.allow_overdraft,
+                a.archived
+            ],
+        )?;
+    }
+    for c in &backup.categories {
+        tx.execute(
+            "INSERT INTO categories (uuid, name) VALUES (?1, ?2)
+             ON CONFLICT (uuid) DO UPDATE SET name = excluded.name",
+            params![c.uuid, c.name],
+        )?;
+    }
+    // Parents may come later in the list, so link once all categories exist.
+    for c in &backup.categories {
+        let parent_id = c
+            .parent
+            .as_deref()
+            .map(|p| lookup_id(tx, "categories", p, "category", &c.uuid))
+            .transpose()?;
+        tx.execute(
+            "UPDATE categories SET parent_id = ?1 WHERE uuid = ?2",
+            params![parent_id, c.uuid],
+        )?;
+    }
+    // Payees are matched on `match_key` as well, so merging two machines
+    // that each created "amazon" keeps a single payee.
+    for p in &backup.payees {
+        tx.execute(
+            "INSERT INTO payees (uuid, name, match_key) VALUES (?1, ?2, ?3)
+             ON CONFLICT (uuid) DO UPDATE SET name = excluded.name, match_key = excluded.match_key
+             ON CONFLICT (match_key) DO UPDATE SET name = excluded.name",
+            params![p.uuid, p.name, p.match_key],
+        )?;
+    }
+    for r in &backup.payee_rules {
+        tx.execute(
+            "INSERT INTO payee_rules (uuid, pattern, payee_name) VALUES (?1, ?2, ?3)
+             ON CONFLICT (uuid) DO UPDATE SET
+                pattern = excluded.pattern, payee_name = excluded.payee_name
+             ON CONFLICT (pattern) DO UPDATE SET payee_name = excluded.payee_name",
+            params![r.uuid, r.pattern, r.payee_name],
+        )?;
+    }
+    // Local ID of each backup transaction; for one that wasn't loaded, the
+    // ID of the local row holding its import hash.
+    let mut transaction_ids: HashMap<&str, i64> = HashMap::new();
+    let mut stood_in = Vec::new();
+    for t in &backup.transactions {
+        let account_id = lookup_id(tx, "accounts", &t.account, "transaction", &t.uuid)?;
+        let category_id = t
+            .category
+            .as_deref()
+            .map(|c| lookup_id(tx, "categories", c, "transaction", &t.uuid))
+            .transpose()?;
+        let payee_id: Option<i64> = t
+            .payee
+            .as_deref()
+            .map(|key| {
+                tx.query_row("SELECT id FROM payees WHERE match_key = ?1", [key], |row| row.get(0))
+                    .optional()?
+                    .ok_or_else(|| {
+                        AppError::Validation(format!(
+                            "transaction {} references unknown payee {key:?}",
+                            t.uuid
+                        ))
+                    })
+            })
+            .transpose()?;
+        tx.execute(
+            "INSERT INTO transactions
+                (uuid, account_id, date, description, amount_minor, category_id, notes, import_hash,
+                 deleted_at, cleared, cleared_date, payee_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+             ON CONFLICT (uuid) DO UPDATE SET
+                account_id = excluded.account_id, date = excluded.date,
+                description = excluded.description, amount_minor = excluded.amount_minor,
+                category_id = excluded.category_id, notes = excluded.notes,
+                import_hash = excluded.import_hash, deleted_at = excluded.deleted_at,
+                cleared = excluded.cleared, cleared_date = excluded.cleared_date,
+                payee_id = excluded.payee_id
+             ON CONFLICT (account_id, import_hash) DO NOTHING",
+            params![
+                t.uuid,
+                account_id,
+                t.date,
+                t.description,
+                t.amount_minor,
+                category_id,
+                t.notes,
+                t.import_hash,
+                t.deleted_at,
+                t.cleared,
+                t.cleared_date,
+                payee_id
+            ],
+        )?;
+        let id = match tx
+            .query_row("SELECT id FROM transactions WHERE uuid = ?1", [&t.uuid], |row| row.get(0))
+            .optional()?
+        {
+            Some(id) => id,
+            None => {
+                stood_in.push(t.uuid.as_str());
+                tx.query_row(
+                    "SELECT id FROM transactions WHERE account_id = ?1 AND import_hash = ?2",
+                    params![account_id, t.import_hash],
+                    |row| row.get(0),
+                )?
+            }
+        };
+        transaction_ids.insert(&t.uuid, id);
+    }
+    let transaction_id = |uuid: &str, owner: &str, owner_uuid: &str| {
+        match transaction_ids.get(uuid) {
+            Some(&id) => Ok(id),
+            None => lookup_id(tx, "transactions", uuid, owner, owner_uuid),
+        }
+    };
+    // Transfer legs point at each other and refunds may come before their
+    // purchase, so link them once all rows exist.
+    // Splits and tags are rewritten wholesale, like set_transaction_splits does.
+    for t in &backup.transactions {
+        if stood_in.contains(&t.uuid.as_str()) {
+            continue;
+        }
+        let id = transaction_ids[t.uuid.as_str()];
+        tx.execute("DELETE FROM transaction_splits WHERE transaction_id = ?1", [id])?;
+        for split in &t.splits {
+            let category_id = lookup_id(tx, "categories", &split.category, "transaction", &t.uuid)?;
+            tx.execute(
+                "INSERT INTO transaction_splits (transaction_id, category_id, amount_minor)
+                 VALUES (?1, ?2, ?3)",
+                params![id, category_id, split.amount_minor],
+            )?;
+        }
+        tx.execute("DELETE FROM transaction_tags WHERE transaction_id = ?1", [id])?;
+        for tag in &t.tags {
+            let tag_id = ensure_tag(tx, &normalize_tag(tag)?)?;
+            tx.execute(
+                "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag_id) VALUES (?1, ?2)",
+                params![id, tag_id],
+            )?;
+        }
+        if let Some(transfer) = &t.transfer {
+            let transfer_id = transaction_id(transfer, "transaction", &t.uuid)?;
+            tx.execute(
+                "UPDATE transactions SET transfer_id = ?1 WHERE id = ?2",
+                params![transfer_id, id],
+            )?;
+        }
+        let refund_of_id = t
+            .refund_of
+            .as_deref()
+            .map(|p| transaction_id(p, "transaction", &t.uuid))
+            .transpose()?;
+        tx.execute(
+            "UPDATE transactions SET refund_of_id = ?1 WHERE id = ?2",
+            params![refund_of_id, id],
+        )?;
+    }
+    for b in &backup.budgets {
+        let category_id = lookup_id(tx, "categories", &b.category, "budget", &b.uuid)?;
+        tx.execute(
+            "INSERT INTO budgets (uuid, category_id, period, limit_minor, rollover_enabled)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (uuid) DO UPDATE SET
+                category_id = excluded.category_id, period = excluded.period,
+                limit_minor = excluded.limit_minor, rollover_enabled = excluded.rollover_enabled
+             ON CONFLICT (category_id, period) DO UPDATE SET
+                limit_minor = excluded.limit_minor, rollover_enabled = excluded.rollover_enabled",
+            params![b.uuid, category_id, b.period, b.limit_minor, b.rollover_enabled],
+        )?;
+    }
+    for r in &backup.recurring_rules {
+        let account_id = lookup_id(tx, "accounts", &r.account, "recurring rule", &r.uuid)?;
+        let category_id = r
+            .category
+            .as_deref()
+            .map(|c| lookup_id(tx, "categories", c, "recurring rule", &r.uuid))
+            .transpose()?;
+        tx.execute(
+            "INSERT INTO recurring_rules
+                (uuid, account_id, description, amount_minor, category_id, interval, start_date, next_run)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT (uuid) DO UPDATE SET
+                account_id = excluded.account_id, description = excluded.description,
+                amount_minor = excluded.amount_minor, category_id = excluded.category_id,
+                interval = excluded.interval, start_date = excluded.start_date,
+                next_run = excluded.next_run",
+            params![
+                r.uuid,
+                account_id,
+                r.description,
+                r.amount_minor,
+                category_id,
+                r.interval,
+                r.start_date,
+                r.next_run
+            ],
+        )?;
+    }
+    for r in &backup.categorization_rules {
+        let category_id = lookup_id(tx, "categories", &r.category, "categorization rule", &r.uuid)?;
+        tx.execute(
+            "INSERT INTO categorization_rules (uuid, match_field, pattern, category_id, priority)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (uuid) DO UPDATE SET
+                match_field = excluded.match_field, pattern = excluded.pattern,
+                category_id = excluded.category_id, priority = excluded.priority",
+            params![r.uuid, r.match_field, r.pattern, category_id, r.priority],
+        )?;
+    }
+    for l in &backup.loans {
+        tx.execute(
+            "INSERT INTO loans (uuid, name, principal_minor, apr_micros, term_months, start_date)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (uuid) DO UPDATE SET
+                name = excluded.name, principal_minor = excluded.principal_minor,
+                apr_micros = excluded.apr_micros, term_months = excluded.term_months,
+                start_date = excluded.start_date",
+            params![l.uuid, l.name, l.principal_minor, l.apr_micros, l.term_months, l.start_date],
+        )?;
+    }
+    for g in &backup.goals {
+        let account_id = g
+            .account
+            .as_deref()
+            .map(|a| lookup_id(tx, "accounts", a, "goal", &g.uuid))
+            .transpose()?;
+        let tag_id = g
+            .tag
+            .as_deref()
+            .map(|t| ensure_tag(tx, &normalize_tag(t)?))
+            .transpose()?;
+        tx.execute(
+            "INSERT INTO goals (uuid, name, target_minor, target_date, linked_account_id, linked_tag_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (uuid) DO UPDATE SET
+                name = excluded.name, target_minor = excluded.target_minor,
+                target_date = excluded.target_date, linked_account_id = excluded.linked_account_id,
+                linked_tag_id = excluded.linked_tag_id",
+            params![g.uuid, g.name, g.target_minor, g.target_date, account_id, tag_id],
+        )?;
+    }
+    for r in &backup.receipts {
+        let transaction_id = transaction_id(&r.transaction, "receipt", &r.uuid)?;
+        tx.execute(
+            "INSERT INTO receipts (uuid, transaction_id, file_name, original_name, hash, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (uuid) DO UPDATE SET
+                transaction_id = excluded.transaction_id, file_name = excluded.file_name,
+                original_name = excluded.original_name, hash = excluded.hash,
+                created_at = excluded.created_at",
+            params![r.uuid, transaction_id, r.file_name, r.original_name, r.hash, r.created_at],
+        )?;
+    }
+    for r in &kept_receipts {
+        tx.execute(
+            "INSERT INTO receipts (uuid, transaction_id, file_name, original_name, hash, created_at)
+             SELECT ?1, id, ?3, ?4, ?5, ?6 FROM transactions WHERE uuid = ?2
+             ON CONFLICT (uuid) DO NOTHING",
+            params![r.uuid, r.transaction, r.file_name, r.original_name, r.hash, r.created_at],
+        )?;
+    }
+    for r in &backup.fx_rates {
+        tx.execute(
+            "INSERT INTO fx_rates (base, quote, date, rate_micros) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (base, quote, date) DO UPDATE SET rate_micros = excluded.rate_micros",
+            params![r.base, r.quote, r.date, r.rate_micros],
+        )?;
+    }
+    for s in &backup.settings {
+        if !s.key.starts_with(AUTO_BACKUP_SETTINGS) {
+            settings::set(tx, &s.key, &s.value)?;
+        }
+    }
+    Ok(())
+}
+
+fn query_all<T>(
+    conn: &Connection,
+    sql: &str,
+    map: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+) -> Result<Vec<T>, AppError> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt
+        .query_map([], map)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Local ID of the row in `table` with `uuid`; `table` is always a literal.
+fn lookup_id(
+    tx: &Transaction,
+    table: &str,
+    uuid: &str,
+    owner: &str,
+    owner_uuid: &str,
+) -> Result<i64, AppError> {
+    tx.query_row(&format!("SELECT id FROM {table} WHERE uuid = ?1"), [uuid], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| {
+            AppError::Validation(format!("{owner} {owner_uuid} references unknown {table} row {uuid}"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn replace_keeps_receipts_of_restored_transactions() {
+        let mut conn = db();
+        conn.execute_batch(
+            "INSERT INTO accounts (uuid, name) VALUES ('a', 'Checking');
+             INSERT INTO transactions (uuid, account_id, date, amount_minor)
+                 VALUES ('t1', 1, '2024-01-01', -100), ('t2', 1, '2024-01-02', -200);
+             INSERT INTO receipts (uuid, transaction_id, file_name, original_name, hash)
+                 VALUES ('r1', 1, 'h1.jpg', 'one.jpg', 'h1'), ('r2', 2, 'h2.jpg', 'two.jpg', 'h2');",
+        )
+        .unwrap();
+        let mut backup = collect(&conn).unwrap();
+        assert_eq!(backup.receipts.len(), 2);
+
+        // As if written before receipts were exported, and without t2.
+        backup.receipts.clear();
+        backup.transactions.retain(|t| t.uuid == "t1");
+        let tx = conn.transaction().unwrap();
+        restore(&tx, &backup, ImportMode::Replace).unwrap();
+        tx.commit().unwrap();
+
+        let kept: Vec<(String, String)> = conn
+            .prepare(
+                "SELECT r.uuid, t.uuid FROM receipts r JOIN transactions t ON t.id = r.transaction_id",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(kept, vec![("r1".to_string(), "t1".to_string())]);
+    }
+
+    #[test]
+    fn receipts_round_trip() {
+        let mut conn = db();
+        conn.execute_batch(
+            "INSERT INTO accounts (uuid, name) VALUES ('a', 'Checking');
+             INSERT INTO transactions (uuid, account_id, date, amount_minor)
+                 VALUES ('t1', 1, '2024-01-01', -100);
+             INSERT INTO receipts (uuid, transaction_id, file_name, original_name, hash)
+                 VALUES ('r1', 1, 'h1.jpg', 'one.jpg', 'h1');",
+        )
+        .unwrap();
+        let backup = collect(&conn).unwrap();
+        conn.execute_batch("DELETE FROM receipts").unwrap();
+        let tx = conn.transaction().unwrap();
+        restore(&tx, &backup, ImportMode::Merge).unwrap();
+        tx.commit().unwrap();
+        let name: String = conn
+            .query_row("SELECT original_name FROM receipts WHERE uuid = 'r1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(name, "one.jpg");
+    }
+
+    #[test]
+    fn a_row_with_a_taken_import_hash_is_stood_in_for_by_the_local_one() {
+        let other = db();
+        other
+            .execute_batch(
+                "INSERT INTO accounts (uuid, name) VALUES ('a', 'Checking');
+                 INSERT INTO transactions (uuid, account_id, date, amount_minor, import_hash)
+                     VALUES ('remote', 1, '2024-01-01', -100, 'h');
+                 INSERT INTO transactions (uuid, account_id, date, amount_minor, refund_of_id)
+                     VALUES ('refund', 1, '2024-01-05', 100, 1);",
+            )
+            .unwrap();
+        let backup = collect(&other).unwrap();
+
+        let mut conn = db();
+        conn.execute_batch(
+            "INSERT INTO accounts (uuid, name) VALUES ('a', 'Checking');
+             INSERT INTO transactions (id, uuid, account_id, date, amount_minor, import_hash)
+                 VALUES (7, 'local', 1, '2024-01-01', -100, 'h');",
+        )
+        .unwrap();
+        let tx = conn.transaction().unwrap();
+        restore(&tx, &backup, ImportMode::Merge).unwrap();
+        tx.commit().unwrap();
+
+        let refund_of: i64 = conn
+            .query_row("SELECT refund_of_id FROM transactions WHERE uuid = 'refund'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(refund_of, 7);
+    }
+
+    #[test]
+    fn validate_catches_dangling_references() {
+        let conn = db();
+        conn.execute_batch(
+            "INSERT INTO accounts (uuid, name) VALUES ('a', 'Checking');
+             INSERT INTO transactions (uuid, account_id, date, amount_minor)
+                 VALUES ('t1', 1, '2024-01-01', -100);",
+        )
+        .unwrap();
+        let mut backup = collect(&conn).unwrap();
+        backup.transactions[0].account = "missing".to_string();
+        let path = std::env::temp_dir().join(format!("dangling-{}.json", std::process::id()));
+        fs::write(&path, serde_json::to_string(&backup).unwrap()).unwrap();
+
+        let result = validate_backup(path.to_string_lossy().into_owned());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn fx_rates_and_settings_round_trip() {
+        let mut conn = db();
+        conn.execute_batch(
+            "INSERT INTO fx_rates (base, quote, date, rate_micros)
+                 VALUES ('EUR', 'USD', '2024-01-01', 1090000);
+             INSERT INTO settings (key, value) VALUES
+                 ('currency.reporting', 'USD'), ('auto_backup.dir', '/home/me/backups');",
+        )
+        .unwrap();
+        let backup = collect(&conn).unwrap();
+        assert_eq!(backup.settings.len(), 1);
+
+        conn.execute_batch("UPDATE settings SET value = '/mnt/other' WHERE key = 'auto_backup.dir'")
+            .unwrap();
+        let tx = conn.transaction().unwrap();
+        restore(&tx, &backup, ImportMode::Replace).unwrap();
+        tx.commit().unwrap();
+        let get = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, String>(0)).unwrap();
+        assert_eq!(get("SELECT CAST(rate_micros AS TEXT) FROM fx_rates"), "1090000");
+        assert_eq!(get("SELECT value FROM settings WHERE key = 'currency.reporting'"), "USD");
+        assert_eq!(get("SELECT value FROM settings WHERE key = 'auto_backup.dir'"), "/mnt/other");
+    }
+
+    #[test]
+    fn auto_backups_rotate_down_to_keep() {
+        let conn = db();
+        let dir = std::env::temp_dir().join(format!("auto-backup-{}", std::process::id()));
+        settings::set(&conn, AUTO_BACKUP_DIR, &dir.to_string_lossy()).unwrap();
+        settings::set(&conn, AUTO_BACKUP_KEEP, "2").unwrap();
+
+        for stamp in ["20240101-090000", "20240102-090000", "20240103-090000"] {
+            write_rotated_backup(&conn, stamp).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a backup").unwrap();
+        write_rotated_backup(&conn, "20240104-090000").unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            names,
+            [
+                "budget-backup-20240103-090000.json",
+                "budget-backup-20240104-090000.json",
+                "notes.txt",
+            ]
+        );
+    }
+}
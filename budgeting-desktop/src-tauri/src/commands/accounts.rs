@@ -0,0 +1,550 @@
+//! Account balances and ledgers.
+//!
+//! A balance is the account's opening balance plus every live transaction
+//! dated on or after its opening date. Anything earlier is assumed to be
+//! part of the opening balance already.
+//!
+//! Accounts with `allow_overdraft` off refuse any expense that would take
+//! the running balance below zero at any point from its date onwards. An
+//! edit, or an undo or redo, is refused if it takes the balance below zero,
+//! or further below than it already was; see [`OverdraftGuard`].
+//!
+//! Reconciling compares the balance of the transactions marked cleared
+//! against the closing balance of the latest bank statement.
+//!
+//! Archiving hides a closed account from [`list_accounts`] while its
+//! transactions keep counting in every report.
+
+use chrono::NaiveDate;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands::transactions::{Transaction, TRANSACTION_COLUMNS};
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub id: i64,
+    pub name: String,
+    /// Bank, Savings, Investment or Wallet, as picked in the frontend.
+    pub kind: String,
+    pub currency: String,
+    pub allow_overdraft: bool,
+    pub archived: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntry {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    /// Account balance after this transaction.
+    pub balance: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconSummary {
+    pub account_id: i64,
+    /// Opening balance plus every cleared transaction.
+    pub cleared_balance: i64,
+    /// Net of the transactions not yet cleared.
+    pub uncleared_total: i64,
+    pub uncleared_count: i64,
+    /// Cleared plus uncleared, i.e. the account balance.
+    pub working_balance: i64,
+    pub statement_balance: Option<i64>,
+    pub statement_date: Option<String>,
+    /// Statement minus cleared balance; zero once reconciled. Positive
+    /// means money on the statement is missing from the cleared set.
+    pub difference: Option<i64>,
+    /// Uncleared transactions whose amount alone would close the gap.
+    pub candidates: Vec<Transaction>,
+}
+
+/// Accounts by name. Pickers for new transactions leave `include_archived`
+/// off.
+#[tauri::command(async)]
+pub fn list_accounts(app: AppHandle, include_archived: bool) -> Result<Vec<Account>, AppError> {
+    db::with_conn(&app, |conn| accounts(conn, include_archived))
+}
+
+pub fn accounts(conn: &Connection, include_archived: bool) -> Result<Vec<Account>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, kind, currency, allow_overdraft, archived FROM accounts
+         WHERE ?1 OR NOT archived
+         ORDER BY name, id",
+    )?;
+    let rows = stmt
+        .query_map([include_archived], |row| {
+            Ok(Account {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                kind: row.get(2)?,
+                currency: row.get(3)?,
+                allow_overdraft: row.get(4)?,
+                archived: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Hides an account from [`list_accounts`]. An account with uncleared
+/// transactions is refused unless `force` is set, since those are usually
+/// still waiting to reach the bank.
+#[tauri::command(async)]
+pub fn archive_account(app: AppHandle, account_id: i64, force: bool) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| archive(conn, account_id, force))
+}
+
+#[tauri::command(async)]
+pub fn unarchive_account(app: AppHandle, account_id: i64) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| set_archived(conn, account_id, false))
+}
+
+pub fn archive(conn: &Connection, account_id: i64, force: bool) -> Result<(), AppError> {
+    if !force {
+        let uncleared: i64 = conn.query_row(
+            "SELECT count(*) FROM transactions
+             WHERE account_id = ?1 AND deleted_at IS NULL AND NOT cleared",
+            [account_id],
+            |row| row.get(0),
+        )?;
+        if uncleared > 0 {
+            return Err(AppError::Conflict(format!(
+                "account {account_id} has {uncleared} uncleared transactions; \
+                 clear them or archive with force"
+            )));
+        }
+    }
+    set_archived(conn, account_id, true)
+}
+
+fn set_archived(conn: &Connection, account_id: i64, archived: bool) -> Result<(), AppError> {
+    let changed = conn.execute(
+        "UPDATE accounts SET archived = ?1 WHERE id = ?2",
+        params![archived, account_id],
+    )?;
+    if changed == 0 {
+        return Err(AppError::NotFound(format!("account {account_id} not found")));
+    }
+    Ok(())
+}
+
+/// Balance at the end of `as_of` (ISO date), or including everything when
+/// no date is given.
+#[tauri::command(async)]
+pub fn account_balance(app: AppHandle, account_id: i64, as_of: Option<String>) -> Result<i64, AppError> {
+    db::with_conn(&app, |conn| balance_as_of(conn, account_id, as_of.as_deref()))
+}
+
+/// Transactions in date order with the running balance after each.
+#[tauri::command(async)]
+pub fn account_ledger(app: AppHandle, account_id: i64) -> Result<Vec<LedgerEntry>, AppError> {
+    db::with_conn(&app, |conn| ledger(conn, account_id))
+}
+
+pub fn ledger(conn: &Connection, account_id: i64) -> Result<Vec<LedgerEntry>, AppError> {
+    let (opening, _) = opening(conn, account_id)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+         JOIN accounts a ON a.id = t.account_id
+         WHERE t.account_id = ?1 AND t.deleted_at IS NULL
+           AND (a.opening_date IS NULL OR t.date >= a.opening_date)
+         ORDER BY t.date, t.id"
+    ))?;
+    let rows = stmt
+        .query_map([account_id], Transaction::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut balance = opening;
+    Ok(rows
+        .into_iter()
+        .map(|transaction| {
+            balance += transaction.amount_minor;
+            LedgerEntry {
+                transaction,
+                balance,
+            }
+        })
+        .collect())
+}
+
+/// Marks transactions as cleared (today) or uncleared and returns how many
+/// changed.
+#[tauri::command(async)]
+pub fn mark_cleared(app: AppHandle, ids: Vec<i64>, cleared: bool) -> Result<usize, AppError> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    db::with_conn(&app, |conn| {
+        let marks = vec!["?"; ids.len()].join(", ");
+        let mut args = vec![cleared as i64];
+        args.extend(&ids);
+        conn.execute(
+            &format!(
+                "UPDATE transactions
+                 SET cleared = ?1, cleared_date = CASE WHEN ?1 THEN date('now') END
+                 WHERE deleted_at IS NULL AND cleared != ?1 AND id IN ({marks})"
+            ),
+            params_from_iter(args),
+        )
+        .map_err(AppError::from)
+    })
+}
+
+#[tauri::command(async)]
+pub fn set_allow_overdraft(app: AppHandle, account_id: i64, allowed: bool) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| {
+        let changed = conn
+            .execute(
+                "UPDATE accounts SET allow_overdraft = ?1 WHERE id = ?2",
+                params![allowed, account_id],
+            )?;
+        if changed == 0 {
+            return Err(AppError::NotFound(format!("account {account_id} not found")));
+        }
+        Ok(())
+    })
+}
+
+/// Fails with [`AppError::InsufficientFunds`] if adding `amount_minor` on `date`
+/// would leave the account below zero at its position in the ledger or at
+/// any later point. New rows sort after existing ones on the same date.
+pub fn check_overdraft(
+    conn: &Connection,
+    account_id: i64,
+    date: &str,
+    amount_minor: i64,
+) -> Result<(), AppError> {
+    let allowed: bool = conn
+        .query_row("SELECT allow_overdraft FROM accounts WHERE id = ?1", [account_id], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("account {account_id} not found")))?;
+    if allowed || amount_minor >= 0 {
+        return Ok(());
+    }
+
+    let mut balance = balance_as_of(conn, account_id, Some(date))?;
+    let mut lowest = balance;
+    let mut stmt = conn
+        .prepare(
+            "SELECT t.amount_minor FROM transactions t
+             JOIN accounts a ON a.id = t.account_id
+             WHERE t.account_id = ?1 AND t.deleted_at IS NULL AND t.date > ?2
+               AND (a.opening_date IS NULL OR t.date >= a.opening_date)
+             ORDER BY t.date, t.id",
+        )?;
+    let later = stmt
+        .query_map(params![account_id, date], |row| row.get::<_, i64>(0))?;
+    for amount in later {
+        balance += amount?;
+        lowest = lowest.min(balance);
+    }
+
+    let shortfall = -(lowest + amount_minor);
+    if shortfall > 0 {
+        return Err(AppError::InsufficientFunds(format!(
+            "account {account_id} would be overdrawn by {shortfall}"
+        )));
+    }
+    Ok(())
+}
+
+/// The lowest running balances of some accounts before a change, to check
+/// the change against once it is made. Accounts that allow an overdraft
+/// are left out.
+pub struct OverdraftGuard {
+    from: String,
+    lowest: Vec<(i64, i64)>,
+}
+
+impl OverdraftGuard {
+    /// Records the lowest balance of each account from `from` onwards.
+    /// Fails with [`AppError::NotFound`] for a missing account.
+    pub fn new(
+        conn: &Connection,
+        account_ids: impl IntoIterator<Item = i64>,
+        from: &str,
+    ) -> Result<Self, AppError> {
+        let mut lowest = Vec::new();
+        for account_id in account_ids {
+            if lowest.iter().any(|&(id, _)| id == account_id) {
+                continue;
+            }
+            if let Some(balance) = lowest_balance(conn, account_id, from)? {
+                lowest.push((account_id, balance));
+            }
+        }
+        Ok(Self {
+            from: from.to_string(),
+            lowest,
+        })
+    }
+
+    /// Fails with [`AppError::InsufficientFunds`] if an account now goes
+    /// below zero, and lower than it did before the change.
+    pub fn check(&self, conn: &Connection) -> Result<(), AppError> {
+        for &(account_id, before) in &self.lowest {
+            let after = lowest_balance(conn, account_id, &self.from)?.unwrap_or(0);
+            if after < 0 && after < before {
+                return Err(AppError::InsufficientFunds(format!(
+                    "account {account_id} would be overdrawn by {}",
+                    -after
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lowest running balance of `account_id` from the start of `from`
+/// onwards, or `None` if the account allows an overdraft.
+fn lowest_balance(conn: &Connection, account_id: i64, from: &str) -> Result<Option<i64>, AppError> {
+    let allowed: bool = conn
+        .query_row("SELECT allow_overdraft FROM accounts WHERE id = ?1", [account_id], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("account {account_id} not found")))?;
+    if allowed {
+        return Ok(None);
+    }
+    let (mut balance, _) = opening(conn, account_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT t.date >= ?2, t.amount_minor FROM transactions t
+         JOIN accounts a ON a.id = t.account_id
+         WHERE t.account_id = ?1 AND t.deleted_at IS NULL
+           AND (a.opening_date IS NULL OR t.date >= a.opening_date)
+         ORDER BY t.date, t.id",
+    )?;
+    let rows = stmt.query_map(params![account_id, from], |row| {
+        Ok((row.get::<_, bool>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    let mut lowest = None;
+    for row in rows {
+        let (since, amount) = row?;
+        if since && lowest.is_none() {
+            lowest = Some(balance);
+        }
+        balance += amount;
+        if let Some(lowest) = &mut lowest {
+            *lowest = balance.min(*lowest);
+        }
+    }
+    Ok(Some(lowest.unwrap_or(balance)))
+}
+
+/// Records the closing balance of a bank statement to reconcile against.
+#[tauri::command(async)]
+pub fn set_statement_balance(
+    app: AppHandle,
+    account_id: i64,
+    balance_minor: i64,
+    date: String,
+) -> Result<(), AppError> {
+    NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|e| AppError::Validation(format!("invalid date {date:?}: {e}")))?;
+    db::with_conn(&app, |conn| {
+        let changed = conn
+            .execute(
+                "UPDATE accounts SET statement_balance_minor = ?1, statement_date = ?2 WHERE id = ?3",
+                params![balance_minor, date, account_id],
+            )?;
+        if changed == 0 {
+            return Err(AppError::NotFound(format!("account {account_id} not found")));
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command(async)]
+pub fn reconciliation_summary(app: AppHandle, account_id: i64) -> Result<ReconSummary, AppError> {
+    db::with_conn(&app, |conn| reconcile(conn, account_id))
+}
+
+pub fn reconcile(conn: &Connection, account_id: i64) -> Result<ReconSummary, AppError> {
+    let (opening, _) = opening(conn, account_id)?;
+    let (cleared, uncleared_total, uncleared_count): (i64, i64, i64) = conn
+        .query_row(
+            "SELECT COALESCE(SUM(CASE WHEN t.cleared THEN t.amount_minor END), 0),
+                    COALESCE(SUM(CASE WHEN NOT t.cleared THEN t.amount_minor END), 0),
+                    COUNT(CASE WHEN NOT t.cleared THEN 1 END)
+             FROM transactions t
+             JOIN accounts a ON a.id = t.account_id
+             WHERE t.account_id = ?1 AND t.deleted_at IS NULL
+               AND (a.opening_date IS NULL OR t.date >= a.opening_date)",
+            [account_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+    let (statement_balance, statement_date): (Option<i64>, Option<String>) = conn
+        .query_row(
+            "SELECT statement_balance_minor, statement_date FROM accounts WHERE id = ?1",
+            [account_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+    let cleared_balance = opening + cleared;
+    let difference = statement_balance.map(|s| s - cleared_balance);
+    let candidates = match difference {
+        Some(diff) if diff != 0 => {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+                     JOIN accounts a ON a.id = t.account_id
+                     WHERE t.account_id = ?1 AND t.deleted_at IS NULL AND NOT t.cleared
+                       AND (a.opening_date IS NULL OR t.date >= a.opening_date)
+                       AND t.amount_minor = ?2
+                     ORDER BY t.date, t.id"
+                ))?;
+            let rows = stmt
+                .query_map(params![account_id, diff], Transaction::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        }
+        _ => Vec::new(),
+    };
+
+    Ok(ReconSummary {
+        account_id,
+        cleared_balance,
+        uncleared_total,
+        uncleared_count,
+        working_balance: cleared_balance + uncleared_total,
+        statement_balance,
+        statement_date,
+        difference,
+        candidates,
+    })
+}
+
+pub fn balance_as_of(conn: &Connection, account_id: i64, as_of: Option<&str>) -> Result<i64, AppError> {
+    let (opening, opening_date) = opening(conn, account_id)?;
+    if let (Some(as_of), Some(opened)) = (as_of, opening_date.as_deref()) {
+        if as_of < opened {
+            return Ok(0);
+        }
+    }
+    let movement: i64 = conn
+        .query_row(
+            "SELECT COALESCE(SUM(t.amount_minor), 0)
+             FROM transactions t
+             JOIN accounts a ON a.id = t.account_id
+             WHERE t.account_id = ?1 AND t.deleted_at IS NULL
+               AND (a.opening_date IS NULL OR t.date >= a.opening_date)
+               AND (?2 IS NULL OR t.date <= ?2)",
+            params![account_id, as_of],
+            |row| row.get(0),
+        )?;
+    Ok(opening + movement)
+}
+
+fn opening(conn: &Connection, account_id: i64) -> Result<(i64, Option<String>), AppError> {
+    conn.query_row(
+        "SELECT opening_balance_minor, opening_date FROM accounts WHERE id = ?1",
+        [account_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("account {account_id} not found")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn reconcile_suggests_uncleared_rows_since_opening() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name, opening_balance_minor, opening_date,
+                                   statement_balance_minor, statement_date)
+             VALUES ('Checking', 10000, '2024-03-01', 7000, '2024-03-31');
+             INSERT INTO transactions (account_id, date, description, amount_minor, cleared)
+             VALUES (1, '2024-02-20', 'Before opening', -2000, 0),
+                    (1, '2024-03-05', 'Rent', -1000, 1),
+                    (1, '2024-03-10', 'Groceries', -2000, 0),
+                    (1, '2024-03-12', 'Fuel', -500, 0);",
+        )
+        .unwrap();
+
+        let summary = reconcile(&conn, 1).unwrap();
+        assert_eq!(summary.cleared_balance, 9000);
+        assert_eq!(summary.uncleared_total, -2500);
+        assert_eq!(summary.uncleared_count, 2);
+        assert_eq!(summary.working_balance, 6500);
+        assert_eq!(summary.difference, Some(-2000));
+        let candidates: Vec<_> =
+            summary.candidates.iter().map(|t| t.description.as_str()).collect();
+        assert_eq!(candidates, ["Groceries"]);
+    }
+
+    #[test]
+    fn balances_start_from_the_opening_balance() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name, opening_balance_minor, opening_date)
+             VALUES ('Checking', 10000, '2024-03-01');
+             INSERT INTO transactions (account_id, date, description, amount_minor, deleted_at)
+             VALUES (1, '2024-03-10', 'Groceries', -2500, NULL),
+                    (1, '2024-02-27', 'Before opening', -999, NULL),
+                    (1, '2024-03-05', 'Salary', 40000, NULL),
+                    (1, '2024-03-06', 'Trashed', -7000, '2024-03-07'),
+                    (1, '2024-03-05', 'Refund', 500, NULL);",
+        )
+        .unwrap();
+
+        assert_eq!(balance_as_of(&conn, 1, None).unwrap(), 48000);
+        assert_eq!(balance_as_of(&conn, 1, Some("2024-03-05")).unwrap(), 50500);
+        assert_eq!(balance_as_of(&conn, 1, Some("2024-02-28")).unwrap(), 0);
+        assert!(matches!(balance_as_of(&conn, 2, None), Err(AppError::NotFound(_))));
+
+        let running: Vec<_> = ledger(&conn, 1)
+            .unwrap()
+            .iter()
+            .map(|e| (e.transaction.description.clone(), e.balance))
+            .collect();
+        assert_eq!(
+            running,
+            [
+                ("Salary".to_string(), 50000),
+                ("Refund".to_string(), 50500),
+                ("Groceries".to_string(), 48000),
+            ]
+        );
+    }
+
+    #[test]
+    fn archived_accounts_leave_the_picker_but_keep_their_history() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking'), ('Old card');
+             INSERT INTO transactions (account_id, date, description, amount_minor, cleared)
+             VALUES (2, '2024-01-05', 'Pending', -700, 0);",
+        )
+        .unwrap();
+        let names = |include_archived| -> Vec<String> {
+            accounts(&conn, include_archived).unwrap().into_iter().map(|a| a.name).collect()
+        };
+
+        assert!(matches!(archive(&conn, 2, false), Err(AppError::Conflict(_))));
+        archive(&conn, 2, true).unwrap();
+        assert_eq!(names(false), ["Checking"]);
+        assert_eq!(names(true), ["Checking", "Old card"]);
+        assert_eq!(balance_as_of(&conn, 2, None).unwrap(), -700);
+
+        set_archived(&conn, 2, false).unwrap();
+        assert_eq!(names(false), ["Checking", "Old card"]);
+        assert!(matches!(archive(&conn, 9, true), Err(AppError::NotFound(_))));
+    }
+}
@@ -0,0 +1,279 @@
+//! Spending patterns that the plain reports don't point out.
+//!
+//! Figures follow the same rules as [`crate::commands::reports`]: live,
+//! non-transfer transactions, counted by their splits where they have any.
+//! Spending by weekday or day of the month only needs whole transactions.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands::reports::CATEGORY_LINES;
+use crate::db;
+use crate::error::AppError;
+use crate::recurring::parse_date;
+use crate::time::{month_start_day, period_containing, period_range, prev_month};
+
+/// Months of history a category needs before it can be flagged.
+pub const MIN_HISTORY_MONTHS: usize = 3;
+
+/// Longest lookback [`detect_anomalies`] accepts, ten years.
+const MAX_LOOKBACK_MONTHS: u32 = 120;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Anomaly {
+    pub category_id: i64,
+    pub category_name: String,
+    /// Net spending in the current month so far; money out is positive.
+    pub spent: i64,
+    /// Mean and population standard deviation of monthly spending over the
+    /// category's history, rounded to minor units.
+    pub mean: i64,
+    pub std_dev: i64,
+    /// How many standard deviations `spent` lies above the mean, to two
+    /// decimals; `None` when the history never varied, so any increase is
+    /// flagged.
+    pub std_devs: Option<f64>,
+}
+
+/// Money spent from `from` to `to` (ISO dates, inclusive) by day of the
+/// week, Monday first. Only expenses count; income and refunds are left out
+/// rather than netted.
+#[tauri::command(async)]
+pub fn spending_by_weekday(app: AppHandle, from: String, to: String) -> Result<[i64; 7], AppError> {
+    db::with_conn(&app, |conn| {
+        let mut totals = [0; 7];
+        for (date, spent) in daily_spending(conn, &from, &to)? {
+            totals[date.weekday().num_days_from_monday() as usize] += spent;
+        }
+        Ok(totals)
+    })
+}
+
+/// Like [`spending_by_weekday`], by day of the month from the 1st to the
+/// 31st.
+#[tauri::command(async)]
+pub fn spending_by_day_of_month(
+    app: AppHandle,
+    from: String,
+    to: String,
+) -> Result<[i64; 31], AppError> {
+    db::with_conn(&app, |conn| {
+        let mut totals = [0; 31];
+        for (date, spent) in daily_spending(conn, &from, &to)? {
+            totals[date.day0() as usize] += spent;
+        }
+        Ok(totals)
+    })
+}
+
+/// Expenses per day as positive minor units, skipping transfers. Dates are
+/// parsed here rather than bucketed with SQLite's date functions.
+pub fn daily_spending(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+) -> Result<Vec<(NaiveDate, i64)>, AppError> {
+    if parse_date(from)? > parse_date(to)? {
+        return Err(AppError::Validation(format!("{from} is after {to}")));
+    }
+    let mut stmt = conn.prepare(
+        "SELECT date, -SUM(amount_minor) FROM transactions
+         WHERE deleted_at IS NULL AND transfer_id IS NULL AND amount_minor < 0
+           AND date >= ?1 AND date <= ?2
+         GROUP BY date",
+    )?;
+    let rows = stmt
+        .query_map(params![from, to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.into_iter()
+        .map(|(date, spent)| Ok((parse_date(&date)?, spent)))
+        .collect()
+}
+
+/// Categories whose spending this month exceeds their mean plus two
+/// standard deviations over the previous `lookback_months` months, most
+/// unusual first.
+///
+/// A category's history starts with the month it was first used, and
+/// months after that without spending count as zero. Categories with
+/// fewer than [`MIN_HISTORY_MONTHS`] months of history are skipped. With
+/// `align_to_fiscal`, months are fiscal months; see [`crate::time`].
+#[tauri::command(async)]
+pub fn detect_anomalies(
+    app: AppHandle,
+    lookback_months: u32,
+    align_to_fiscal: bool,
+) -> Result<Vec<Anomaly>, AppError> {
+    db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        anomalies(conn, lookback_months, start_day, chrono::Local::now().date_naive())
+    })
+}
+
+pub fn anomalies(
+    conn: &Connection,
+    lookback_months: u32,
+    start_day: u32,
+    today: NaiveDate,
+) -> Result<Vec<Anomaly>, AppError> {
+    if !(MIN_HISTORY_MONTHS as u32..=MAX_LOOKBACK_MONTHS).contains(&lookback_months) {
+        return Err(AppError::Validation(format!(
+            "lookback_months must be between {MIN_HISTORY_MONTHS} and {MAX_LOOKBACK_MONTHS}"
+        )));
+    }
+    let current = period_containing(today, start_day)?;
+    // Oldest first; the current month comes last.
+    let mut months = vec![(current.year, current.month)];
+    for _ in 0..lookback_months {
+        let &(year, month) = months.last().unwrap();
+        months.push(prev_month(year, month));
+    }
+    months.reverse();
+    let (start, _) = period_range(months[0].0, months[0].1, start_day)?;
+    let (_, end) = period_range(current.year, current.month, start_day)?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT l.category_id, c.name, l.date, -SUM(l.amount_minor)
+         FROM ({CATEGORY_LINES}) l JOIN categories c ON c.id = l.category_id
+         WHERE l.date >= ?1 AND l.date < ?2
+         GROUP BY l.category_id, l.date"
+    ))?;
+    let days = stmt
+        .query_map(params![start, end], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT l.category_id, MIN(l.date) FROM ({CATEGORY_LINES}) l
+         WHERE l.category_id IS NOT NULL
+         GROUP BY l.category_id"
+    ))?;
+    let first_used: BTreeMap<i64, String> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    // category -> (name, spending per entry of `months`)
+    let mut spent: BTreeMap<i64, (String, Vec<i64>)> = BTreeMap::new();
+    for (category_id, name, date, amount) in days {
+        let period = period_containing(parse_date(&date)?, start_day)?;
+        if let Ok(i) = months.binary_search(&(period.year, period.month)) {
+            spent
+                .entry(category_id)
+                .or_insert_with(|| (name, vec![0; months.len()]))
+                .1[i] += amount;
+        }
+    }
+
+    let mut flagged = Vec::new();
+    for (category_id, (category_name, monthly)) in spent {
+        let Some((&current_spent, history)) = monthly.split_last() else {
+            continue;
+        };
+        let first = match first_used.get(&category_id) {
+            Some(date) => {
+                let period = period_containing(parse_date(date)?, start_day)?;
+                months.partition_point(|&m| m < (period.year, period.month))
+            }
+            None => 0,
+        };
+        let history = &history[first.min(history.len())..];
+        if history.len() < MIN_HISTORY_MONTHS || current_spent <= 0 {
+            continue;
+        }
+
+        let n = history.len() as f64;
+        let mean = history.iter().sum::<i64>() as f64 / n;
+        let variance = history.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / n;
+        let std_dev = variance.sqrt();
+        let excess = current_spent as f64 - mean;
+        if excess <= 2.0 * std_dev {
+            continue;
+        }
+        flagged.push(Anomaly {
+            category_id,
+            category_name,
+            spent: current_spent,
+            mean: mean.round() as i64,
+            std_dev: std_dev.round() as i64,
+            std_devs: (std_dev > 0.0).then(|| (excess / std_dev * 100.0).round() / 100.0),
+        });
+    }
+    flagged.sort_by(|a, b| {
+        let key = |x: &Anomaly| x.std_devs.unwrap_or(f64::INFINITY);
+        key(b).total_cmp(&key(a)).then_with(|| a.category_name.cmp(&b.category_name))
+    });
+    Ok(flagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn a_spike_beyond_two_standard_deviations_is_flagged() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (id, name)
+                 VALUES (1, 'Food'), (2, 'Fun'), (3, 'Home'), (4, 'Rent');
+             INSERT INTO transactions (account_id, date, amount_minor, category_id) VALUES
+                 (1, '2023-12-05', -100, 1), (1, '2024-01-05', -120, 1),
+                 (1, '2024-02-05', -80, 1), (1, '2024-03-05', -200, 1),
+                 (1, '2023-12-05', -100, 2), (1, '2024-01-05', -120, 2),
+                 (1, '2024-02-05', -80, 2), (1, '2024-03-05', -110, 2),
+                 (1, '2024-01-05', -10, 3), (1, '2024-02-05', -10, 3), (1, '2024-03-05', -900, 3),
+                 (1, '2023-12-01', -500, 4), (1, '2024-01-01', -500, 4),
+                 (1, '2024-02-01', -500, 4), (1, '2024-03-01', -501, 4);",
+        )
+        .unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+
+        let flagged = anomalies(&conn, 3, 1, today).unwrap();
+        let summary: Vec<_> = flagged
+            .iter()
+            .map(|a| (a.category_name.as_str(), a.spent, a.mean, a.std_devs))
+            .collect();
+        assert_eq!(summary, [("Rent", 501, 500, None), ("Food", 200, 100, Some(6.12))]);
+        assert!(matches!(anomalies(&conn, 2, 1, today), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn daily_spending_skips_income_and_transfers() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking'), ('Savings');
+             INSERT INTO transactions (account_id, date, amount_minor) VALUES
+                 (1, '2024-03-02', -300), (1, '2024-03-02', -200),
+                 (1, '2024-03-04', 5000), (1, '2024-03-09', -50), (1, '2024-04-01', -7);",
+        )
+        .unwrap();
+        crate::commands::transfers::insert_transfer(&mut conn, 1, 2, 900, "2024-03-02").unwrap();
+
+        let days = daily_spending(&conn, "2024-03-01", "2024-03-31").unwrap();
+        let days: Vec<_> = days.iter().map(|(d, s)| (d.to_string(), d.weekday(), *s)).collect();
+        assert_eq!(
+            days,
+            [
+                ("2024-03-02".to_string(), chrono::Weekday::Sat, 500),
+                ("2024-03-09".to_string(), chrono::Weekday::Sat, 50),
+            ]
+        );
+        let reversed = daily_spending(&conn, "2024-03-31", "2024-03-01");
+        assert!(matches!(reversed, Err(AppError::Validation(_))));
+    }
+}
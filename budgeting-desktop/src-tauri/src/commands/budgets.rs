@@ -0,0 +1,370 @@
+//! Per-category spending limits.
+//!
+//! A row in `budgets` sets a category's monthly limit from `period`
+//! (`YYYY-MM`) onwards, until a later row for the same category replaces it.
+//! With `rollover_enabled`, whatever is left of one month's limit (or the
+//! overspend) carries into the next, starting fresh in the row's `period`.
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands::reports::{parse_month, CATEGORY_LINES};
+use crate::db;
+use crate::error::AppError;
+use crate::time::{month_start_day, next_month, period_containing, period_range};
+
+/// Event `create_transaction` emits with a [`ThresholdCrossed`] payload.
+pub const BUDGET_THRESHOLD_CROSSED: &str = "budget-threshold-crossed";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BudgetState {
+    NoLimit,
+    UnderBudget,
+    /// At or above 90% of the limit, but not over it.
+    NearLimit,
+    OverBudget,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetStatus {
+    pub category_id: i64,
+    pub category_name: String,
+    pub limit: Option<i64>,
+    /// Net amount spent in the month, positive minor units.
+    pub spent: i64,
+    /// Limit minus spent; negative when over budget.
+    pub remaining: Option<i64>,
+    pub status: BudgetState,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectiveBudget {
+    pub category_id: i64,
+    /// `YYYY-MM` the governing budget row starts from.
+    pub since: String,
+    pub base_limit: i64,
+    pub rollover_enabled: bool,
+    /// Surplus (positive) or deficit (negative) brought in from earlier
+    /// months; always zero without rollover.
+    pub carried_over: i64,
+    pub effective_limit: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThresholdCrossed {
+    pub category_id: i64,
+    pub category_name: String,
+    /// Percentage of the limit reached: 90, or 100 once spending is at or
+    /// over the limit.
+    pub threshold: u32,
+    pub limit: i64,
+    /// Spending in the month including the new expense.
+    pub spent: i64,
+}
+
+/// With `align_to_fiscal`, spending is counted over the fiscal month; see
+/// [`crate::time`].
+#[tauri::command(async)]
+pub fn check_budget_status(
+    app: AppHandle,
+    year: i32,
+    month: u32,
+    align_to_fiscal: bool,
+) -> Result<Vec<BudgetStatus>, AppError> {
+    db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        budget_statuses(conn, year, month, start_day)
+    })
+}
+
+/// `start_day` is the day months begin on, 1 for calendar months.
+pub fn budget_statuses(
+    conn: &Connection,
+    year: i32,
+    month: u32,
+    start_day: u32,
+) -> Result<Vec<BudgetStatus>, AppError> {
+    let (start, end) = period_range(year, month, start_day)?;
+    let period = format!("{year:04}-{month:02}");
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT c.id, c.name,
+                    (SELECT b.limit_minor FROM budgets b
+                     WHERE b.category_id = c.id AND b.period <= ?3
+                     ORDER BY b.period DESC LIMIT 1),
+                    (SELECT COALESCE(-SUM(l.amount_minor), 0) FROM ({CATEGORY_LINES}) l
+                     WHERE l.category_id = c.id AND l.date >= ?1 AND l.date < ?2)
+             FROM categories c
+             ORDER BY c.name"
+        ))?;
+    let rows = stmt
+        .query_map(params![start, end, period], |row| {
+            let limit: Option<i64> = row.get(2)?;
+            let spent: i64 = row.get(3)?;
+            Ok(BudgetStatus {
+                category_id: row.get(0)?,
+                category_name: row.get(1)?,
+                limit,
+                spent,
+                remaining: limit.map(|l| l - spent),
+                status: classify(spent, limit),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// The threshold an expense of `amount_minor` on `date` would take its
+/// category to, checked before the expense is saved. Reports nothing when
+/// the category had already reached that threshold, and only 100 when one
+/// expense reaches both. Months are fiscal months and the limit is the one
+/// [`budget_statuses`] shows, without rollover.
+///
+/// Spending exactly the limit reaches 100, although [`classify`] still
+/// calls it [`BudgetState::NearLimit`] until the limit is exceeded.
+pub fn threshold_crossed(
+    conn: &Connection,
+    category_id: i64,
+    date: NaiveDate,
+    amount_minor: i64,
+) -> Result<Option<ThresholdCrossed>, AppError> {
+    if amount_minor >= 0 {
+        return Ok(None);
+    }
+    let start_day = month_start_day(conn, true)?;
+    let period = period_containing(date, start_day)?;
+    let (start, end) = period_range(period.year, period.month, start_day)?;
+    let month = format!("{:04}-{:02}", period.year, period.month);
+    let budget: Option<(String, Option<i64>, i64)> = conn
+        .query_row(
+            &format!(
+                "SELECT c.name,
+                        (SELECT b.limit_minor FROM budgets b
+                         WHERE b.category_id = c.id AND b.period <= ?4
+                         ORDER BY b.period DESC LIMIT 1),
+                        (SELECT COALESCE(-SUM(l.amount_minor), 0) FROM ({CATEGORY_LINES}) l
+                         WHERE l.category_id = c.id AND l.date >= ?2 AND l.date < ?3)
+                 FROM categories c
+                 WHERE c.id = ?1"
+            ),
+            params![category_id, start, end, month],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((category_name, Some(limit), before)) = budget else {
+        return Ok(None);
+    };
+    // Integer percentages, like `classify`; nothing spent reaches nothing.
+    let reached = |spent: i64, percent: i64| spent > 0 && spent * 100 >= limit * percent;
+    let spent = before - amount_minor;
+    let threshold = if reached(spent, 100) && !reached(before, 100) {
+        100
+    } else if reached(spent, 90) && !reached(before, 90) {
+        90
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(ThresholdCrossed {
+        category_id,
+        category_name,
+        threshold,
+        limit,
+        spent,
+    }))
+}
+
+/// With `align_to_fiscal`, every month carried over is a fiscal month.
+#[tauri::command(async)]
+pub fn compute_effective_budget(
+    app: AppHandle,
+    category_id: i64,
+    year: i32,
+    month: u32,
+    align_to_fiscal: bool,
+) -> Result<EffectiveBudget, AppError> {
+    db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        effective_budget(conn, category_id, year, month, start_day)
+    })
+}
+
+/// Recomputed from the transactions on every call, so an edit to a past
+/// month is reflected immediately. Only months since the governing row's
+/// `period` are read; an earlier row's history never carries over.
+pub fn effective_budget(
+    conn: &Connection,
+    category_id: i64,
+    year: i32,
+    month: u32,
+    start_day: u32,
+) -> Result<EffectiveBudget, AppError> {
+    let (month_start, _) = period_range(year, month, start_day)?;
+    let period = format!("{year:04}-{month:02}");
+    let (since, base_limit, rollover_enabled): (String, i64, bool) = conn
+        .query_row(
+            "SELECT period, limit_minor, rollover_enabled FROM budgets
+             WHERE category_id = ?1 AND period <= ?2
+             ORDER BY period DESC LIMIT 1",
+            params![category_id, period],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| {
+            AppError::NotFound(format!("category {category_id} has no budget for {period}"))
+        })?;
+
+    let mut carried_over = 0;
+    if rollover_enabled {
+        let (since_year, since_month) = parse_month(&since)?;
+        let (since_start, _) = period_range(since_year, since_month, start_day)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT l.date, -SUM(l.amount_minor) FROM ({CATEGORY_LINES}) l
+                 WHERE l.category_id = ?1 AND l.date >= ?2 AND l.date < ?3
+                 GROUP BY l.date
+                 ORDER BY l.date"
+            ))?;
+        let spent = stmt
+            .query_map(params![category_id, since_start, month_start], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut spent = spent.into_iter().peekable();
+        let (mut y, mut m) = (since_year, since_month);
+        while (y, m) < (year, month) {
+            let (_, end) = period_range(y, m, start_day)?;
+            carried_over += base_limit;
+            while let Some((_, amount)) = spent.next_if(|(date, _)| *date < end) {
+                carried_over -= amount;
+            }
+            (y, m) = next_month(y, m);
+        }
+    }
+
+    Ok(EffectiveBudget {
+        category_id,
+        since,
+        base_limit,
+        rollover_enabled,
+        carried_over,
+        effective_limit: base_limit + carried_over,
+    })
+}
+
+/// Compares in integers (`spent * 10 >= limit * 9`) so a zero limit needs
+/// no special case.
+pub fn classify(spent: i64, limit: Option<i64>) -> BudgetState {
+    match limit {
+        None => BudgetState::NoLimit,
+        Some(limit) if spent > limit => BudgetState::OverBudget,
+        Some(limit) if spent > 0 && spent * 10 >= limit * 9 => BudgetState::NearLimit,
+        Some(_) => BudgetState::UnderBudget,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (name) VALUES ('Food');
+             INSERT INTO budgets (category_id, period, limit_minor) VALUES (1, '2024-01', 10000);
+             INSERT INTO transactions (account_id, date, description, amount_minor, category_id)
+             VALUES (1, '2024-03-02', 'Groceries', -8000, 1);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn crossed(conn: &Connection, amount_minor: i64) -> Option<u32> {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        threshold_crossed(conn, 1, date, amount_minor).unwrap().map(|c| c.threshold)
+    }
+
+    #[test]
+    fn spending_exactly_the_limit_reaches_100() {
+        let conn = db();
+        assert_eq!(crossed(&conn, -500), None);
+        assert_eq!(crossed(&conn, -1000), Some(90));
+        assert_eq!(crossed(&conn, -2000), Some(100));
+        assert_eq!(crossed(&conn, -3000), Some(100));
+    }
+
+    #[test]
+    fn nothing_is_reported_once_the_limit_is_reached() {
+        let conn = db();
+        conn.execute("UPDATE transactions SET amount_minor = -10000", []).unwrap();
+        assert_eq!(crossed(&conn, -100), None);
+    }
+
+    #[test]
+    fn each_category_gets_a_status_against_its_latest_limit() {
+        let conn = db();
+        conn.execute_batch(
+            "INSERT INTO categories (name) VALUES ('Home'), ('Pets'), ('Toys');
+             INSERT INTO budgets (category_id, period, limit_minor)
+             VALUES (2, '2024-01', 50000), (2, '2024-03', 100000), (4, '2024-01', 0);
+             INSERT INTO transactions (account_id, date, description, amount_minor, category_id)
+             VALUES (1, '2024-03-20', 'More groceries', -1500, 1),
+                    (1, '2024-03-01', 'Rent', -90000, 2),
+                    (1, '2024-02-28', 'Old rent', -90000, 2);",
+        )
+        .unwrap();
+
+        let statuses = budget_statuses(&conn, 2024, 3, 1).unwrap();
+        let summary: Vec<_> = statuses
+            .iter()
+            .map(|s| (s.category_name.as_str(), s.spent, s.remaining, s.status))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Food", 9500, Some(500), BudgetState::NearLimit),
+                ("Home", 90000, Some(10000), BudgetState::NearLimit),
+                ("Pets", 0, None, BudgetState::NoLimit),
+                ("Toys", 0, Some(0), BudgetState::UnderBudget),
+            ]
+        );
+        assert_eq!(classify(10001, Some(10000)), BudgetState::OverBudget);
+        assert_eq!(classify(8999, Some(10000)), BudgetState::UnderBudget);
+    }
+
+    #[test]
+    fn rollover_carries_surplus_and_deficit_since_the_budget_started() {
+        let conn = db();
+        conn.execute_batch(
+            "UPDATE budgets SET rollover_enabled = 1;
+             INSERT INTO budgets (category_id, period, limit_minor, rollover_enabled)
+             VALUES (1, '2024-02', 10000, 1);
+             INSERT INTO transactions (account_id, date, description, amount_minor, category_id)
+             VALUES (1, '2024-01-15', 'Before this budget', -99999, 1),
+                    (1, '2024-02-10', 'Groceries', -6000, 1),
+                    (1, '2024-04-02', 'Party', -20000, 1);",
+        )
+        .unwrap();
+
+        // February left 4000 and March (the seeded -8000) 2000 more.
+        let april = effective_budget(&conn, 1, 2024, 4, 1).unwrap();
+        assert_eq!((april.since.as_str(), april.carried_over), ("2024-02", 6000));
+        assert_eq!(april.effective_limit, 16000);
+        // April overspent by 4000, which May starts behind by.
+        let may = effective_budget(&conn, 1, 2024, 5, 1).unwrap();
+        assert_eq!((may.carried_over, may.effective_limit), (-4000, 6000));
+
+        conn.execute("UPDATE budgets SET rollover_enabled = 0", []).unwrap();
+        assert_eq!(effective_budget(&conn, 1, 2024, 5, 1).unwrap().effective_limit, 10000);
+        assert!(matches!(effective_budget(&conn, 1, 2023, 12, 1), Err(AppError::NotFound(_))));
+    }
+}
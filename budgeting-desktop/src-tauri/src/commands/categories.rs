@@ -0,0 +1,296 @@
+//! Category maintenance.
+//!
+//! Categories nest through `parent_id`; a category without one is
+//! top-level.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryNode {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+    /// Sorted by name.
+    pub children: Vec<CategoryNode>,
+}
+
+/// Every category, nested under its parent. Top-level categories come
+/// first, sorted by name. A category whose parent no longer exists is
+/// listed at the top level rather than left out, and so is the lowest id
+/// in a loop of parents, with the rest of the loop below it.
+/// [`crate::commands::reports::CATEGORY_ROOTS`] picks the same top level.
+#[tauri::command(async)]
+pub fn category_tree(app: AppHandle) -> Result<Vec<CategoryNode>, AppError> {
+    db::with_conn(&app, |conn| build_tree(conn))
+}
+
+pub fn build_tree(conn: &Connection) -> Result<Vec<CategoryNode>, AppError> {
+    let mut stmt = conn
+        .prepare("SELECT id, name, parent_id FROM categories ORDER BY name, id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<i64>>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let parents: HashMap<i64, Option<i64>> =
+        rows.iter().map(|(id, _, parent_id)| (*id, *parent_id)).collect();
+
+    // (id, name, parent_id) keyed by the parent a category is listed under,
+    // `None` for the top level.
+    type Levels = BTreeMap<Option<i64>, Vec<(i64, String, Option<i64>)>>;
+    let mut children = Levels::new();
+    for (id, name, parent_id) in rows {
+        let listed_under = parent_id.filter(|_| !is_top_level(id, &parents));
+        children.entry(listed_under).or_default().push((id, name, parent_id));
+    }
+    fn attach(
+        listed_under: Option<i64>,
+        children: &mut Levels,
+    ) -> Vec<CategoryNode> {
+        let Some(level) = children.remove(&listed_under) else {
+            return Vec::new();
+        };
+        level
+            .into_iter()
+            .map(|(id, name, parent_id)| CategoryNode {
+                id,
+                name,
+                parent_id,
+                children: attach(Some(id), children),
+            })
+            .collect()
+    }
+    Ok(attach(None, &mut children))
+}
+
+/// Whether `id` has no parent, a missing one, or is the lowest id in a loop
+/// of parents.
+fn is_top_level(id: i64, parents: &HashMap<i64, Option<i64>>) -> bool {
+    let mut visited = vec![id];
+    let mut current = id;
+    loop {
+        let Some(&Some(parent_id)) = parents.get(&current) else {
+            // No parent, or `current` itself is missing.
+            return current == id;
+        };
+        if !parents.contains_key(&parent_id) {
+            return current == id;
+        }
+        if visited.contains(&parent_id) {
+            // Back at the start means `id` is in the loop, rather than
+            // below one.
+            return parent_id == id && visited.iter().all(|&v| v >= id);
+        }
+        visited.push(parent_id);
+        current = parent_id;
+    }
+}
+
+/// Moves a category under `parent_id`, or to the top level with `None`.
+/// Refuses a parent that is the category itself or one of its descendants.
+#[tauri::command(async)]
+pub fn set_category_parent(app: AppHandle, category_id: i64, parent_id: Option<i64>) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| set_parent(conn, category_id, parent_id))
+}
+
+pub fn set_parent(conn: &Connection, category_id: i64, parent_id: Option<i64>) -> Result<(), AppError> {
+    let parent_of = |id: i64| -> Result<Option<Option<i64>>, AppError> {
+        conn.query_row("SELECT parent_id FROM categories WHERE id = ?1", [id], |row| row.get(0))
+            .optional()
+            .map_err(AppError::from)
+    };
+    if parent_of(category_id)?.is_none() {
+        return Err(AppError::NotFound(format!("category {category_id} not found")));
+    }
+    // Walk up from the new parent; meeting the category means a loop.
+    let mut ancestor = parent_id;
+    let mut seen = HashSet::new();
+    while let Some(id) = ancestor {
+        if id == category_id {
+            return Err(AppError::Validation(format!(
+                "category {category_id} cannot be nested under itself or its own subcategory"
+            )));
+        }
+        if !seen.insert(id) {
+            return Err(AppError::Validation(format!(
+                "category {id} is already nested in a loop; move one of its ancestors first"
+            )));
+        }
+        ancestor = parent_of(id)?
+            .ok_or_else(|| AppError::NotFound(format!("category {id} not found")))?;
+    }
+    conn.execute(
+        "UPDATE categories SET parent_id = ?1 WHERE id = ?2",
+        params![parent_id, category_id],
+    )?;
+    Ok(())
+}
+
+/// Moves every live transaction (and split) in `from_category` to
+/// `to_category`, optionally only those dated within `date_range`
+/// (inclusive ISO dates). Returns how many transactions changed.
+/// Categories have no trash, so a deleted target is simply one that no
+/// longer exists and fails with [`AppError::NotFound`].
+#[tauri::command(async)]
+pub fn reassign_category(
+    app: AppHandle,
+    from_category: i64,
+    to_category: i64,
+    date_range: Option<(String, String)>,
+) -> Result<usize, AppError> {
+    db::with_conn(&app, |conn| reassign(conn, from_category, to_category, date_range.as_ref()))
+}
+
+pub fn reassign(
+    conn: &mut Connection,
+    from_category: i64,
+    to_category: i64,
+    date_range: Option<&(String, String)>,
+) -> Result<usize, AppError> {
+    if from_category == to_category {
+        return Err(AppError::Validation("source and target category are the same".into()));
+    }
+    let (from_date, to_date) = match date_range {
+        Some((from, to)) => {
+            for date in [from, to] {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|e| AppError::Validation(format!("invalid date {date:?}: {e}")))?;
+            }
+            if from > to {
+                return Err(AppError::Validation(format!("{from} is after {to}")));
+            }
+            (Some(from.as_str()), Some(to.as_str()))
+        }
+        None => (None, None),
+    };
+
+    let tx = conn.transaction()?;
+    let exists: bool = tx
+        .query_row("SELECT EXISTS (SELECT 1 FROM categories WHERE id = ?1)", [to_category], |row| {
+            row.get(0)
+        })?;
+    if !exists {
+        return Err(AppError::NotFound(format!("category {to_category} not found")));
+    }
+
+    // ?1 is the source category, ?2/?3 the optional date bounds.
+    let matching = "SELECT t.id FROM transactions t
+        WHERE t.deleted_at IS NULL
+          AND (?2 IS NULL OR t.date >= ?2) AND (?3 IS NULL OR t.date <= ?3)
+          AND (t.category_id = ?1 OR EXISTS (SELECT 1 FROM transaction_splits s
+               WHERE s.transaction_id = t.id AND s.category_id = ?1))";
+    let changed: usize = tx
+        .query_row(
+            &format!("SELECT count(*) FROM ({matching})"),
+            params![from_category, from_date, to_date],
+            |row| row.get(0),
+        )?;
+    tx.execute(
+        &format!(
+            "UPDATE transactions SET category_id = ?4
+             WHERE category_id = ?1 AND id IN ({matching})"
+        ),
+        params![from_category, from_date, to_date, to_category],
+    )?;
+    tx.execute(
+        &format!(
+            "UPDATE transaction_splits SET category_id = ?4
+             WHERE category_id = ?1 AND transaction_id IN ({matching})"
+        ),
+        params![from_category, from_date, to_date, to_category],
+    )?;
+    tx.commit()?;
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn a_parent_in_an_existing_loop_is_refused() {
+        let conn = db();
+        conn.execute_batch(
+            "INSERT INTO categories (id, name, parent_id) VALUES
+                 (1, 'Food', 2), (2, 'Groceries', 1), (3, 'Home', NULL);",
+        )
+        .unwrap();
+        let result = set_parent(&conn, 3, Some(1));
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn orphans_and_loops_are_listed_at_the_top_level() {
+        let conn = db();
+        conn.execute_batch(
+            "INSERT INTO categories (id, name, parent_id) VALUES
+                 (1, 'Food', NULL), (2, 'Groceries', 1), (3, 'Pets', 99),
+                 (4, 'A', 5), (5, 'B', 4), (6, 'Below', 5), (7, 'Self', 7);",
+        )
+        .unwrap();
+        let tree = build_tree(&conn).unwrap();
+        let top: Vec<_> = tree.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(top, ["A", "Food", "Pets", "Self"]);
+        let b = &tree[0].children[0];
+        assert_eq!(b.name, "B");
+        assert_eq!(b.children[0].name, "Below");
+        assert!(b.children[0].children.is_empty());
+        assert_eq!(tree[1].children[0].name, "Groceries");
+        assert!(tree[3].children.is_empty());
+    }
+
+    #[test]
+    fn reassign_moves_categories_and_splits_in_range() {
+        let mut conn = db();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (id, name) VALUES (1, 'Dining'), (2, 'Food'), (3, 'Home');
+             INSERT INTO transactions (id, account_id, date, description, amount_minor, category_id)
+             VALUES (1, 1, '2024-02-28', 'Cafe', -500, 1),
+                    (2, 1, '2024-03-01', 'Diner', -900, 1),
+                    (3, 1, '2024-03-02', 'Market', -700, 3);
+             INSERT INTO transaction_splits (transaction_id, category_id, amount_minor)
+             VALUES (3, 1, -200), (3, 3, -500);",
+        )
+        .unwrap();
+        let march = ("2024-03-01".to_string(), "2024-03-31".to_string());
+
+        assert_eq!(reassign(&mut conn, 1, 2, Some(&march)).unwrap(), 2);
+        let categories: Vec<i64> = conn
+            .prepare("SELECT category_id FROM transactions ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(categories, [1, 2, 3]);
+        let split_categories: Vec<i64> = conn
+            .prepare("SELECT category_id FROM transaction_splits ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(split_categories, [2, 3]);
+
+        conn.execute("DELETE FROM categories WHERE id = 3", []).unwrap();
+        assert!(matches!(reassign(&mut conn, 1, 3, None), Err(AppError::NotFound(_))));
+        assert!(matches!(reassign(&mut conn, 1, 1, None), Err(AppError::Validation(_))));
+    }
+}
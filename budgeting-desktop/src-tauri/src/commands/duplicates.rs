@@ -0,0 +1,242 @@
+//! Finding and merging near-identical transactions left by messy imports.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands::transactions::{Transaction, TRANSACTION_COLUMNS};
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub account_id: i64,
+    pub amount_minor: i64,
+    /// The normalized description every member shares.
+    pub description: String,
+    /// Oldest first.
+    pub transactions: Vec<Transaction>,
+}
+
+/// Groups live transactions with the same account, amount and normalized
+/// description whose dates are each within `window_days` of the previous
+/// member. Transfers are left out; their legs are meant to look alike.
+#[tauri::command(async)]
+pub fn find_duplicate_candidates(app: AppHandle, window_days: i64) -> Result<Vec<DuplicateGroup>, AppError> {
+    if window_days < 0 {
+        return Err(AppError::Validation("window_days must not be negative".into()));
+    }
+    db::with_conn(&app, |conn| {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+                 WHERE t.deleted_at IS NULL AND t.transfer_id IS NULL
+                 ORDER BY t.date, t.id"
+            ))?;
+        let rows = stmt
+            .query_map([], Transaction::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut buckets: BTreeMap<(i64, i64, String), Vec<Transaction>> = BTreeMap::new();
+        for t in rows {
+            let key = (t.account_id, t.amount_minor, normalize_description(&t.description));
+            buckets.entry(key).or_default().push(t);
+        }
+
+        let mut groups = Vec::new();
+        for ((account_id, amount_minor, description), members) in buckets {
+            let mut run: Vec<Transaction> = Vec::new();
+            for t in members {
+                let close = run
+                    .last()
+                    .and_then(|prev| days_between(&prev.date, &t.date))
+                    .is_some_and(|days| days <= window_days);
+                if !close && run.len() > 1 {
+                    groups.push(DuplicateGroup {
+                        account_id,
+                        amount_minor,
+                        description: description.clone(),
+                        transactions: std::mem::take(&mut run),
+                    });
+                } else if !close {
+                    run.clear();
+                }
+                run.push(t);
+            }
+            if run.len() > 1 {
+                groups.push(DuplicateGroup {
+                    account_id,
+                    amount_minor,
+                    description,
+                    transactions: run,
+                });
+            }
+        }
+        Ok(groups)
+    })
+}
+
+/// Keeps `keep_id` and moves `drop_ids` to the trash. Every dropped row
+/// must be a duplicate of the keeper: same account, amount and normalized
+/// description, and not a transfer leg. If the keeper has no category,
+/// notes or purchase it refunds, the first dropped row that has one
+/// donates it. Tags, receipts and refunds of the dropped rows move to the
+/// keeper.
+#[tauri::command(async)]
+pub fn merge_duplicates(app: AppHandle, keep_id: i64, drop_ids: Vec<i64>) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| merge(conn, keep_id, &drop_ids))
+}
+
+pub fn merge(conn: &mut Connection, keep_id: i64, drop_ids: &[i64]) -> Result<(), AppError> {
+    if drop_ids.is_empty() {
+        return Err(AppError::Validation("nothing to merge".into()));
+    }
+    if drop_ids.contains(&keep_id) {
+        return Err(AppError::Validation(format!(
+            "transaction {keep_id} cannot be both kept and dropped"
+        )));
+    }
+
+    let tx = conn.transaction()?;
+    let load = |id: i64| {
+        tx.query_row(
+            "SELECT account_id, amount_minor, description, transfer_id, category_id, notes,
+                    refund_of_id
+             FROM transactions WHERE id = ?1 AND deleted_at IS NULL",
+            [id],
+            |row| {
+                let description: String = row.get(2)?;
+                Ok(Candidate {
+                    key: (row.get(0)?, row.get(1)?, normalize_description(&description)),
+                    transfer: row.get::<_, Option<i64>>(3)?.is_some(),
+                    category_id: row.get(4)?,
+                    notes: row.get(5)?,
+                    refund_of_id: row.get(6)?,
+                })
+            },
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("transaction {id} not found")))
+    };
+
+    let mut keep = load(keep_id)?;
+    for &id in drop_ids {
+        let drop = load(id)?;
+        if keep.transfer || drop.transfer {
+            return Err(AppError::Validation(format!(
+                "transaction {} is part of a transfer and can't be merged",
+                if keep.transfer { keep_id } else { id }
+            )));
+        }
+        if drop.key != keep.key {
+            return Err(AppError::Validation(format!(
+                "transaction {id} is not a duplicate of transaction {keep_id}"
+            )));
+        }
+        keep.category_id = keep.category_id.or(drop.category_id);
+        if keep.notes.as_deref().is_none_or(|n| n.trim().is_empty()) {
+            keep.notes = drop.notes.filter(|n| !n.trim().is_empty()).or(keep.notes);
+        }
+        keep.refund_of_id = keep.refund_of_id.or(drop.refund_of_id);
+    }
+
+    tx.execute(
+        "UPDATE transactions SET category_id = ?1, notes = ?2, refund_of_id = ?3 WHERE id = ?4",
+        params![keep.category_id, keep.notes, keep.refund_of_id, keep_id],
+    )?;
+    for &id in drop_ids {
+        tx.execute(
+            "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag_id)
+             SELECT ?1, tag_id FROM transaction_tags WHERE transaction_id = ?2",
+            [keep_id, id],
+        )?;
+        tx.execute("DELETE FROM transaction_tags WHERE transaction_id = ?1", [id])?;
+        tx.execute(
+            "UPDATE receipts SET transaction_id = ?1 WHERE transaction_id = ?2",
+            [keep_id, id],
+        )?;
+        tx.execute(
+            "UPDATE transactions SET refund_of_id = ?1 WHERE refund_of_id = ?2",
+            [keep_id, id],
+        )?;
+        tx.execute(
+            "UPDATE transactions SET deleted_at = datetime('now'), refund_of_id = NULL
+             WHERE id = ?1",
+            [id],
+        )?;
+    }
+    tx.commit().map_err(AppError::from)
+}
+
+/// A transaction as [`merge`] compares and combines it.
+struct Candidate {
+    /// Account, amount and normalized description, which duplicates share.
+    key: (i64, i64, String),
+    transfer: bool,
+    category_id: Option<i64>,
+    notes: Option<String>,
+    refund_of_id: Option<i64>,
+}
+
+/// Lowercases, trims and collapses runs of whitespace.
+pub fn normalize_description(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn days_between(a: &str, b: &str) -> Option<i64> {
+    let a = NaiveDate::parse_from_str(a, "%Y-%m-%d").ok()?;
+    let b = NaiveDate::parse_from_str(b, "%Y-%m-%d").ok()?;
+    Some((b - a).num_days().abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (id, account_id, date, description, amount_minor) VALUES
+                 (1, 1, '2024-03-01', 'Coffee Shop', -450),
+                 (2, 1, '2024-03-02', 'coffee  shop', -450),
+                 (3, 1, '2024-03-02', 'Bakery', -450),
+                 (4, 1, '2024-03-09', 'Coffee shop refund', 450);
+             UPDATE transactions SET refund_of_id = 2 WHERE id = 4;
+             INSERT INTO tags (name) VALUES ('work');
+             INSERT INTO transaction_tags (transaction_id, tag_id) VALUES (2, 1);
+             INSERT INTO receipts (transaction_id, file_name, original_name, hash)
+                 VALUES (2, 'h.jpg', 'coffee.jpg', 'h');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn tags_receipts_and_refunds_follow_the_kept_row() {
+        let mut conn = db();
+        merge(&mut conn, 1, &[2]).unwrap();
+        let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT count(*) FROM transaction_tags WHERE transaction_id = 1"), 1);
+        assert_eq!(count("SELECT count(*) FROM receipts WHERE transaction_id = 1"), 1);
+        assert_eq!(count("SELECT refund_of_id FROM transactions WHERE id = 4"), 1);
+        assert_eq!(count("SELECT deleted_at IS NOT NULL FROM transactions WHERE id = 2"), 1);
+    }
+
+    #[test]
+    fn rows_that_are_not_duplicates_are_refused() {
+        let mut conn = db();
+        assert!(matches!(merge(&mut conn, 1, &[3]), Err(AppError::Validation(_))));
+        conn.execute("UPDATE transactions SET transfer_id = 9 WHERE id = 2", []).unwrap();
+        assert!(matches!(merge(&mut conn, 1, &[2]), Err(AppError::Validation(_))));
+    }
+}
@@ -0,0 +1,182 @@
+//! Opt-in SQLCipher encryption of the budget database.
+//!
+//! Only the fact that the file is encrypted is persisted (next to the
+//! database in `encryption.json`, or `profile_{id}.encryption.json` for
+//! profiles other than the default); the passphrase itself lives in
+//! [`DbKey`] for the session and must be supplied again on every launch.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::db::{self, DbKey};
+use crate::error::AppError;
+use crate::profiles::{self, ActiveProfile, DEFAULT_PROFILE};
+use crate::{migrations, recurring};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EncryptionConfig {
+    encrypted: bool,
+}
+
+fn config_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let profile = *app.state::<ActiveProfile>().0.lock().unwrap();
+    let name = if profile == DEFAULT_PROFILE {
+        "encryption.json".to_string()
+    } else {
+        format!("profile_{profile}.encryption.json")
+    };
+    Ok(db::data_dir(app)?.join(name))
+}
+
+/// Whether the database was encrypted on a previous run, meaning nothing
+/// can read it until [`set_database_passphrase`] is called.
+pub fn is_encrypted(app: &AppHandle) -> Result<bool, AppError> {
+    let path = config_path(app)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    let text = fs::read_to_string(&path)?;
+    let config: EncryptionConfig = serde_json::from_str(&text)?;
+    Ok(config.encrypted)
+}
+
+fn mark_encrypted(app: &AppHandle) -> Result<(), AppError> {
+    let text = serde_json::to_string(&EncryptionConfig { encrypted: true })?;
+    fs::write(config_path(app)?, text).map_err(AppError::from)
+}
+
+/// Unlocks the database for this session, enabling encryption if it is off.
+///
+/// A missing database is created encrypted and an existing plaintext one is
+/// re-written encrypted. For an already encrypted database the passphrase is
+/// checked and `WrongPassphrase` returned if it doesn't open the file.
+#[tauri::command(async)]
+pub fn set_database_passphrase(
+    app: AppHandle,
+    key: State<'_, DbKey>,
+    passphrase: String,
+) -> Result<(), AppError> {
+    if passphrase.is_empty() {
+        return Err(AppError::Validation("passphrase must not be empty".into()));
+    }
+    let path = db::db_path(&app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // The file may be about to be replaced by its encrypted copy.
+    db::reset(&app);
+
+    if is_encrypted(&app)? {
+        db::open_at(&path, Some(&passphrase))?;
+    } else if path.exists() {
+        encrypt_existing(&path, &passphrase)?;
+        mark_encrypted(&app)?;
+    } else {
+        db::open_at(&path, Some(&passphrase))?;
+        mark_encrypted(&app)?;
+    }
+
+    *key.0.lock().unwrap() = Some(passphrase);
+    db::reset(&app);
+    // Startup work was skipped while the database was locked.
+    db::with_conn(&app, |conn| migrations::run_migrations(conn))?;
+    profiles::save_active(&app)?;
+    recurring::materialize_on_startup(&app);
+    Ok(())
+}
+
+/// Changes the passphrase of an encrypted database.
+#[tauri::command(async)]
+pub fn rekey_database(
+    app: AppHandle,
+    key: State<'_, DbKey>,
+    old: String,
+    new: String,
+) -> Result<(), AppError> {
+    if !is_encrypted(&app)? {
+        return Err(AppError::Validation(
+            "database is not encrypted; call set_database_passphrase first".into(),
+        ));
+    }
+    if new.is_empty() {
+        return Err(AppError::Validation("passphrase must not be empty".into()));
+    }
+    let conn = db::open_at(&db::db_path(&app)?, Some(&old))?;
+    conn.pragma_update(None, "rekey", &new)?;
+    *key.0.lock().unwrap() = Some(new);
+    db::reset(&app);
+    Ok(())
+}
+
+/// Copies a plaintext database into an encrypted sibling file with
+/// `sqlcipher_export` and swaps it into place. The write-ahead log is
+/// checkpointed into the copy first and its files removed, since SQLite
+/// would otherwise replay the plaintext log over the encrypted file.
+fn encrypt_existing(path: &Path, passphrase: &str) -> Result<(), AppError> {
+    let tmp = path.with_extension("db.encrypting");
+    if tmp.exists() {
+        fs::remove_file(&tmp)?;
+    }
+    {
+        let conn = db::open_at(path, None).map_err(|e| match e {
+            AppError::WrongPassphrase => AppError::Conflict(
+                "database is already encrypted with an unknown passphrase".to_string(),
+            ),
+            e => e,
+        })?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            params![tmp.to_string_lossy(), passphrase],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        conn.execute_batch("DETACH DATABASE encrypted")?;
+    }
+    for suffix in ["-wal", "-shm"] {
+        let mut side = path.as_os_str().to_owned();
+        side.push(suffix);
+        match fs::remove_file(&side) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    fs::rename(&tmp, path).map_err(AppError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+
+    #[test]
+    fn encrypt_existing_folds_in_the_write_ahead_log() {
+        let dir = std::env::temp_dir().join(format!("encrypt-wal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("budget.db");
+        // Another connection holding the log open keeps SQLite from
+        // checkpointing it on close.
+        let writer = Connection::open(&path).unwrap();
+        writer
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 PRAGMA wal_autocheckpoint = 0;
+                 CREATE TABLE t (x INTEGER);
+                 INSERT INTO t VALUES (1), (2);",
+            )
+            .unwrap();
+
+        encrypt_existing(&path, "secret").unwrap();
+
+        assert!(!dir.join("budget.db-wal").exists());
+        let conn = db::open_at(&path, Some("secret")).unwrap();
+        let count: i64 = conn.query_row("SELECT count(*) FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 2);
+        drop((conn, writer));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+//! Writing transactions out for people who don't use the app.
+
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use tauri::AppHandle;
+
+use crate::commands::transactions::{search, SearchQuery};
+use crate::db;
+use crate::error::AppError;
+use crate::money::format_minor;
+
+/// Writes the transactions matching `query` to a CSV file at `path` and
+/// returns how many rows were written, not counting the header.
+#[tauri::command(async)]
+pub fn export_transactions_csv(
+    app: AppHandle,
+    query: SearchQuery,
+    path: String,
+) -> Result<usize, AppError> {
+    db::with_conn(&app, |conn| export_csv(conn, &query, &path))
+}
+
+pub fn export_csv(conn: &Connection, query: &SearchQuery, path: &str) -> Result<usize, AppError> {
+    let rows = search(conn, query)?;
+    let names = |sql: &str| -> Result<HashMap<i64, String>, AppError> {
+        let mut stmt = conn.prepare(sql)?;
+        let map = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(map)
+    };
+    let accounts = names("SELECT id, name FROM accounts")?;
+    let categories = names("SELECT id, name FROM categories")?;
+
+    let mut writer =
+        csv::Writer::from_path(path).map_err(|e| AppError::Io(format!("{path}: {e}")))?;
+    writer.write_record(["Date", "Account", "Description", "Amount", "Category", "Notes"])?;
+    for t in &rows {
+        let lookup = |map: &HashMap<i64, String>, id: Option<i64>| {
+            id.and_then(|id| map.get(&id)).cloned().unwrap_or_default()
+        };
+        writer.write_record([
+            t.date.clone(),
+            lookup(&accounts, Some(t.account_id)),
+            t.description.clone(),
+            format_minor(t.amount_minor),
+            lookup(&categories, t.category_id),
+            t.notes.clone().unwrap_or_default(),
+        ])?;
+    }
+    writer.flush().map_err(|e| AppError::Io(format!("{path}: {e}")))?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn exported_rows_are_escaped_and_named() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (name) VALUES ('Food');
+             INSERT INTO transactions (account_id, date, description, amount_minor, category_id)
+             VALUES (1, '2024-03-01', 'Fish, \"fresh\"', -1205, 1),
+                    (1, '2024-03-02', 'Salary', 250000, NULL),
+                    (1, '2023-12-31', 'Last year', -5, NULL);",
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("export-{}.csv", std::process::id()));
+        let query = SearchQuery { date_from: Some("2024-01-01".into()), ..Default::default() };
+
+        let written = export_csv(&conn, &query, &path.to_string_lossy()).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(
+            text,
+            "Date,Account,Description,Amount,Category,Notes\n\
+             2024-03-02,Checking,Salary,2500.00,,\n\
+             2024-03-01,Checking,\"Fish, \"\"fresh\"\"\",-12.05,Food,\n"
+        );
+    }
+}
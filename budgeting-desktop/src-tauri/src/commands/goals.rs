@@ -0,0 +1,156 @@
+//! Savings goals, such as a vacation fund.
+//!
+//! A goal counts its savings from one of two places: the balance of a
+//! linked account that holds the money, or the net of every transaction
+//! carrying a linked tag, for families who save inside a shared account.
+
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands::accounts::balance_as_of;
+use crate::db;
+use crate::error::AppError;
+use crate::recurring::parse_date;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum GoalState {
+    Reached,
+    InProgress,
+    /// The target date has passed without reaching the target.
+    Overdue,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoalProgress {
+    pub goal_id: i64,
+    pub name: String,
+    pub target_minor: i64,
+    pub target_date: String,
+    pub saved_minor: i64,
+    /// What is still missing; zero once the target is reached.
+    pub remaining_minor: i64,
+    /// Whole percent saved, rounded down; can exceed 100.
+    pub percent_complete: i64,
+    /// Monthly contributions left before the target date, counting the
+    /// current month.
+    pub months_left: u32,
+    /// Amount to save each month to reach the target in time; `None` when
+    /// the goal is overdue.
+    pub required_monthly: Option<i64>,
+    pub status: GoalState,
+}
+
+#[tauri::command(async)]
+pub fn goal_progress(app: AppHandle, goal_id: i64) -> Result<GoalProgress, AppError> {
+    db::with_conn(&app, |conn| progress(conn, goal_id, chrono::Local::now().date_naive()))
+}
+
+pub fn progress(conn: &Connection, goal_id: i64, today: NaiveDate) -> Result<GoalProgress, AppError> {
+    let (name, target_minor, target_date, account_id, tag_id): (
+        String,
+        i64,
+        String,
+        Option<i64>,
+        Option<i64>,
+    ) = conn
+        .query_row(
+            "SELECT name, target_minor, target_date, linked_account_id, linked_tag_id
+             FROM goals WHERE id = ?1",
+            [goal_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("goal {goal_id} not found")))?;
+    if target_minor <= 0 {
+        return Err(AppError::Validation(format!("goal {goal_id} has no positive target")));
+    }
+    let target = parse_date(&target_date)?;
+
+    let saved_minor = match (account_id, tag_id) {
+        (Some(account_id), _) => balance_as_of(conn, account_id, None)?,
+        (None, Some(tag_id)) => conn
+            .query_row(
+                "SELECT COALESCE(SUM(t.amount_minor), 0)
+                 FROM transactions t JOIN transaction_tags tt ON tt.transaction_id = t.id
+                 WHERE tt.tag_id = ?1 AND t.deleted_at IS NULL",
+                [tag_id],
+                |row| row.get(0),
+            )?,
+        (None, None) => {
+            return Err(AppError::Validation(format!(
+                "goal {goal_id} has no linked account or tag to count savings from"
+            )))
+        }
+    };
+    let remaining_minor = (target_minor - saved_minor).max(0);
+
+    let mut months_left = (target.year() * 12 + target.month0() as i32)
+        - (today.year() * 12 + today.month0() as i32);
+    if target.day() > today.day() {
+        months_left += 1;
+    }
+    let months_left = if target < today { 0 } else { months_left.max(1) as u32 };
+
+    let (status, required_monthly) = if remaining_minor == 0 {
+        (GoalState::Reached, Some(0))
+    } else if months_left == 0 {
+        (GoalState::Overdue, None)
+    } else {
+        let months = i64::from(months_left);
+        (GoalState::InProgress, Some((remaining_minor + months - 1) / months))
+    };
+
+    Ok(GoalProgress {
+        goal_id,
+        name,
+        target_minor,
+        target_date,
+        saved_minor,
+        remaining_minor,
+        percent_complete: (saved_minor.max(0) as i128 * 100 / target_minor as i128) as i64,
+        months_left,
+        required_monthly,
+        status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn progress_counts_the_account_or_the_tag_and_flags_overdue_goals() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name, opening_balance_minor) VALUES ('Vacation fund', 50000);
+             INSERT INTO tags (id, name) VALUES (1, 'bike');
+             INSERT INTO transactions (id, account_id, date, amount_minor)
+                 VALUES (1, 1, '2024-01-05', 3000), (2, 1, '2024-02-05', -500);
+             INSERT INTO transaction_tags (transaction_id, tag_id) VALUES (1, 1), (2, 1);
+             INSERT INTO goals (name, target_minor, target_date, linked_account_id, linked_tag_id)
+             VALUES ('Vacation', 200000, '2024-12-15', 1, NULL),
+                    ('Bike', 2500, '2024-06-01', NULL, 1),
+                    ('Late', 999999, '2024-03-01', 1, NULL);",
+        )
+        .unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+
+        let vacation = progress(&conn, 1, today).unwrap();
+        assert_eq!((vacation.saved_minor, vacation.percent_complete), (52500, 26));
+        assert_eq!((vacation.months_left, vacation.required_monthly), (10, Some(14750)));
+        assert_eq!(vacation.status, GoalState::InProgress);
+
+        let bike = progress(&conn, 2, today).unwrap();
+        assert_eq!((bike.saved_minor, bike.status), (2500, GoalState::Reached));
+        assert_eq!(bike.required_monthly, Some(0));
+
+        let late = progress(&conn, 3, today).unwrap();
+        assert_eq!((late.status, late.required_monthly), (GoalState::Overdue, None));
+        assert!(matches!(progress(&conn, 4, today), Err(AppError::NotFound(_))));
+    }
+}
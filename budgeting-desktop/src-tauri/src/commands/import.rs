@@ -0,0 +1,426 @@
+//! Bank statement ingestion.
+
+use std::fs;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::{db, journal};
+
+/// Which CSV columns (zero-based) hold each transaction field.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ColumnMapping {
+    pub date: usize,
+    pub description: usize,
+    pub amount: usize,
+    /// chrono format string for the date column, e.g. `%d/%m/%Y`.
+    pub date_format: String,
+    pub has_header: bool,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            date: 0,
+            description: 1,
+            amount: 2,
+            date_format: "%Y-%m-%d".into(),
+            has_header: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub skipped_duplicates: usize,
+    pub malformed: usize,
+}
+
+/// A statement row after parsing, ready to insert.
+struct ParsedRow {
+    date: NaiveDate,
+    description: String,
+    amount_minor: i64,
+    notes: Option<String>,
+    /// Key for duplicate detection; see [`row_hash`].
+    import_hash: String,
+}
+
+/// Imports a CSV bank statement into `transactions` for one account.
+///
+/// Rows are keyed by a hash of (date, amount, description), so importing the
+/// same statement twice only inserts the rows that are new.
+#[tauri::command(async)]
+pub fn import_csv(
+    app: AppHandle,
+    path: String,
+    account_id: i64,
+    mapping: Option<ColumnMapping>,
+) -> Result<ImportSummary, AppError> {
+    let mapping = mapping.unwrap_or_default();
+    db::with_conn(&app, |conn| check_account(conn, account_id))?;
+    let text = fs::read_to_string(&path).map_err(|e| AppError::Io(format!("{path}: {e}")))?;
+    let rows = parse_csv(&text, &mapping);
+    db::with_conn(&app, |conn| insert_rows(conn, account_id, &rows))
+}
+
+/// Imports the `STMTTRN` entries of an OFX or QFX download, either the
+/// SGML flavour (OFX 1.x, unclosed tags) or XML (OFX 2.x). The bank's
+/// `FITID` identifies each row, so re-importing an overlapping download
+/// skips what is already there.
+#[tauri::command(async)]
+pub fn import_ofx(app: AppHandle, path: String, account_id: i64) -> Result<ImportSummary, AppError> {
+    db::with_conn(&app, |conn| check_account(conn, account_id))?;
+    let bytes = fs::read(&path).map_err(|e| AppError::Io(format!("{path}: {e}")))?;
+    // Older statements are often Windows-1252; the fields we read are ASCII.
+    let text = String::from_utf8_lossy(&bytes);
+    let rows = parse_ofx(&text);
+    db::with_conn(&app, |conn| insert_rows(conn, account_id, &rows))
+}
+
+fn check_account(conn: &Connection, account_id: i64) -> Result<(), AppError> {
+    let exists: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM accounts WHERE id = ?1)", [account_id], |row| {
+            row.get(0)
+        })?;
+    if !exists {
+        return Err(AppError::NotFound(format!("account {account_id} not found")));
+    }
+    Ok(())
+}
+
+/// Inserts parsed rows in one transaction, skipping any whose
+/// `import_hash` the account already has. `None` counts as malformed.
+fn insert_rows(
+    conn: &mut Connection,
+    account_id: i64,
+    rows: &[Option<ParsedRow>],
+) -> Result<ImportSummary, AppError> {
+    let tx = conn.transaction()?;
+    check_account(&tx, account_id)?;
+    journal::clear(&tx)?;
+    let mut summary = ImportSummary::default();
+    {
+        let mut insert = tx
+            .prepare(
+                "INSERT OR IGNORE INTO transactions
+                    (account_id, date, description, amount_minor, notes, import_hash)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+
+        for row in rows {
+            let Some(row) = row else {
+                summary.malformed += 1;
+                continue;
+            };
+            let date = row.date.format("%Y-%m-%d").to_string();
+            let changed = insert
+                .execute(params![
+                    account_id,
+                    date,
+                    row.description,
+                    row.amount_minor,
+                    row.notes,
+                    row.import_hash
+                ])?;
+            if changed == 0 {
+                summary.skipped_duplicates += 1;
+            } else {
+                summary.inserted += 1;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(summary)
+}
+
+/// One entry per CSV record, `None` for records that don't parse.
+fn parse_csv(text: &str, mapping: &ColumnMapping) -> Vec<Option<ParsedRow>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(sniff_delimiter(text))
+        .has_headers(mapping.has_header)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    reader
+        .records()
+        .map(|record| record.ok().and_then(|r| parse_row(&r, mapping)))
+        .collect()
+}
+
+fn parse_row(record: &csv::StringRecord, mapping: &ColumnMapping) -> Option<ParsedRow> {
+    let date = NaiveDate::parse_from_str(record.get(mapping.date)?, &mapping.date_format).ok()?;
+    let description = record.get(mapping.description)?.to_string();
+    let amount_minor = parse_amount_minor(record.get(mapping.amount)?)?;
+    let import_hash = row_hash(&date.format("%Y-%m-%d").to_string(), amount_minor, &description);
+    Some(ParsedRow {
+        date,
+        description,
+        amount_minor,
+        notes: None,
+        import_hash,
+    })
+}
+
+/// One entry per `STMTTRN` block, `None` for blocks missing a date or
+/// amount. Tag names are matched case-insensitively.
+fn parse_ofx(text: &str) -> Vec<Option<ParsedRow>> {
+    let upper = text.to_ascii_uppercase();
+    let mut rows = Vec::new();
+    let mut rest = 0;
+    while let Some(open) = upper[rest..].find("<STMTTRN>") {
+        let start = rest + open + "<STMTTRN>".len();
+        let end = upper[start..].find("</STMTTRN>").map_or(upper.len(), |i| start + i);
+        rows.push(parse_ofx_transaction(&text[start..end], &upper[start..end]));
+        rest = end;
+    }
+    rows
+}
+
+fn parse_ofx_transaction(block: &str, upper: &str) -> Option<ParsedRow> {
+    // The value runs from the tag to the next `<`: the closing tag in XML,
+    // the next field in SGML.
+    let field = |tag: &str| -> Option<String> {
+        let open = format!("<{tag}>");
+        let start = upper.find(&open)? + open.len();
+        let end = block[start..].find('<').map_or(block.len(), |i| start + i);
+        let value = block[start..end]
+            .trim()
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&");
+        (!value.is_empty()).then_some(value)
+    };
+
+    let posted = field("DTPOSTED")?;
+    let date = NaiveDate::parse_from_str(posted.get(..8)?, "%Y%m%d").ok()?;
+    let amount_minor = parse_amount_minor(&field("TRNAMT")?)?;
+    let name = field("NAME");
+    let memo = field("MEMO");
+    let (description, notes) = match (name, memo) {
+        (Some(name), Some(memo)) if memo != name => (name, Some(memo)),
+        (Some(name), _) => (name, None),
+        (None, memo) => (memo.unwrap_or_default(), None),
+    };
+    // Without a FITID, fall back to the same key the CSV importer uses.
+    let import_hash = match field("FITID") {
+        Some(fitid) => format!("ofx:{fitid}"),
+        None => row_hash(&date.format("%Y-%m-%d").to_string(), amount_minor, &description),
+    };
+    Some(ParsedRow {
+        date,
+        description,
+        amount_minor,
+        notes,
+        import_hash,
+    })
+}
+
+/// Picks `;` or `,` by counting unquoted occurrences in the first line.
+fn sniff_delimiter(text: &str) -> u8 {
+    let first_line = text.lines().next().unwrap_or("");
+    let (mut commas, mut semicolons, mut quoted) = (0, 0, false);
+    for c in first_line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => commas += 1,
+            ';' if !quoted => semicolons += 1,
+            _ => {}
+        }
+    }
+    if semicolons > commas {
+        b';'
+    } else {
+        b','
+    }
+}
+
+/// Parses a statement amount such as `-1,234.56`, `1.234,56` or `(12.00)`
+/// into minor units. The last `.` or `,` followed by one or two digits is
+/// taken as the decimal separator; any other separators are grouping.
+fn parse_amount_minor(raw: &str) -> Option<i64> {
+    let mut s: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | ',' | '(' | ')'))
+        .collect();
+    let mut negative = false;
+    if s.starts_with('(') && s.ends_with(')') {
+        negative = true;
+        s = s[1..s.len() - 1].to_string();
+    }
+    if let Some(rest) = s.strip_prefix('-') {
+        negative = !negative;
+        s = rest.to_string();
+    } else if let Some(rest) = s.strip_prefix('+') {
+        s = rest.to_string();
+    }
+
+    let (whole, frac) = match s.rfind(['.', ',']) {
+        Some(i) if (1..=2).contains(&(s.len() - i - 1)) => (&s[..i], &s[i + 1..]),
+        _ => (s.as_str(), ""),
+    };
+    let whole: String = whole.chars().filter(|c| *c != '.' && *c != ',').collect();
+    if whole.is_empty() && frac.is_empty() {
+        return None;
+    }
+    if !whole.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let units: i64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let cents: i64 = match frac.len() {
+        0 => 0,
+        1 => frac.parse::<i64>().ok()? * 10,
+        _ => frac.parse().ok()?,
+    };
+    let minor = units.checked_mul(100)?.checked_add(cents)?;
+    Some(if negative { -minor } else { minor })
+}
+
+fn row_hash(date: &str, amount_minor: i64, description: &str) -> String {
+    let digest = Sha256::digest(format!("{date}|{amount_minor}|{}", description.trim()));
+    format!("{digest:x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    const SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+
+<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20240105120000[-5:EST]
+<TRNAMT>-12.34
+<FITID>A1
+<NAME>STARBUCKS &amp; CO
+<MEMO>Card 1234
+</STMTTRN>
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240106<TRNAMT>1500.00<FITID>A2<MEMO>PAYROLL</STMTTRN>
+<STMTTRN><TRNTYPE>DEBIT<TRNAMT>-1.00<FITID>A3</STMTTRN>
+</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>";
+
+    const XML: &str = "<?xml version=\"1.0\"?>
+<OFX>
+<stmttrn><DTPOSTED>20240105</DTPOSTED><TRNAMT>-12.34</TRNAMT><FITID>A1</FITID>
+<NAME>Renamed</NAME></stmttrn>
+<STMTTRN><DTPOSTED>20240107</DTPOSTED><TRNAMT>-3.5</TRNAMT><FITID>A4</FITID>
+<NAME>Bakery</NAME></STMTTRN>
+</OFX>";
+
+    #[test]
+    fn amounts_accept_either_decimal_separator() {
+        assert_eq!(parse_amount_minor("-1,234.56"), Some(-123_456));
+        assert_eq!(parse_amount_minor("1.234,56"), Some(123_456));
+        assert_eq!(parse_amount_minor("(12.00)"), Some(-1200));
+        assert_eq!(parse_amount_minor("+3,5"), Some(350));
+        assert_eq!(parse_amount_minor("$ 1,000"), Some(100_000));
+        assert_eq!(parse_amount_minor("n/a"), None);
+        assert_eq!(parse_amount_minor(""), None);
+    }
+
+    #[test]
+    fn csv_rows_follow_the_sniffed_delimiter_and_quotes() {
+        let mapping = ColumnMapping { date_format: "%d/%m/%Y".into(), ..Default::default() };
+        let text = "Date;Description;Amount
+05/01/2024;\"Coffee; large\";-3,50
+06/01/2024;Salary;1.500,00
+not a date;Broken;1,00
+";
+        let rows = parse_csv(text, &mapping);
+        assert_eq!(rows.len(), 3);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.date, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap());
+        assert_eq!((first.description.as_str(), first.amount_minor), ("Coffee; large", -350));
+        assert_eq!(rows[1].as_ref().unwrap().amount_minor, 150_000);
+        assert!(rows[2].is_none());
+
+        assert_eq!(sniff_delimiter("date,\"a;b;c\",amount"), b',');
+    }
+
+    #[test]
+    fn reimporting_a_csv_inserts_only_new_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch("INSERT INTO accounts (name) VALUES ('Checking')").unwrap();
+        let mapping = ColumnMapping::default();
+
+        let march = "date,description,amount\n2024-03-01,Rent,-900.00\n2024-03-02,Fuel,-40\n";
+        let summary = insert_rows(&mut conn, 1, &parse_csv(march, &mapping)).unwrap();
+        assert_eq!((summary.inserted, summary.skipped_duplicates, summary.malformed), (2, 0, 0));
+        let more = format!("{march}2024-03-03,Bakery,-4.20\n2024-03-04,Oops,\n");
+        let summary = insert_rows(&mut conn, 1, &parse_csv(&more, &mapping)).unwrap();
+        assert_eq!((summary.inserted, summary.skipped_duplicates, summary.malformed), (1, 2, 1));
+    }
+
+    #[test]
+    fn importing_into_a_missing_account_is_not_found() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        let text = "date,description,amount\n2024-03-01,Rent,-900\n";
+        let rows = parse_csv(text, &ColumnMapping::default());
+        assert!(matches!(insert_rows(&mut conn, 1, &rows), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn sgml_fields_run_to_the_next_tag() {
+        let rows = parse_ofx(SGML);
+        assert_eq!(rows.len(), 3);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.date, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap());
+        assert_eq!(first.amount_minor, -1234);
+        assert_eq!(first.description, "STARBUCKS & CO");
+        assert_eq!(first.notes.as_deref(), Some("Card 1234"));
+        assert_eq!(first.import_hash, "ofx:A1");
+
+        // Without a name the memo is the description.
+        let second = rows[1].as_ref().unwrap();
+        assert_eq!((second.description.as_str(), second.notes.as_deref()), ("PAYROLL", None));
+        assert_eq!(second.amount_minor, 150_000);
+        // No DTPOSTED.
+        assert!(rows[2].is_none());
+    }
+
+    #[test]
+    fn xml_tags_match_in_any_case() {
+        let rows = parse_ofx(XML);
+        let descriptions: Vec<&str> =
+            rows.iter().map(|row| row.as_ref().unwrap().description.as_str()).collect();
+        assert_eq!(descriptions, ["Renamed", "Bakery"]);
+        assert_eq!(rows[1].as_ref().unwrap().amount_minor, -350);
+    }
+
+    #[test]
+    fn a_missing_fitid_falls_back_to_the_row_hash() {
+        let rows = parse_ofx("<STMTTRN><DTPOSTED>20240105<TRNAMT>-2.00<NAME>Kiosk</STMTTRN>");
+        let row = rows[0].as_ref().unwrap();
+        assert_eq!(row.import_hash, row_hash("2024-01-05", -200, "Kiosk"));
+    }
+
+    #[test]
+    fn reimporting_an_overlapping_download_skips_known_fitids() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch("INSERT INTO accounts (name) VALUES ('Checking')").unwrap();
+
+        let summary = insert_rows(&mut conn, 1, &parse_ofx(SGML)).unwrap();
+        assert_eq!((summary.inserted, summary.skipped_duplicates, summary.malformed), (2, 0, 1));
+        let summary = insert_rows(&mut conn, 1, &parse_ofx(XML)).unwrap();
+        assert_eq!((summary.inserted, summary.skipped_duplicates, summary.malformed), (1, 1, 0));
+
+        let mut stmt = conn.prepare("SELECT description FROM transactions ORDER BY id").unwrap();
+        let descriptions: Vec<String> =
+            stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(descriptions, ["STARBUCKS & CO", "PAYROLL", "Bakery"]);
+    }
+}
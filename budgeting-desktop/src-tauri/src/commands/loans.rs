@@ -0,0 +1,222 @@
+//! Fixed-rate installment loans.
+//!
+//! `apr_micros` is the annual rate in millionths (6.5% is `65_000`),
+//! charged monthly at a twelfth of that. Everything is computed in integer
+//! minor units: each month's interest is rounded to the nearest unit, the
+//! regular payment is the smallest whole amount that clears the loan in
+//! `term_months`, and the last payment is trimmed so the balance ends at
+//! exactly zero.
+
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+use crate::money::div_round;
+use crate::recurring::{add_months, parse_date};
+
+const MAX_TERM_MONTHS: i64 = 1200;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmortizationRow {
+    /// 1-based payment number.
+    pub period: u32,
+    /// Due one month after the previous payment, the first a month after
+    /// the loan starts.
+    pub date: String,
+    pub payment: i64,
+    pub interest: i64,
+    pub principal: i64,
+    pub remaining_balance: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoanSummary {
+    pub loan_id: i64,
+    /// The regular monthly payment; the final one may be smaller.
+    pub monthly_payment: i64,
+    pub payments: u32,
+    pub total_paid: i64,
+    pub total_interest: i64,
+    pub payoff_date: Option<String>,
+}
+
+#[tauri::command(async)]
+pub fn loan_schedule(app: AppHandle, loan_id: i64) -> Result<Vec<AmortizationRow>, AppError> {
+    db::with_conn(&app, |conn| schedule_for(conn, loan_id))
+}
+
+#[tauri::command(async)]
+pub fn loan_payoff_summary(app: AppHandle, loan_id: i64) -> Result<LoanSummary, AppError> {
+    db::with_conn(&app, |conn| {
+        let rows = schedule_for(conn, loan_id)?;
+        Ok(LoanSummary {
+            loan_id,
+            monthly_payment: rows.first().map_or(0, |r| r.payment),
+            payments: rows.len() as u32,
+            total_paid: rows.iter().map(|r| r.payment).sum(),
+            total_interest: rows.iter().map(|r| r.interest).sum(),
+            payoff_date: rows.last().map(|r| r.date.clone()),
+        })
+    })
+}
+
+fn schedule_for(conn: &Connection, loan_id: i64) -> Result<Vec<AmortizationRow>, AppError> {
+    let (principal, apr_micros, term_months, start): (i64, i64, i64, String) = conn
+        .query_row(
+            "SELECT principal_minor, apr_micros, term_months, start_date FROM loans WHERE id = ?1",
+            [loan_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("loan {loan_id} not found")))?;
+    amortize(principal, apr_micros, term_months, parse_date(&start)?)
+}
+
+pub fn amortize(
+    principal: i64,
+    apr_micros: i64,
+    term_months: i64,
+    start: NaiveDate,
+) -> Result<Vec<AmortizationRow>, AppError> {
+    if principal <= 0 {
+        return Err(AppError::Validation("loan principal must be positive".into()));
+    }
+    if apr_micros < 0 {
+        return Err(AppError::Validation("loan APR must not be negative".into()));
+    }
+    if !(1..=MAX_TERM_MONTHS).contains(&term_months) {
+        return Err(AppError::Validation(format!(
+            "loan term must be between 1 and {MAX_TERM_MONTHS} months"
+        )));
+    }
+    let interest = |balance: i128| div_round(balance * i128::from(apr_micros), 12 * 1_000_000);
+
+    // Whether `term_months` payments of `payment` clear the loan. Once a
+    // payment no longer covers the month's interest the balance can only
+    // grow, so the loop stops there; compounding on at a high rate over a
+    // long term would overflow even an i128.
+    let clears = |payment: i128| {
+        let mut balance = i128::from(principal);
+        for _ in 0..term_months {
+            let charged = interest(balance);
+            if charged >= payment {
+                return false;
+            }
+            balance += charged - payment;
+            if balance <= 0 {
+                return true;
+            }
+        }
+        false
+    };
+    // One payment of principal plus a month's interest always clears it,
+    // and the remaining balance only shrinks as the payment grows.
+    let (mut lo, mut hi) = (0, i128::from(principal) + interest(i128::from(principal)));
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if clears(mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    let payment = lo;
+    if i64::try_from(payment).is_err() {
+        return Err(AppError::Validation("loan payments are too large to represent".into()));
+    }
+
+    let mut rows = Vec::new();
+    let mut balance = i128::from(principal);
+    for period in 1..=term_months as u32 {
+        let charged = interest(balance);
+        let mut paid = payment;
+        if period == term_months as u32 || balance + charged <= paid {
+            paid = balance + charged;
+        }
+        balance += charged - paid;
+        rows.push(AmortizationRow {
+            period,
+            date: add_months(start, period, start.day()).format("%Y-%m-%d").to_string(),
+            payment: paid as i64,
+            interest: charged as i64,
+            principal: (paid - charged) as i64,
+            remaining_balance: balance as i64,
+        });
+        if balance == 0 {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap()
+    }
+
+    #[test]
+    fn the_payment_clears_the_loan_in_the_term() {
+        // 10,000.00 at 6% over a year; the textbook payment is 860.66.
+        let rows = amortize(1_000_000, 60_000, 12, start()).unwrap();
+        assert_eq!(rows.len(), 12);
+        assert_eq!(rows[0].payment, 86_067);
+        assert_eq!(rows[0].interest, 5_000);
+        assert!(rows.last().unwrap().payment <= 86_067);
+        assert_eq!(rows.last().unwrap().remaining_balance, 0);
+        assert_eq!(rows.iter().map(|r| r.principal).sum::<i64>(), 1_000_000);
+    }
+
+    #[test]
+    fn the_last_payment_is_trimmed_to_the_balance() {
+        let rows = amortize(1_000, 0, 3, start()).unwrap();
+        let payments: Vec<i64> = rows.iter().map(|r| r.payment).collect();
+        assert_eq!(payments, [334, 334, 332]);
+    }
+
+    #[test]
+    fn payments_fall_due_monthly_from_the_start_day() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let rows = amortize(1_000_000, 60_000, 3, start).unwrap();
+        let dates: Vec<&str> = rows.iter().map(|r| r.date.as_str()).collect();
+        assert_eq!(dates, ["2024-02-29", "2024-03-31", "2024-04-30"]);
+    }
+
+    #[test]
+    fn a_thirty_year_mortgage_never_goes_negative() {
+        let rows = amortize(30_000_000, 65_000, 360, start()).unwrap();
+        assert_eq!(rows.len(), 360);
+        assert_eq!(rows[0].payment, 189_621);
+        assert!(rows.iter().all(|r| r.principal >= 0 && r.remaining_balance >= 0));
+        assert_eq!(rows.last().unwrap().remaining_balance, 0);
+    }
+
+    #[test]
+    fn invalid_terms_are_rejected() {
+        let cases = [(0, 1, 1), (1, -1, 1), (1, 1, 0), (1, 1, MAX_TERM_MONTHS + 1)];
+        for (principal, apr_micros, term_months) in cases {
+            let result = amortize(principal, apr_micros, term_months, start());
+            assert!(matches!(result, Err(AppError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn a_high_rate_over_the_longest_term_does_not_overflow() {
+        let rows = amortize(100_000_000, 1_000_000, MAX_TERM_MONTHS, start()).unwrap();
+        assert_eq!(rows.last().unwrap().remaining_balance, 0);
+        assert!(rows.len() <= MAX_TERM_MONTHS as usize);
+    }
+
+    #[test]
+    fn a_payment_too_large_for_i64_is_rejected() {
+        let result = amortize(i64::MAX, 12_000_000, 1, start());
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+}
@@ -0,0 +1,20 @@
+pub mod accounts;
+pub mod analytics;
+pub mod budgets;
+pub mod categories;
+pub mod duplicates;
+pub mod encryption;
+pub mod export;
+pub mod goals;
+pub mod import;
+pub mod loans;
+pub mod payees;
+pub mod receipts;
+pub mod refunds;
+pub mod reminders;
+pub mod reports;
+pub mod rules;
+pub mod sql;
+pub mod tags;
+pub mod transactions;
+pub mod transfers;
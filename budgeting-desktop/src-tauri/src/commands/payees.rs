@@ -0,0 +1,308 @@
+//! Merchants recognized from transaction descriptions.
+//!
+//! Bank descriptions carry processor prefixes and reference numbers, so
+//! "AMZN Mktp US*2Y4AB" and "Amazon.com" both need cleaning before they
+//! can be compared. [`clean_description`] lowercases the text, drops the
+//! `payee_rules` prefixes without a `payee_name`, cuts everything from the
+//! first `*` or `#`, and drops trailing words containing digits. A cleaned
+//! name starting with a rule's pattern that has a `payee_name` collapses
+//! into that payee.
+//!
+//! Payees are matched by their cleaned `match_key`, never by display name,
+//! so a payee renamed with [`rename_payee`] keeps its name when
+//! [`normalize_payees`] runs again.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+
+/// A row of `payee_rules` with its pattern lowercased.
+#[derive(Debug, Clone)]
+pub struct PayeeRule {
+    pub pattern: String,
+    /// `None` strips the pattern as a prefix; otherwise names the payee
+    /// every description starting with the pattern belongs to.
+    pub payee_name: Option<String>,
+}
+
+/// The payee a description belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanedPayee {
+    pub match_key: String,
+    /// Name given to the payee when it is first created.
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayeeTotal {
+    pub payee_id: i64,
+    pub name: String,
+    /// Signed minor units; expenses are negative.
+    pub total: i64,
+    pub transaction_count: i64,
+}
+
+/// Links every live, non-transfer transaction to the payee its description
+/// cleans to, creating payees as needed. Returns how many transactions
+/// changed payee.
+#[tauri::command(async)]
+pub fn normalize_payees(app: AppHandle) -> Result<usize, AppError> {
+    db::with_conn(&app, normalize)
+}
+
+pub fn normalize(conn: &mut Connection) -> Result<usize, AppError> {
+    let tx = conn.transaction()?;
+    let rules = load_payee_rules(&tx)?;
+    let rows = tx
+        .prepare(
+            "SELECT id, description, payee_id FROM transactions
+             WHERE deleted_at IS NULL AND transfer_id IS NULL",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<i64>>(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })?;
+
+    let mut payee_ids: HashMap<String, i64> = HashMap::new();
+    let mut changed = 0;
+    for (id, description, current) in rows {
+        let payee_id = match clean_description(&description, &rules) {
+            Some(cleaned) => Some(match payee_ids.get(&cleaned.match_key) {
+                Some(&payee_id) => payee_id,
+                None => {
+                    let payee_id = ensure_payee(&tx, &cleaned)?;
+                    payee_ids.insert(cleaned.match_key, payee_id);
+                    payee_id
+                }
+            }),
+            None => None,
+        };
+        if payee_id != current {
+            tx.execute(
+                "UPDATE transactions SET payee_id = ?1 WHERE id = ?2",
+                params![payee_id, id],
+            )?;
+            changed += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Sets the name shown for a payee. Normalization never overwrites it.
+#[tauri::command(async)]
+pub fn rename_payee(app: AppHandle, payee_id: i64, new_name: String) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| rename(conn, payee_id, &new_name))
+}
+
+pub fn rename(conn: &Connection, payee_id: i64, new_name: &str) -> Result<(), AppError> {
+    let name = new_name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation("payee name must not be empty".into()));
+    }
+    let updated = conn
+        .execute("UPDATE payees SET name = ?1 WHERE id = ?2", params![name, payee_id])?;
+    if updated == 0 {
+        return Err(AppError::NotFound(format!("payee {payee_id} not found")));
+    }
+    Ok(())
+}
+
+/// Net amount per payee for live, non-transfer transactions dated within
+/// `from`..=`to`, largest first. Transactions without a payee are left out.
+#[tauri::command(async)]
+pub fn spending_by_payee(app: AppHandle, from: String, to: String) -> Result<Vec<PayeeTotal>, AppError> {
+    db::with_conn(&app, |conn| payee_totals(conn, &from, &to))
+}
+
+pub fn payee_totals(conn: &Connection, from: &str, to: &str) -> Result<Vec<PayeeTotal>, AppError> {
+    for date in [from, to] {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| AppError::Validation(format!("invalid date {date:?}: {e}")))?;
+    }
+    if from > to {
+        return Err(AppError::Validation(format!("{from} is after {to}")));
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT p.id, p.name, SUM(t.amount_minor) AS total, count(*)
+             FROM transactions t JOIN payees p ON p.id = t.payee_id
+             WHERE t.deleted_at IS NULL AND t.transfer_id IS NULL
+               AND t.date >= ?1 AND t.date <= ?2
+             GROUP BY p.id
+             ORDER BY ABS(total) DESC, p.name",
+        )?;
+    let rows = stmt
+        .query_map(params![from, to], |row| {
+            Ok(PayeeTotal {
+                payee_id: row.get(0)?,
+                name: row.get(1)?,
+                total: row.get(2)?,
+                transaction_count: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Payee rules with the longest patterns first, so the most specific wins.
+pub fn load_payee_rules(conn: &Connection) -> Result<Vec<PayeeRule>, AppError> {
+    let mut stmt = conn
+        .prepare("SELECT pattern, payee_name FROM payee_rules ORDER BY length(pattern) DESC, id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(PayeeRule {
+                pattern: row.get::<_, String>(0)?.to_lowercase(),
+                payee_name: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// The payee `description` belongs to, or `None` when nothing is left
+/// after cleaning.
+pub fn clean_description(description: &str, rules: &[PayeeRule]) -> Option<CleanedPayee> {
+    let mut text = description.trim().to_lowercase();
+    while let Some(rule) = rules
+        .iter()
+        .find(|r| r.payee_name.is_none() && !r.pattern.is_empty() && text.starts_with(&r.pattern))
+    {
+        text = text[rule.pattern.len()..].trim_start().to_string();
+    }
+    if let Some(i) = text.find(['*', '#']) {
+        text.truncate(i);
+    }
+
+    let mut words: Vec<&str> = text
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '&'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    // Keep the first word even if it has digits, e.g. "7-eleven".
+    while words.len() > 1 && words.last().is_some_and(|w| w.chars().any(|c| c.is_ascii_digit())) {
+        words.pop();
+    }
+    if let Some(last) = words.last_mut() {
+        *last = last.strip_suffix(".com").unwrap_or(last);
+    }
+    let cleaned = words.join(" ");
+    if cleaned.is_empty() {
+        return None;
+    }
+
+    for rule in rules {
+        let Some(name) = &rule.payee_name else {
+            continue;
+        };
+        let at_word_end = cleaned
+            .strip_prefix(rule.pattern.as_str())
+            .is_some_and(|rest| !rest.starts_with(char::is_alphanumeric));
+        if !rule.pattern.is_empty() && at_word_end {
+            return Some(CleanedPayee {
+                match_key: name.to_lowercase(),
+                name: name.clone(),
+            });
+        }
+    }
+    let name = cleaned
+        .split(' ')
+        .map(|w| {
+            let mut chars = w.chars();
+            chars.next().map_or_else(String::new, |c| c.to_uppercase().chain(chars).collect())
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(CleanedPayee { match_key: cleaned, name })
+}
+
+fn ensure_payee(conn: &Connection, cleaned: &CleanedPayee) -> Result<i64, AppError> {
+    let existing: Option<i64> = conn
+        .query_row("SELECT id FROM payees WHERE match_key = ?1", [&cleaned.match_key], |row| row.get(0))
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    conn.execute(
+        "INSERT INTO payees (name, match_key) VALUES (?1, ?2)",
+        params![cleaned.name, cleaned.match_key],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn
+    }
+
+    fn cleaned(description: &str) -> Option<(String, String)> {
+        let rules = load_payee_rules(&db()).unwrap();
+        clean_description(description, &rules).map(|p| (p.match_key, p.name))
+    }
+
+    fn payee(match_key: &str, name: &str) -> Option<(String, String)> {
+        Some((match_key.to_string(), name.to_string()))
+    }
+
+    #[test]
+    fn processor_prefixes_and_references_are_dropped() {
+        assert_eq!(
+            cleaned("SQ *BLUE BOTTLE COFFEE 4412"),
+            payee("blue bottle coffee", "Blue Bottle Coffee")
+        );
+        assert_eq!(cleaned("STARBUCKS STORE 01234 #55"), payee("starbucks store", "Starbucks Store"));
+        assert_eq!(cleaned("  #123 "), None);
+    }
+
+    #[test]
+    fn named_rules_collapse_variants_into_one_payee() {
+        assert_eq!(cleaned("AMZN Mktp US*2Y4AB12"), payee("amazon", "Amazon"));
+        assert_eq!(cleaned("Amazon.com"), payee("amazon", "Amazon"));
+        // The pattern has to end at a word boundary.
+        assert_eq!(cleaned("Amazonia Tours"), payee("amazonia tours", "Amazonia Tours"));
+    }
+
+    #[test]
+    fn the_first_word_is_kept_even_with_digits() {
+        assert_eq!(cleaned("7-ELEVEN 1234"), payee("7-eleven", "7-eleven"));
+    }
+
+    #[test]
+    fn renamed_payees_keep_their_name() {
+        let mut conn = db();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (account_id, date, description, amount_minor) VALUES
+                 (1, '2024-01-01', 'AMZN Mktp US*2Y4', -500),
+                 (1, '2024-01-02', 'Amazon.com', -300),
+                 (1, '2024-01-03', 'Starbucks 11', -50);",
+        )
+        .unwrap();
+        assert_eq!(normalize(&mut conn).unwrap(), 3);
+        let amazon: i64 = conn
+            .query_row("SELECT id FROM payees WHERE match_key = 'amazon'", [], |row| row.get(0))
+            .unwrap();
+        rename(&conn, amazon, " Amazon Shopping ").unwrap();
+        assert_eq!(normalize(&mut conn).unwrap(), 0);
+
+        let totals = payee_totals(&conn, "2024-01-01", "2024-01-31").unwrap();
+        let totals: Vec<_> =
+            totals.iter().map(|p| (p.name.as_str(), p.total, p.transaction_count)).collect();
+        assert_eq!(totals, [("Amazon Shopping", -800, 2), ("Starbucks", -50, 1)]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Receipt files attached to transactions.
+//!
+//! Attached files are copied into an app-managed `receipts/` directory and
+//! named after the SHA-256 of their contents, so the same photo attached
+//! twice is stored once. A trashed transaction keeps its receipts so it can
+//! be restored; they go when the trash is purged, and a file is deleted
+//! once no receipt row refers to its hash any more.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::db;
+use crate::error::AppError;
+use crate::profiles::{ActiveProfile, DEFAULT_PROFILE};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub id: i64,
+    pub transaction_id: i64,
+    /// Absolute path of the stored copy.
+    pub path: String,
+    /// File name the receipt was attached from.
+    pub original_name: String,
+    pub hash: String,
+    pub created_at: String,
+}
+
+/// Copies `source_path` into the receipts directory and links it to the
+/// transaction. Returns the new receipt's ID.
+#[tauri::command(async)]
+pub fn attach_receipt(app: AppHandle, transaction_id: i64, source_path: String) -> Result<i64, AppError> {
+    let source = Path::new(&source_path);
+    let bytes = fs::read(source).map_err(|e| AppError::Io(format!("{source_path}: {e}")))?;
+    let hash = format!("{:x}", Sha256::digest(&bytes));
+    let original_name = source
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    db::with_conn(&app, |conn| {
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM transactions WHERE id = ?1 AND deleted_at IS NULL)",
+                [transaction_id],
+                |row| row.get(0),
+            )?;
+        if !exists {
+            return Err(AppError::NotFound(format!("transaction {transaction_id} not found")));
+        }
+
+        // Reuse the stored copy of identical content, whatever it was called.
+        let stored: Option<String> = conn
+            .query_row("SELECT file_name FROM receipts WHERE hash = ?1 LIMIT 1", [&hash], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let file_name = stored.unwrap_or_else(|| match source.extension() {
+            Some(ext) => format!("{hash}.{}", ext.to_string_lossy().to_lowercase()),
+            None => hash.clone(),
+        });
+
+        let dir = receipts_dir(&app)?;
+        fs::create_dir_all(&dir).map_err(|e| AppError::Io(format!("{}: {e}", dir.display())))?;
+        let target = dir.join(&file_name);
+        if !target.exists() {
+            fs::write(&target, &bytes)
+                .map_err(|e| AppError::Io(format!("{}: {e}", target.display())))?;
+        }
+
+        conn.execute(
+            "INSERT INTO receipts (transaction_id, file_name, original_name, hash) VALUES (?1, ?2, ?3, ?4)",
+            params![transaction_id, file_name, original_name, hash],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+#[tauri::command(async)]
+pub fn list_receipts(app: AppHandle, transaction_id: i64) -> Result<Vec<Receipt>, AppError> {
+    let dir = receipts_dir(&app)?;
+    db::with_conn(&app, |conn| {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, transaction_id, file_name, original_name, hash, created_at
+                 FROM receipts WHERE transaction_id = ?1 ORDER BY id",
+            )?;
+        let rows = stmt
+            .query_map([transaction_id], |row| {
+                Ok(Receipt {
+                    id: row.get(0)?,
+                    transaction_id: row.get(1)?,
+                    path: dir.join(row.get::<_, String>(2)?).to_string_lossy().into_owned(),
+                    original_name: row.get(3)?,
+                    hash: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    })
+}
+
+/// Each profile gets its own directory, since a file may only be deleted
+/// when nothing in its database still refers to it.
+pub fn receipts_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let profile = *app.state::<ActiveProfile>().0.lock().unwrap();
+    let dir = db::data_dir(app)?.join("receipts");
+    Ok(if profile == DEFAULT_PROFILE {
+        dir
+    } else {
+        dir.join(format!("profile_{profile}"))
+    })
+}
+
+/// Deletes the receipt rows of `transaction_ids` and returns the stored
+/// file names nothing refers to any more. The files themselves are left
+/// for [`remove_files`], to be called once the caller has committed.
+pub fn delete_receipt_rows(conn: &Connection, transaction_ids: &[i64]) -> Result<Vec<String>, AppError> {
+    let mut delete = conn
+        .prepare("DELETE FROM receipts WHERE transaction_id = ?1 RETURNING hash, file_name")?;
+    let mut orphaned = Vec::new();
+    for &id in transaction_ids {
+        let names = delete
+            .query_map([id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        orphaned.extend(names);
+    }
+    orphaned.sort();
+    orphaned.dedup();
+    let mut still_used = conn
+        .prepare("SELECT EXISTS (SELECT 1 FROM receipts WHERE hash = ?1)")?;
+    let mut unused = Vec::new();
+    for (hash, name) in orphaned {
+        let used: bool = still_used
+            .query_row([&hash], |row| row.get(0))?;
+        if !used {
+            unused.push(name);
+        }
+    }
+    Ok(unused)
+}
+
+/// Deletes stored receipt files. Failures are logged, since the rows are
+/// already gone and a stray file does no harm.
+pub fn remove_files(app: &AppHandle, file_names: &[String]) {
+    let dir = match receipts_dir(app) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("failed to locate receipts: {e}");
+            return;
+        }
+    };
+    for name in file_names {
+        let path = dir.join(name);
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("failed to delete receipt {}: {e}", path.display());
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Refunds linked to the purchase they return.
+//!
+//! A refund is an ordinary transaction whose `refund_of_id` points at the
+//! purchase. A purchase can have several refunds, such as partial returns,
+//! but together they can't give back more than it cost. With `net_refunds`,
+//! [`crate::commands::reports::monthly_summary`] counts a linked refund
+//! against the purchase's category instead of as income.
+
+use rusqlite::{Connection, OptionalExtension};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+
+#[tauri::command(async)]
+pub fn link_refund(app: AppHandle, purchase_id: i64, refund_id: i64) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| link(conn, purchase_id, refund_id))
+}
+
+#[tauri::command(async)]
+pub fn unlink_refund(app: AppHandle, refund_id: i64) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| unlink(conn, refund_id))
+}
+
+pub fn link(conn: &mut Connection, purchase_id: i64, refund_id: i64) -> Result<(), AppError> {
+    if purchase_id == refund_id {
+        return Err(AppError::Validation("a transaction can't refund itself".into()));
+    }
+    let tx = conn.transaction()?;
+    let (purchase_amount, purchase_refund_of) = linkable(&tx, purchase_id)?;
+    let (refund_amount, refund_of) = linkable(&tx, refund_id)?;
+    if refund_amount.signum() != -purchase_amount.signum() || refund_amount == 0 {
+        return Err(AppError::Validation(format!(
+            "refund {refund_id} must have the opposite sign of purchase {purchase_id}"
+        )));
+    }
+    if purchase_refund_of.is_some() {
+        return Err(AppError::Validation(format!("transaction {purchase_id} is itself a refund")));
+    }
+    if let Some(existing) = refund_of {
+        return Err(AppError::Conflict(format!(
+            "transaction {refund_id} is already a refund of transaction {existing}; unlink it first"
+        )));
+    }
+    let has_refunds: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM transactions WHERE refund_of_id = ?1 AND deleted_at IS NULL)",
+        [refund_id],
+        |row| row.get(0),
+    )?;
+    if has_refunds {
+        return Err(AppError::Validation(format!("transaction {refund_id} has refunds of its own")));
+    }
+
+    let refunded: i64 = tx.query_row(
+        "SELECT COALESCE(SUM(amount_minor), 0) FROM transactions
+         WHERE refund_of_id = ?1 AND deleted_at IS NULL",
+        [purchase_id],
+        |row| row.get(0),
+    )?;
+    if (refunded + refund_amount).abs() > purchase_amount.abs() {
+        return Err(AppError::Validation(format!(
+            "refunds would total {} but purchase {purchase_id} is {}",
+            (refunded + refund_amount).abs(),
+            purchase_amount.abs()
+        )));
+    }
+
+    tx.execute(
+        "UPDATE transactions SET refund_of_id = ?1 WHERE id = ?2",
+        [purchase_id, refund_id],
+    )?;
+    tx.commit().map_err(AppError::from)
+}
+
+pub fn unlink(conn: &Connection, refund_id: i64) -> Result<(), AppError> {
+    let updated = conn.execute(
+        "UPDATE transactions SET refund_of_id = NULL
+         WHERE id = ?1 AND refund_of_id IS NOT NULL",
+        [refund_id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::NotFound(format!(
+            "transaction {refund_id} is not linked to a purchase"
+        )));
+    }
+    Ok(())
+}
+
+/// Amount and `refund_of_id` of a live transaction that isn't a transfer.
+fn linkable(conn: &Connection, id: i64) -> Result<(i64, Option<i64>), AppError> {
+    let (amount, refund_of, transfer_id): (i64, Option<i64>, Option<i64>) = conn
+        .query_row(
+            "SELECT amount_minor, refund_of_id, transfer_id FROM transactions
+             WHERE id = ?1 AND deleted_at IS NULL",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("transaction {id} not found")))?;
+    if transfer_id.is_some() {
+        return Err(AppError::Validation(format!(
+            "transaction {id} is part of a transfer and can't be linked as a refund"
+        )));
+    }
+    Ok((amount, refund_of))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (id, account_id, date, amount_minor)
+             VALUES (1, 1, '2024-03-02', -8000),
+                    (2, 1, '2024-03-10', 5000),
+                    (3, 1, '2024-03-12', 4000),
+                    (4, 1, '2024-03-14', -100);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn a_refund_needs_the_opposite_sign() {
+        let mut conn = db();
+        assert!(matches!(link(&mut conn, 1, 4), Err(AppError::Validation(_))));
+        assert!(matches!(link(&mut conn, 1, 1), Err(AppError::Validation(_))));
+        assert!(matches!(link(&mut conn, 1, 9), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn refunds_cannot_give_back_more_than_the_purchase() {
+        let mut conn = db();
+        link(&mut conn, 1, 2).unwrap();
+        assert!(matches!(link(&mut conn, 1, 3), Err(AppError::Validation(_))));
+        assert!(matches!(link(&mut conn, 4, 2), Err(AppError::Conflict(_))));
+
+        unlink(&conn, 2).unwrap();
+        assert!(matches!(unlink(&conn, 2), Err(AppError::NotFound(_))));
+        link(&mut conn, 1, 3).unwrap();
+    }
+}
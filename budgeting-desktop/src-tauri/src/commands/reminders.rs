@@ -0,0 +1,241 @@
+//! Reminders for upcoming bills.
+//!
+//! Every recurring rule that takes money out is a bill. Asking for the
+//! upcoming reminders records a row in `reminders` for each occurrence due
+//! in the window, so a bill still unpaid after its date keeps showing up.
+//! A reminder is paid once a live transaction with the rule's amount and
+//! category is dated within its period, which runs from the previous
+//! occurrence to the next one. That includes the transaction the rule
+//! itself creates on the day.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+use crate::recurring::{parse_date, Interval};
+
+/// Longest window [`upcoming_reminders`] looks ahead, about a year.
+const MAX_DAYS_AHEAD: u32 = 366;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reminder {
+    pub id: i64,
+    pub rule_id: i64,
+    pub account_id: i64,
+    pub description: String,
+    /// Negative, like the transaction the rule creates.
+    pub amount_minor: i64,
+    pub category_id: Option<i64>,
+    pub due_date: String,
+    /// Due before today and still unpaid.
+    pub overdue: bool,
+}
+
+struct Bill {
+    account_id: i64,
+    description: String,
+    amount_minor: i64,
+    category_id: Option<i64>,
+    interval: Interval,
+    start: NaiveDate,
+}
+
+/// Unpaid bills due within `days_ahead` days of today, overdue ones
+/// included, soonest first.
+#[tauri::command(async)]
+pub fn upcoming_reminders(app: AppHandle, days_ahead: u32) -> Result<Vec<Reminder>, AppError> {
+    db::with_conn(&app, |conn| upcoming(conn, days_ahead, chrono::Local::now().date_naive()))
+}
+
+pub fn upcoming(
+    conn: &mut Connection,
+    days_ahead: u32,
+    today: NaiveDate,
+) -> Result<Vec<Reminder>, AppError> {
+    if days_ahead > MAX_DAYS_AHEAD {
+        return Err(AppError::Validation(format!(
+            "days_ahead must be at most {MAX_DAYS_AHEAD}"
+        )));
+    }
+    let horizon = today + Duration::days(i64::from(days_ahead));
+    let day = |date: NaiveDate| date.format("%Y-%m-%d").to_string();
+
+    let tx = conn.transaction()?;
+    // A reminder goes with its rule, and is owed again if the payment is
+    // deleted.
+    tx.execute_batch(
+        "DELETE FROM reminders WHERE rule_id NOT IN (SELECT id FROM recurring_rules);
+         UPDATE reminders SET paid_transaction_id = NULL
+         WHERE paid_transaction_id NOT IN (SELECT id FROM transactions WHERE deleted_at IS NULL);",
+    )?;
+    let bills = bills(&tx)?;
+    let mut reminders = Vec::new();
+    {
+        let mut insert =
+            tx.prepare("INSERT OR IGNORE INTO reminders (rule_id, due_date) VALUES (?1, ?2)")?;
+        for (rule_id, bill) in &bills {
+            let mut due = bill.start;
+            while due <= horizon {
+                if due >= today {
+                    insert.execute(params![rule_id, day(due)])?;
+                }
+                due = bill.interval.advance(due, bill.start);
+            }
+        }
+
+        let mut stmt = tx.prepare(
+            "SELECT id, rule_id, due_date FROM reminders
+             WHERE paid_transaction_id IS NULL AND due_date <= ?1
+             ORDER BY due_date, id",
+        )?;
+        let unpaid = stmt
+            .query_map([day(horizon)], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        // Each transaction pays one reminder at most.
+        let mut payment = tx.prepare(
+            "SELECT id FROM transactions
+             WHERE deleted_at IS NULL AND amount_minor = ?1 AND category_id IS ?2
+               AND date > ?3 AND date < ?4
+               AND id NOT IN (SELECT paid_transaction_id FROM reminders
+                              WHERE paid_transaction_id IS NOT NULL)
+             ORDER BY date, id
+             LIMIT 1",
+        )?;
+        let mut mark_paid =
+            tx.prepare("UPDATE reminders SET paid_transaction_id = ?1 WHERE id = ?2")?;
+
+        for (id, rule_id, due_date) in unpaid {
+            // A rule that became income no longer reminds.
+            let Some(bill) = bills.get(&rule_id) else {
+                continue;
+            };
+            let due = parse_date(&due_date)?;
+            let (after, before) = period(bill, due);
+            let paid_by: Option<i64> = payment
+                .query_map(
+                    params![bill.amount_minor, bill.category_id, day(after), day(before)],
+                    |row| row.get(0),
+                )?
+                .next()
+                .transpose()?;
+            if let Some(transaction_id) = paid_by {
+                mark_paid.execute([transaction_id, id])?;
+                continue;
+            }
+            reminders.push(Reminder {
+                id,
+                rule_id,
+                account_id: bill.account_id,
+                description: bill.description.clone(),
+                amount_minor: bill.amount_minor,
+                category_id: bill.category_id,
+                overdue: due < today,
+                due_date,
+            });
+        }
+    }
+    tx.commit()?;
+    Ok(reminders)
+}
+
+fn bills(conn: &Connection) -> Result<BTreeMap<i64, Bill>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, account_id, description, amount_minor, category_id, interval, start_date
+         FROM recurring_rules WHERE amount_minor < 0",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Option<i64>>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.into_iter()
+        .map(|(id, account_id, description, amount_minor, category_id, interval, start)| {
+            Ok((
+                id,
+                Bill {
+                    account_id,
+                    description,
+                    amount_minor,
+                    category_id,
+                    interval: interval.parse()?,
+                    start: parse_date(&start)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Bounds, both exclusive, of the dates a payment for the occurrence on
+/// `due` may have: the previous occurrence and the next one. The first
+/// occurrence looks back as far as the next one lies ahead.
+fn period(bill: &Bill, due: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let mut previous = None;
+    let mut current = bill.start;
+    while current < due {
+        previous = Some(current);
+        current = bill.interval.advance(current, bill.start);
+    }
+    let next = bill.interval.advance(due, bill.start);
+    (previous.unwrap_or(due - (next - due)), next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn due_dates(reminders: &[Reminder]) -> Vec<(&str, bool)> {
+        reminders.iter().map(|r| (r.due_date.as_str(), r.overdue)).collect()
+    }
+
+    #[test]
+    fn a_bill_reminds_until_a_matching_payment_appears() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (id, name) VALUES (1, 'Home');
+             INSERT INTO recurring_rules
+                 (account_id, description, amount_minor, category_id, interval,
+                  start_date, next_run)
+             VALUES (1, 'Rent', -90000, 1, 'monthly', '2024-03-01', '2024-03-01'),
+                    (1, 'Salary', 250000, NULL, 'monthly', '2024-03-01', '2024-03-01');",
+        )
+        .unwrap();
+        let date = |s| parse_date(s).unwrap();
+
+        let first = upcoming(&mut conn, 10, date("2024-02-25")).unwrap();
+        assert_eq!(due_dates(&first), [("2024-03-01", false)]);
+        let later = upcoming(&mut conn, 30, date("2024-03-10")).unwrap();
+        assert_eq!(due_dates(&later), [("2024-03-01", true), ("2024-04-01", false)]);
+
+        // The wrong amount pays nothing; one payment settles one month.
+        conn.execute_batch(
+            "INSERT INTO transactions (account_id, date, amount_minor, category_id)
+             VALUES (1, '2024-03-02', -9000, 1), (1, '2024-03-03', -90000, 1);",
+        )
+        .unwrap();
+        let paid = upcoming(&mut conn, 30, date("2024-03-10")).unwrap();
+        assert_eq!(due_dates(&paid), [("2024-04-01", false)]);
+
+        conn.execute("UPDATE transactions SET deleted_at = '2024-03-11' WHERE id = 2", []).unwrap();
+        let owed = upcoming(&mut conn, 30, date("2024-03-12")).unwrap();
+        assert_eq!(due_dates(&owed), [("2024-03-01", true), ("2024-04-01", false)]);
+    }
+}
@@ -0,0 +1,1090 @@
+//! Aggregations computed in SQL so the UI doesn't have to sum rows itself.
+//!
+//! Reports skip soft-deleted rows and both legs of internal transfers, which
+//! would otherwise count once as income and once as expense. Per-category
+//! figures read [`CATEGORY_LINES`] so split transactions count by their splits.
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands::accounts::balance_as_of;
+use crate::commands::budgets::{budget_statuses, BudgetStatus};
+use crate::db;
+use crate::error::AppError;
+use crate::money::fx::{convert_between, reporting_currency};
+use crate::money::{div_round, format_minor};
+use crate::recurring::{parse_date, Interval};
+use crate::time::{month_start_day, next_month, period_containing, period_range};
+
+/// How far ahead the dashboard lists recurring transactions.
+const UPCOMING_DAYS: u32 = 30;
+
+/// Longest forecast [`forecast_balance`] will compute, about ten years.
+const MAX_FORECAST_DAYS: u32 = 3660;
+
+/// Subquery of `(transaction_id, date, category_id, amount_minor)` lines for
+/// live, non-transfer transactions: one line per split for split
+/// transactions, otherwise one line with the transaction's own category.
+pub const CATEGORY_LINES: &str = "
+    SELECT t.id AS transaction_id, t.date, t.category_id, t.amount_minor
+    FROM transactions t
+    WHERE t.deleted_at IS NULL AND t.transfer_id IS NULL
+      AND NOT EXISTS (SELECT 1 FROM transaction_splits s WHERE s.transaction_id = t.id)
+    UNION ALL
+    SELECT t.id, t.date, s.category_id, s.amount_minor
+    FROM transaction_splits s
+    JOIN transactions t ON t.id = s.transaction_id
+    WHERE t.deleted_at IS NULL AND t.transfer_id IS NULL";
+
+/// [`CATEGORY_LINES`] with each linked refund that has no splits of its own
+/// moved to the category of its purchase, provided the purchase is live and
+/// not split either.
+const NETTED_CATEGORY_LINES: &str = "
+    SELECT t.id AS transaction_id, t.date,
+           CASE WHEN p.id IS NULL THEN t.category_id ELSE p.category_id END AS category_id,
+           t.amount_minor
+    FROM transactions t
+    LEFT JOIN transactions p ON p.id = t.refund_of_id AND p.deleted_at IS NULL
+      AND NOT EXISTS (SELECT 1 FROM transaction_splits s WHERE s.transaction_id = p.id)
+    WHERE t.deleted_at IS NULL AND t.transfer_id IS NULL
+      AND NOT EXISTS (SELECT 1 FROM transaction_splits s WHERE s.transaction_id = t.id)
+    UNION ALL
+    SELECT t.id, t.date, s.category_id, s.amount_minor
+    FROM transaction_splits s
+    JOIN transactions t ON t.id = s.transaction_id
+    WHERE t.deleted_at IS NULL AND t.transfer_id IS NULL";
+
+/// Recursive CTE `category_roots(id, root_id, path)` mapping every category
+/// to its top-level ancestor; use after `WITH RECURSIVE`. The top level is
+/// the one [`crate::commands::categories::build_tree`] lists: categories
+/// without a parent or with a missing one, and the lowest id in each loop
+/// of parents. `path` lists the ids on the way down, so that walking round
+/// a loop stops.
+pub const CATEGORY_ROOTS: &str = "
+    category_ancestors(id, ancestor_id, path) AS (
+        SELECT id, parent_id, ',' || id || ',' FROM categories WHERE parent_id IS NOT NULL
+        UNION ALL
+        SELECT a.id, c.parent_id, a.path || a.ancestor_id || ','
+        FROM category_ancestors a JOIN categories c ON c.id = a.ancestor_id
+        WHERE c.parent_id IS NOT NULL AND instr(a.path, ',' || a.ancestor_id || ',') = 0
+    ),
+    category_roots(id, root_id, path) AS (
+        SELECT c.id, c.id, ',' || c.id || ',' FROM categories c
+        WHERE c.parent_id IS NULL
+           OR NOT EXISTS (SELECT 1 FROM categories p WHERE p.id = c.parent_id)
+           OR (EXISTS (SELECT 1 FROM category_ancestors a
+                       WHERE a.id = c.id AND a.ancestor_id = c.id)
+               AND c.id = (SELECT min(a.ancestor_id) FROM category_ancestors a
+                           WHERE a.id = c.id))
+        UNION ALL
+        SELECT c.id, r.root_id, r.path || c.id || ','
+        FROM categories c JOIN category_roots r ON c.parent_id = r.id
+        WHERE instr(r.path, ',' || c.id || ',') = 0
+    )";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryTotal {
+    pub category_id: Option<i64>,
+    pub category_name: Option<String>,
+    /// Signed minor units; expenses are negative.
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlySummary {
+    pub total_income: i64,
+    /// Positive minor units spent.
+    pub total_expense: i64,
+    pub net: i64,
+    /// Sorted by absolute total, largest first.
+    pub categories: Vec<CategoryTotal>,
+}
+
+/// With `rollup`, each top-level category's total includes everything
+/// filed under its subcategories, which then don't appear on their own.
+/// With `net_refunds`, a refund linked to a live purchase reduces expense
+/// instead of adding income, and counts under the purchase's category; see
+/// [`crate::commands::refunds`]. With `align_to_fiscal`, months follow the
+/// `fiscal_month_start_day` setting instead of the calendar; see
+/// [`crate::time`].
+#[tauri::command(async)]
+pub fn monthly_summary(
+    app: AppHandle,
+    year: i32,
+    month: u32,
+    rollup: bool,
+    net_refunds: bool,
+    align_to_fiscal: bool,
+) -> Result<MonthlySummary, AppError> {
+    db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        summarize_month(conn, year, month, rollup, net_refunds, start_day)
+    })
+}
+
+/// `start_day` is the day months begin on, 1 for calendar months.
+pub fn summarize_month(
+    conn: &Connection,
+    year: i32,
+    month: u32,
+    rollup: bool,
+    net_refunds: bool,
+    start_day: u32,
+) -> Result<MonthlySummary, AppError> {
+    let (start, end) = period_range(year, month, start_day)?;
+
+    // `side` is the sign that decides income or expense: a netted refund
+    // takes its purchase's.
+    let (total_income, total_expense): (i64, i64) = conn
+        .query_row(
+            "SELECT COALESCE(SUM(CASE WHEN side > 0 THEN amount_minor END), 0),
+                    COALESCE(-SUM(CASE WHEN side < 0 THEN amount_minor END), 0)
+             FROM (
+                SELECT t.amount_minor,
+                       CASE WHEN ?3 AND p.id IS NOT NULL THEN p.amount_minor
+                            ELSE t.amount_minor END AS side
+                FROM transactions t
+                LEFT JOIN transactions p ON p.id = t.refund_of_id AND p.deleted_at IS NULL
+                WHERE t.date >= ?1 AND t.date < ?2
+                  AND t.deleted_at IS NULL AND t.transfer_id IS NULL
+             )",
+            params![start, end, net_refunds],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+    let group = if rollup {
+        "(SELECT r.root_id FROM category_roots r WHERE r.id = l.category_id)"
+    } else {
+        "l.category_id"
+    };
+    let lines = if net_refunds { NETTED_CATEGORY_LINES } else { CATEGORY_LINES };
+    let mut stmt = conn
+        .prepare(&format!(
+            "WITH RECURSIVE {CATEGORY_ROOTS}
+             SELECT g.category_id, c.name, g.total FROM (
+                SELECT {group} AS category_id, SUM(l.amount_minor) AS total
+                FROM ({lines}) l
+                WHERE l.date >= ?1 AND l.date < ?2
+                GROUP BY 1
+             ) g
+             LEFT JOIN categories c ON c.id = g.category_id
+             ORDER BY ABS(g.total) DESC"
+        ))?;
+    let categories = stmt
+        .query_map(params![start, end], |row| {
+            Ok(CategoryTotal {
+                category_id: row.get(0)?,
+                category_name: row.get(1)?,
+                total: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(MonthlySummary {
+        total_income,
+        total_expense,
+        net: total_income - total_expense,
+        categories,
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearSpending {
+    pub year: i32,
+    /// Net amount spent, positive minor units.
+    pub spent: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YoyRow {
+    /// 1 to 12.
+    pub month: u32,
+    /// One entry per requested year, oldest first.
+    pub years: Vec<YearSpending>,
+    /// Change from the second most recent year to the most recent, rounded
+    /// to a whole percent; `None` without two years or when the earlier
+    /// one spent nothing.
+    pub change_percent: Option<i64>,
+}
+
+/// A category's spending per month in each of `years`, for comparing
+/// across years. Every month is listed, with zero where nothing was spent.
+/// With `align_to_fiscal`, months are fiscal months; see [`crate::time`].
+#[tauri::command(async)]
+pub fn category_yoy(
+    app: AppHandle,
+    category_id: i64,
+    years: Vec<i32>,
+    align_to_fiscal: bool,
+) -> Result<Vec<YoyRow>, AppError> {
+    db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        year_over_year(conn, category_id, &years, start_day)
+    })
+}
+
+pub fn year_over_year(
+    conn: &Connection,
+    category_id: i64,
+    years: &[i32],
+    start_day: u32,
+) -> Result<Vec<YoyRow>, AppError> {
+    let mut years = years.to_vec();
+    years.sort_unstable();
+    years.dedup();
+    let (Some(&first), Some(&last)) = (years.first(), years.last()) else {
+        return Err(AppError::Validation("at least one year is required".into()));
+    };
+    let exists: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM categories WHERE id = ?1)", [category_id], |row| {
+            row.get(0)
+        })?;
+    if !exists {
+        return Err(AppError::NotFound(format!("category {category_id} not found")));
+    }
+
+    let (start, _) = period_range(first, 1, start_day)?;
+    let (_, end) = period_range(last, 12, start_day)?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT l.date, -SUM(l.amount_minor) FROM ({CATEGORY_LINES}) l
+             WHERE l.category_id = ?1 AND l.date >= ?2 AND l.date < ?3
+             GROUP BY l.date"
+        ))?;
+    let days = stmt
+        .query_map(params![category_id, start, end], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // spent[month - 1][index into years]
+    let mut spent = vec![vec![0i64; years.len()]; 12];
+    for (date, amount) in days {
+        let period = period_containing(parse_date(&date)?, start_day)?;
+        if let Ok(i) = years.binary_search(&period.year) {
+            spent[period.month as usize - 1][i] += amount;
+        }
+    }
+
+    Ok(spent
+        .into_iter()
+        .enumerate()
+        .map(|(i, amounts)| {
+            let change_percent = match amounts[..] {
+                [.., previous, current] if previous != 0 => Some(div_round(
+                    i128::from(current - previous) * 100,
+                    i128::from(previous.abs()),
+                ) as i64),
+                _ => None,
+            };
+            YoyRow {
+                month: i as u32 + 1,
+                years: years
+                    .iter()
+                    .zip(amounts)
+                    .map(|(&year, spent)| YearSpending { year, spent })
+                    .collect(),
+                change_percent,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetWorthPoint {
+    /// `YYYY-MM`.
+    pub month: String,
+    /// Reporting currency `net_worth` is in.
+    pub currency: String,
+    /// Combined balance at the end of the month of every account that
+    /// could be converted.
+    pub net_worth: i64,
+    /// Set when some balances had no rate in the month and are listed in
+    /// `unconverted` instead of counting towards `net_worth`.
+    pub partial: bool,
+    pub unconverted: Vec<UnconvertedBalance>,
+}
+
+/// A month-end balance left in its account's own currency.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnconvertedBalance {
+    pub account_id: i64,
+    pub currency: String,
+    pub balance: i64,
+}
+
+/// Month-end net worth for every month from `from` to `to` (`YYYY-MM`,
+/// inclusive). Months without transactions carry the previous balance.
+/// Balances are converted into the reporting currency at the latest rate
+/// within the month; see [`crate::money::fx`]. With `align_to_fiscal`,
+/// months follow the `fiscal_month_start_day` setting instead of the
+/// calendar; see [`crate::time`].
+#[tauri::command(async)]
+pub fn net_worth_timeseries(
+    app: AppHandle,
+    from: String,
+    to: String,
+    align_to_fiscal: bool,
+) -> Result<Vec<NetWorthPoint>, AppError> {
+    db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        net_worth_points(conn, &from, &to, start_day)
+    })
+}
+
+pub fn net_worth_points(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+    start_day: u32,
+) -> Result<Vec<NetWorthPoint>, AppError> {
+    let first = parse_month(from)?;
+    let last = parse_month(to)?;
+    if first > last {
+        return Err(AppError::Validation(format!("{from} is after {to}")));
+    }
+    // Each month with its first day and the first day after it.
+    let mut months = Vec::new();
+    let (mut year, mut month) = first;
+    while (year, month) <= last {
+        let (start, end) = period_range(year, month, start_day)?;
+        months.push((format!("{year:04}-{month:02}"), parse_date(&start)?, end));
+        (year, month) = next_month(year, month);
+    }
+    let reporting = reporting_currency(conn)?;
+
+    // Movements per account and day, with each opening balance landing on
+    // the day the account was opened (or before everything if it has no
+    // date). Transactions dated before an account's opening date are
+    // already part of its opening balance.
+    let mut stmt = conn
+        .prepare(
+            "SELECT m.account_id, a.currency, m.date, SUM(m.amount) FROM (
+                SELECT id AS account_id, COALESCE(opening_date, '') AS date,
+                       opening_balance_minor AS amount
+                FROM accounts
+                UNION ALL
+                SELECT t.account_id, t.date, t.amount_minor
+                FROM transactions t
+                JOIN accounts a ON a.id = t.account_id
+                WHERE t.deleted_at IS NULL
+                  AND (a.opening_date IS NULL OR t.date >= a.opening_date)
+             ) m
+             JOIN accounts a ON a.id = m.account_id
+             WHERE m.date < ?1
+             GROUP BY m.account_id, m.date
+             ORDER BY m.date",
+        )?;
+    let movements = stmt
+        .query_map([months.last().map(|(_, _, end)| end)], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut movements = movements.into_iter().peekable();
+    // account -> (currency, balance)
+    let mut balances: BTreeMap<i64, (String, i64)> = BTreeMap::new();
+    let mut points = Vec::with_capacity(months.len());
+    for (month, start, end) in months {
+        while let Some((account_id, currency, _, amount)) =
+            movements.next_if(|(_, _, date, _)| *date < end)
+        {
+            balances.entry(account_id).or_insert((currency, 0)).1 += amount;
+        }
+        let month_end = parse_date(&end)? - Duration::days(1);
+        let mut net_worth = 0;
+        let mut unconverted = Vec::new();
+        for (&account_id, (currency, balance)) in &balances {
+            if *balance == 0 {
+                continue;
+            }
+            match convert_between(conn, *balance, currency, &reporting, start, month_end)? {
+                Some(converted) => net_worth += converted,
+                None => unconverted.push(UnconvertedBalance {
+                    account_id,
+                    currency: currency.clone(),
+                    balance: *balance,
+                }),
+            }
+        }
+        points.push(NetWorthPoint {
+            month,
+            currency: reporting.clone(),
+            net_worth,
+            partial: !unconverted.is_empty(),
+            unconverted,
+        });
+    }
+    Ok(points)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastPoint {
+    pub date: String,
+    /// Net of the recurring occurrences falling on this day.
+    pub change: i64,
+    /// Projected balance at the end of the day.
+    pub balance: i64,
+    pub negative: bool,
+}
+
+/// Projects an account's balance for each of the next `days_ahead` days,
+/// starting from today's balance and applying only the occurrences of its
+/// recurring rules. One-off transactions aren't extrapolated.
+#[tauri::command(async)]
+pub fn forecast_balance(
+    app: AppHandle,
+    account_id: i64,
+    days_ahead: u32,
+) -> Result<Vec<ForecastPoint>, AppError> {
+    db::with_conn(&app, |conn| {
+        forecast(conn, account_id, chrono::Local::now().date_naive(), days_ahead)
+    })
+}
+
+pub fn forecast(
+    conn: &Connection,
+    account_id: i64,
+    today: NaiveDate,
+    days_ahead: u32,
+) -> Result<Vec<ForecastPoint>, AppError> {
+    if days_ahead > MAX_FORECAST_DAYS {
+        return Err(AppError::Validation(format!(
+            "days_ahead must be at most {MAX_FORECAST_DAYS}"
+        )));
+    }
+    let today_s = today.format("%Y-%m-%d").to_string();
+    let mut balance = balance_as_of(conn, account_id, Some(&today_s))?;
+    let last = today + chrono::Duration::days(i64::from(days_ahead));
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT amount_minor, interval, start_date, next_run
+             FROM recurring_rules WHERE account_id = ?1",
+        )?;
+    let rules = stmt
+        .query_map([account_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Net change per day offset; index 0 is tomorrow. Occurrences up to
+    // today are already real transactions once the app has started.
+    let mut changes = vec![0i64; days_ahead as usize];
+    for (amount, interval, start, next) in rules {
+        let interval: Interval = interval.parse()?;
+        let start = parse_date(&start)?;
+        let mut next = parse_date(&next)?;
+        while next <= last {
+            if next > today {
+                changes[(next - today).num_days() as usize - 1] += amount;
+            }
+            next = interval.advance(next, start);
+        }
+    }
+
+    let mut points = Vec::with_capacity(changes.len());
+    let mut date = today;
+    for change in changes {
+        date = date.succ_opt().ok_or_else(|| AppError::Validation("date out of range".into()))?;
+        balance += change;
+        points.push(ForecastPoint {
+            date: date.format("%Y-%m-%d").to_string(),
+            change,
+            balance,
+            negative: balance < 0,
+        });
+    }
+    Ok(points)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountBalance {
+    pub account_id: i64,
+    pub name: String,
+    pub currency: String,
+    /// Balance at the end of the month.
+    pub balance: i64,
+    /// `balance` in the reporting currency, or `None` with `unconverted`
+    /// set when the month has no rate.
+    pub reporting_balance: Option<i64>,
+    pub unconverted: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpcomingRecurrence {
+    pub rule_id: i64,
+    pub account_id: i64,
+    pub description: String,
+    pub amount_minor: i64,
+    pub date: String,
+}
+
+/// Everything the home screen shows, in one call.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dashboard {
+    pub accounts: Vec<AccountBalance>,
+    pub reporting_currency: String,
+    /// Sum of the accounts' `reporting_balance`, leaving out unconverted
+    /// ones.
+    pub net_worth: i64,
+    pub total_income: i64,
+    pub total_expense: i64,
+    pub net: i64,
+    /// The five categories with the most net spending in the month.
+    pub top_spending: Vec<CategoryTotal>,
+    pub budgets: Vec<BudgetStatus>,
+    /// Next occurrence of each recurring rule due within 30 days, soonest
+    /// first.
+    pub upcoming: Vec<UpcomingRecurrence>,
+}
+
+/// Account balances are taken at the month's end and converted into the
+/// reporting currency at the latest rate within the month, as
+/// [`net_worth_timeseries`] does. With `align_to_fiscal`, months follow the
+/// `fiscal_month_start_day` setting instead of the calendar; see
+/// [`crate::time`].
+#[tauri::command(async)]
+pub fn dashboard_snapshot(
+    app: AppHandle,
+    year: i32,
+    month: u32,
+    align_to_fiscal: bool,
+) -> Result<Dashboard, AppError> {
+    db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        dashboard(conn, year, month, start_day, chrono::Local::now().date_naive())
+    })
+}
+
+pub fn dashboard(
+    conn: &Connection,
+    year: i32,
+    month: u32,
+    start_day: u32,
+    today: NaiveDate,
+) -> Result<Dashboard, AppError> {
+    let summary = summarize_month(conn, year, month, false, false, start_day)?;
+    let top_spending = summary
+        .categories
+        .into_iter()
+        .filter(|c| c.total < 0)
+        .take(5)
+        .collect();
+
+    let reporting_currency = reporting_currency(conn)?;
+    let (start, end) = period_range(year, month, start_day)?;
+    let month_start = parse_date(&start)?;
+    let month_end = parse_date(&end)? - Duration::days(1);
+    let closing_date = month_end.format("%Y-%m-%d").to_string();
+
+    let mut stmt = conn.prepare("SELECT id, name, currency FROM accounts ORDER BY name, id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut accounts = Vec::with_capacity(rows.len());
+    let mut net_worth = 0;
+    for (account_id, name, currency) in rows {
+        let balance = balance_as_of(conn, account_id, Some(&closing_date))?;
+        let reporting_balance = match balance {
+            0 => Some(0),
+            balance => convert_between(
+                conn,
+                balance,
+                &currency,
+                &reporting_currency,
+                month_start,
+                month_end,
+            )?,
+        };
+        net_worth += reporting_balance.unwrap_or(0);
+        accounts.push(AccountBalance {
+            account_id,
+            name,
+            currency,
+            balance,
+            reporting_balance,
+            unconverted: reporting_balance.is_none(),
+        });
+    }
+
+    let horizon = today + chrono::Duration::days(i64::from(UPCOMING_DAYS));
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, account_id, description, amount_minor, next_run
+             FROM recurring_rules WHERE next_run <= ?1
+             ORDER BY next_run, id",
+        )?;
+    let upcoming = stmt
+        .query_map([horizon.format("%Y-%m-%d").to_string()], |row| {
+            Ok(UpcomingRecurrence {
+                rule_id: row.get(0)?,
+                account_id: row.get(1)?,
+                description: row.get(2)?,
+                amount_minor: row.get(3)?,
+                date: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Dashboard {
+        accounts,
+        reporting_currency,
+        net_worth,
+        total_income: summary.total_income,
+        total_expense: summary.total_expense,
+        net: summary.net,
+        top_spending,
+        budgets: budget_statuses(conn, year, month, start_day)?,
+        upcoming,
+    })
+}
+
+/// Writes a Markdown statement for a month to `path`: each account's
+/// opening and closing balance, net amounts by category and the month's
+/// transactions, transfers included. Columns are padded so the file reads
+/// just as well as plain text. With `align_to_fiscal`, the month is the
+/// fiscal month; see [`crate::time`].
+#[tauri::command(async)]
+pub fn generate_statement(
+    app: AppHandle,
+    year: i32,
+    month: u32,
+    align_to_fiscal: bool,
+    path: String,
+) -> Result<(), AppError> {
+    let text = db::with_conn(&app, |conn| {
+        let start_day = month_start_day(conn, align_to_fiscal)?;
+        statement(conn, year, month, start_day, chrono::Local::now().date_naive())
+    })?;
+    fs::write(&path, text).map_err(|e| AppError::Io(format!("{path}: {e}")))
+}
+
+/// Amounts are shown in their account's currency, so a category used from
+/// accounts in different currencies gets a row for each. A month that
+/// doesn't start on the 1st names its first and last day under the title.
+pub fn statement(
+    conn: &Connection,
+    year: i32,
+    month: u32,
+    start_day: u32,
+    today: NaiveDate,
+) -> Result<String, AppError> {
+    let (start, end) = period_range(year, month, start_day)?;
+    let first = parse_date(&start)?;
+    let opening_date = (first - Duration::days(1)).format("%Y-%m-%d").to_string();
+    let closing_date = (parse_date(&end)? - Duration::days(1)).format("%Y-%m-%d").to_string();
+    let title = NaiveDate::from_ymd_opt(year, month, 1)
+        .ok_or_else(|| AppError::Validation(format!("invalid month {year}-{month:02}")))?;
+    let mut out = format!("# Statement for {}\n\n", title.format("%B %Y"));
+    if start_day != 1 {
+        out += &format!("From {start} to {closing_date}.\n");
+    }
+    out += &format!("Generated {today}.\n");
+
+    let mut stmt = conn.prepare("SELECT id, name, currency FROM accounts ORDER BY name, id")?;
+    let accounts = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut rows = Vec::new();
+    for (id, name, currency) in accounts {
+        let opening = balance_as_of(conn, id, Some(&opening_date))?;
+        let closing = balance_as_of(conn, id, Some(&closing_date))?;
+        rows.push(vec![name, money(opening, &currency), money(closing, &currency)]);
+    }
+    out += "\n## Accounts\n\n";
+    out += &table(&["Account", "Opening", "Closing"], &[false, true, true], &rows);
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT COALESCE(c.name, 'Uncategorized'), a.currency, SUM(l.amount_minor) AS total
+         FROM ({CATEGORY_LINES}) l
+         JOIN transactions t ON t.id = l.transaction_id
+         JOIN accounts a ON a.id = t.account_id
+         LEFT JOIN categories c ON c.id = l.category_id
+         WHERE l.date >= ?1 AND l.date < ?2
+         GROUP BY l.category_id, a.currency
+         ORDER BY ABS(total) DESC, 1"
+    ))?;
+    let rows = stmt
+        .query_map(params![start, end], |row| {
+            let currency: String = row.get(1)?;
+            Ok(vec![row.get(0)?, money(row.get(2)?, &currency)])
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    out += "\n## Categories\n\n";
+    out += &table(&["Category", "Amount"], &[false, true], &rows);
+
+    let mut stmt = conn.prepare(
+        "SELECT t.date, a.name, t.description,
+                CASE WHEN t.transfer_id IS NOT NULL THEN 'Transfer'
+                     WHEN EXISTS (SELECT 1 FROM transaction_splits s WHERE s.transaction_id = t.id)
+                        THEN 'Split'
+                     ELSE COALESCE(c.name, '') END,
+                t.amount_minor, a.currency
+         FROM transactions t
+         JOIN accounts a ON a.id = t.account_id
+         LEFT JOIN categories c ON c.id = t.category_id
+         WHERE t.deleted_at IS NULL AND t.date >= ?1 AND t.date < ?2
+         ORDER BY t.date, t.id",
+    )?;
+    let rows = stmt
+        .query_map(params![start, end], |row| {
+            let currency: String = row.get(5)?;
+            Ok(vec![
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                money(row.get(4)?, &currency),
+            ])
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    out += "\n## Transactions\n\n";
+    out += &table(
+        &["Date", "Account", "Description", "Category", "Amount"],
+        &[false, false, false, false, true],
+        &rows,
+    );
+    Ok(out)
+}
+
+fn money(amount_minor: i64, currency: &str) -> String {
+    format!("{} {currency}", format_minor(amount_minor))
+}
+
+/// A Markdown table with every column padded to its widest cell. `right`
+/// marks the columns to right-align.
+fn table(headers: &[&str], right: &[bool], rows: &[Vec<String>]) -> String {
+    if rows.is_empty() {
+        return "None.\n".to_string();
+    }
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            // A line break would end the table row.
+            row.iter().map(|cell| cell.replace(['\r', '\n'], " ").replace('|', "\\|")).collect()
+        })
+        .collect();
+    // Three dashes is the shortest separator Markdown accepts.
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count().max(3)).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let pad = |i: usize, cell: &str| {
+        let width = widths[i];
+        if right[i] {
+            format!("{cell:>width$}")
+        } else {
+            format!("{cell:<width$}")
+        }
+    };
+
+    let mut out = line(headers.iter().enumerate().map(|(i, h)| pad(i, h)).collect());
+    out += &line(
+        widths
+            .iter()
+            .zip(right)
+            .map(|(&width, &right)| {
+                if right {
+                    format!("{}:", "-".repeat(width - 1))
+                } else {
+                    "-".repeat(width)
+                }
+            })
+            .collect(),
+    );
+    for row in &rows {
+        out += &line(row.iter().enumerate().map(|(i, cell)| pad(i, cell)).collect());
+    }
+    out
+}
+
+/// Parses `YYYY-MM`.
+pub fn parse_month(s: &str) -> Result<(i32, u32), AppError> {
+    let date = NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d")
+        .map_err(|_| AppError::Validation(format!("invalid month {s:?}, expected YYYY-MM")))?;
+    Ok((date.year(), date.month()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn a_netted_refund_reduces_its_purchases_category() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (id, name) VALUES (1, 'Clothes');
+             INSERT INTO transactions (id, account_id, date, amount_minor, category_id)
+             VALUES (1, 1, '2024-03-02', -8000, 1),
+                    (2, 1, '2024-03-20', 3000, NULL),
+                    (3, 1, '2024-03-25', 50000, NULL);",
+        )
+        .unwrap();
+        crate::commands::refunds::link(&mut conn, 1, 2).unwrap();
+
+        let gross = summarize_month(&conn, 2024, 3, false, false, 1).unwrap();
+        assert_eq!((gross.total_income, gross.total_expense), (53000, 8000));
+        let netted = summarize_month(&conn, 2024, 3, false, true, 1).unwrap();
+        assert_eq!((netted.total_income, netted.total_expense), (50000, 5000));
+        assert_eq!(netted.net, gross.net);
+        let clothes = netted.categories.iter().find(|c| c.category_id == Some(1)).unwrap();
+        assert_eq!(clothes.total, -5000);
+    }
+
+    #[test]
+    fn the_dashboard_and_the_timeseries_agree_on_net_worth() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name, currency, opening_balance_minor) VALUES
+                 ('Checking', 'USD', 1000), ('Girokonto', 'EUR', 200);
+             INSERT INTO transactions (account_id, date, amount_minor)
+                 VALUES (2, '2024-02-10', 100);
+             INSERT INTO fx_rates (base, quote, date, rate_micros)
+                 VALUES ('EUR', 'USD', '2024-01-20', 1500000);
+             INSERT INTO settings (key, value) VALUES ('currency.reporting', 'USD');",
+        )
+        .unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let points = net_worth_points(&conn, "2024-01", "2024-02", 1).unwrap();
+
+        // The January balance, before February's income, at January's rate.
+        let january = dashboard(&conn, 2024, 1, 1, today).unwrap();
+        assert_eq!(january.net_worth, 1300);
+        assert_eq!(january.accounts[1].balance, 200);
+        assert_eq!(points[0].net_worth, january.net_worth);
+
+        // February has no rate, and a January one isn't used.
+        let february = dashboard(&conn, 2024, 2, 1, today).unwrap();
+        assert_eq!(february.net_worth, 1000);
+        assert!(february.accounts.iter().any(|a| a.unconverted && a.currency == "EUR"));
+        assert_eq!(points[1].net_worth, february.net_worth);
+        assert!(points[1].partial);
+    }
+
+    #[test]
+    fn rollup_keeps_orphans_and_loops_under_their_own_top_level() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (id, name, parent_id) VALUES
+                 (1, 'Food', NULL), (2, 'Groceries', 1), (3, 'Pets', 99), (4, 'Vet', 3),
+                 (5, 'A', 6), (6, 'B', 5), (7, 'Below', 6);
+             INSERT INTO transactions (account_id, date, amount_minor, category_id) VALUES
+                 (1, '2024-03-01', -100, 2), (1, '2024-03-02', -20, 4),
+                 (1, '2024-03-03', -3, 6), (1, '2024-03-04', -4, 7);",
+        )
+        .unwrap();
+        let summary = summarize_month(&conn, 2024, 3, true, false, 1).unwrap();
+        let totals: Vec<_> =
+            summary.categories.iter().map(|c| (c.category_id, c.total)).collect();
+        assert_eq!(totals, [(Some(1), -100), (Some(3), -20), (Some(5), -7)]);
+    }
+
+    #[test]
+    fn statement_cells_stay_on_one_row() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (account_id, date, description, amount_minor)
+                 VALUES (1, '2024-03-20', 'Rent
+April | May', -1000);",
+        )
+        .unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 4, 20).unwrap();
+
+        let text = statement(&conn, 2024, 3, 15, today).unwrap();
+        assert!(text.contains("From 2024-03-15 to 2024-04-14."), "{text}");
+        let row = text.lines().find(|line| line.contains("Rent")).unwrap();
+        assert!(row.contains("Rent April \\| May"), "{row}");
+    }
+
+    #[test]
+    fn monthly_summary_skips_trash_and_transfers() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking'), ('Savings');
+             INSERT INTO categories (id, name) VALUES (1, 'Food'), (2, 'Home'), (3, 'Salary');
+             INSERT INTO transactions (account_id, date, amount_minor, category_id, deleted_at)
+             VALUES (1, '2024-03-01', 250000, 3, NULL),
+                    (1, '2024-03-03', -4000, 1, NULL),
+                    (1, '2024-03-04', -90000, 2, NULL),
+                    (1, '2024-03-05', -7000, 1, '2024-03-06'),
+                    (1, '2024-04-01', -1000, 1, NULL);",
+        )
+        .unwrap();
+        crate::commands::transfers::insert_transfer(&mut conn, 1, 2, 50000, "2024-03-10").unwrap();
+
+        let summary = summarize_month(&conn, 2024, 3, false, false, 1).unwrap();
+        assert_eq!((summary.total_income, summary.total_expense), (250000, 94000));
+        assert_eq!(summary.net, 156000);
+        let categories: Vec<_> =
+            summary.categories.iter().map(|c| (c.category_name.as_deref(), c.total)).collect();
+        assert_eq!(
+            categories,
+            [(Some("Salary"), 250000), (Some("Home"), -90000), (Some("Food"), -4000)]
+        );
+    }
+
+    #[test]
+    fn net_worth_carries_forward_and_starts_accounts_when_opened() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name, opening_balance_minor, opening_date) VALUES
+                 ('Checking', 1000, NULL), ('Savings', 5000, '2024-02-15');
+             INSERT INTO transactions (account_id, date, amount_minor) VALUES
+                 (1, '2024-01-10', -200),
+                 (2, '2024-02-01', 999),
+                 (2, '2024-02-20', 300),
+                 (1, '2024-04-05', 100);",
+        )
+        .unwrap();
+
+        let points = net_worth_points(&conn, "2024-01", "2024-04", 1).unwrap();
+        let worth: Vec<_> = points.iter().map(|p| (p.month.as_str(), p.net_worth)).collect();
+        assert_eq!(
+            worth,
+            [("2024-01", 800), ("2024-02", 6100), ("2024-03", 6100), ("2024-04", 6200)]
+        );
+        assert!(points.iter().all(|p| !p.partial && p.currency == "USD"));
+        assert!(matches!(
+            net_worth_points(&conn, "2024-04", "2024-01", 1),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn the_forecast_applies_only_upcoming_recurrences() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name, opening_balance_minor) VALUES ('Checking', 1000);
+             INSERT INTO transactions (account_id, date, amount_minor)
+                 VALUES (1, '2024-03-01', -400), (1, '2024-03-20', -9999);
+             INSERT INTO recurring_rules (account_id, amount_minor, interval, start_date, next_run)
+             VALUES (1, -700, 'weekly', '2024-03-04', '2024-03-11'),
+                    (1, 2000, 'monthly', '2024-01-15', '2024-03-15');",
+        )
+        .unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+
+        let points = forecast(&conn, 1, today, 6).unwrap();
+        let days: Vec<_> =
+            points.iter().map(|p| (p.date.as_str(), p.change, p.balance, p.negative)).collect();
+        assert_eq!(
+            days,
+            [
+                ("2024-03-11", -700, -100, true),
+                ("2024-03-12", 0, -100, true),
+                ("2024-03-13", 0, -100, true),
+                ("2024-03-14", 0, -100, true),
+                ("2024-03-15", 2000, 1900, false),
+                ("2024-03-16", 0, 1900, false),
+            ]
+        );
+        assert!(matches!(
+            forecast(&conn, 1, today, MAX_FORECAST_DAYS + 1),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn the_dashboard_lists_top_spending_and_upcoming_bills() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (id, name) VALUES
+                 (1, 'A'), (2, 'B'), (3, 'C'), (4, 'D'), (5, 'E'), (6, 'F'), (7, 'Salary');
+             INSERT INTO transactions (account_id, date, amount_minor, category_id)
+             VALUES (1, '2024-03-01', 100000, 7),
+                    (1, '2024-03-02', -100, 1), (1, '2024-03-02', -600, 2),
+                    (1, '2024-03-02', -300, 3), (1, '2024-03-02', -500, 4),
+                    (1, '2024-03-02', -200, 5), (1, '2024-03-02', -400, 6);
+             INSERT INTO recurring_rules
+                 (account_id, description, amount_minor, interval, start_date, next_run)
+             VALUES (1, 'Rent', -90000, 'monthly', '2024-01-01', '2024-04-01'),
+                    (1, 'Gym', -3000, 'monthly', '2024-01-12', '2024-03-12'),
+                    (1, 'Insurance', -12000, 'yearly', '2023-06-01', '2024-06-01');",
+        )
+        .unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+
+        let dashboard = dashboard(&conn, 2024, 3, 1, today).unwrap();
+        let top: Vec<_> = dashboard.top_spending.iter().map(|c| c.total).collect();
+        assert_eq!(top, [-600, -500, -400, -300, -200]);
+        let upcoming: Vec<_> = dashboard.upcoming.iter().map(|u| u.description.as_str()).collect();
+        assert_eq!(upcoming, ["Gym", "Rent"]);
+        assert_eq!((dashboard.total_income, dashboard.total_expense), (100000, 2100));
+        assert_eq!(dashboard.accounts[0].balance, 97900);
+        assert_eq!(dashboard.budgets.len(), 7);
+    }
+
+    #[test]
+    fn year_over_year_lists_every_month_side_by_side() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (id, name) VALUES (1, 'Groceries');
+             INSERT INTO transactions (account_id, date, amount_minor, category_id)
+             VALUES (1, '2023-01-10', -20000, 1), (1, '2024-01-12', -25000, 1),
+                    (1, '2024-01-30', 1000, 1), (1, '2024-02-03', -3000, 1),
+                    (1, '2022-01-05', -99999, 1);",
+        )
+        .unwrap();
+
+        let rows = year_over_year(&conn, 1, &[2024, 2023, 2024], 1).unwrap();
+        assert_eq!(rows.len(), 12);
+        let january: Vec<_> = rows[0].years.iter().map(|y| (y.year, y.spent)).collect();
+        assert_eq!(january, [(2023, 20000), (2024, 24000)]);
+        assert_eq!(rows[0].change_percent, Some(20));
+        // Nothing spent the year before leaves the change undefined.
+        assert_eq!(rows[1].years[1].spent, 3000);
+        assert_eq!(rows[1].change_percent, None);
+        assert!(rows[11].years.iter().all(|y| y.spent == 0));
+
+        assert!(matches!(year_over_year(&conn, 1, &[], 1), Err(AppError::Validation(_))));
+        assert!(matches!(year_over_year(&conn, 2, &[2024], 1), Err(AppError::NotFound(_))));
+    }
+}
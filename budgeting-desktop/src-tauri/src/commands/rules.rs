@@ -0,0 +1,229 @@
+//! Auto-categorization rules.
+//!
+//! A rule matches one text field of a transaction against a pattern. A
+//! pattern containing `*` (any run of characters) or `?` (any one
+//! character) is a glob that must match the whole field; anything else
+//! matches as a substring. Matching ignores case either way.
+//!
+//! Rules are tried from the highest `priority` down, ties broken by age,
+//! and the first match decides the category.
+
+use std::str::FromStr;
+
+use rusqlite::{params, Connection};
+use tauri::AppHandle;
+
+use crate::commands::transactions::{Transaction, TRANSACTION_COLUMNS};
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchField {
+    Description,
+    Notes,
+}
+
+impl FromStr for MatchField {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "description" => Ok(Self::Description),
+            "notes" => Ok(Self::Notes),
+            other => Err(AppError::Validation(format!("unknown match field {other:?}"))),
+        }
+    }
+}
+
+impl MatchField {
+    fn value(self, t: &Transaction) -> &str {
+        match self {
+            Self::Description => &t.description,
+            Self::Notes => t.notes.as_deref().unwrap_or(""),
+        }
+    }
+}
+
+/// A rule ready to match, with its pattern already lowercased.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub field: MatchField,
+    pub pattern: String,
+    pub category_id: i64,
+}
+
+impl Rule {
+    pub fn new(field: MatchField, pattern: &str, category_id: i64) -> Self {
+        Self {
+            field,
+            pattern: pattern.to_lowercase(),
+            category_id,
+        }
+    }
+
+    pub fn matches(&self, t: &Transaction) -> bool {
+        let value = self.field.value(t).to_lowercase();
+        if self.pattern.contains(['*', '?']) {
+            glob_match(&self.pattern, &value)
+        } else {
+            value.contains(&self.pattern)
+        }
+    }
+}
+
+/// Runs every rule over live, unsplit transactions (transfers excluded) and
+/// returns how many got a new category. With `only_uncategorized` set,
+/// transactions that already have a category are left alone.
+#[tauri::command(async)]
+pub fn apply_categorization_rules(app: AppHandle, only_uncategorized: bool) -> Result<usize, AppError> {
+    db::with_conn(&app, |conn| apply_rules(conn, only_uncategorized))
+}
+
+/// Live transactions a pattern would match, newest first, without changing
+/// anything.
+#[tauri::command(async)]
+pub fn test_rule(app: AppHandle, pattern: String, field: String) -> Result<Vec<Transaction>, AppError> {
+    if pattern.trim().is_empty() {
+        return Err(AppError::Validation("pattern must not be empty".into()));
+    }
+    let rule = Rule::new(field.parse()?, &pattern, 0);
+    db::with_conn(&app, |conn| {
+        let rows = candidates(conn, false)?;
+        Ok(rows.into_iter().rev().filter(|t| rule.matches(t)).collect())
+    })
+}
+
+pub fn apply_rules(conn: &mut Connection, only_uncategorized: bool) -> Result<usize, AppError> {
+    let rules = load_rules(conn)?;
+    if rules.is_empty() {
+        return Ok(0);
+    }
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+    for t in candidates(&tx, only_uncategorized)? {
+        let Some(rule) = rules.iter().find(|r| r.matches(&t)) else {
+            continue;
+        };
+        if t.category_id == Some(rule.category_id) {
+            continue;
+        }
+        tx.execute(
+            "UPDATE transactions SET category_id = ?1 WHERE id = ?2",
+            params![rule.category_id, t.id],
+        )?;
+        changed += 1;
+    }
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Rules in the order they should be tried.
+pub fn load_rules(conn: &Connection) -> Result<Vec<Rule>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT match_field, pattern, category_id FROM categorization_rules
+             ORDER BY priority DESC, id",
+        )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.into_iter()
+        .map(|(field, pattern, category_id)| Ok(Rule::new(field.parse()?, &pattern, category_id)))
+        .collect()
+}
+
+/// Transactions a rule may recategorize, oldest first. Split transactions
+/// are skipped since their categories live on the splits.
+fn candidates(conn: &Connection, only_uncategorized: bool) -> Result<Vec<Transaction>, AppError> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+             WHERE t.deleted_at IS NULL AND t.transfer_id IS NULL
+               AND NOT EXISTS (SELECT 1 FROM transaction_splits s WHERE s.transaction_id = t.id)
+               AND (?1 = 0 OR t.category_id IS NULL)
+             ORDER BY t.date, t.id"
+        ))?;
+    let rows = stmt
+        .query_map([only_uncategorized], Transaction::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Matches `*` and `?` wildcards against the whole of `text`.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Where the last `*` was, and how much text it has swallowed so far.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('*') => {
+                backtrack = Some((pi, ti));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match backtrack {
+                Some((star, consumed)) => {
+                    pi = star + 1;
+                    ti = consumed + 1;
+                    backtrack = Some((star, consumed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn globs_match_the_whole_text() {
+        assert!(glob_match("amazon*", "amazon mktp us"));
+        assert!(glob_match("*coffee*", "blue bottle coffee co"));
+        assert!(glob_match("uber ?ats", "uber eats"));
+        assert!(glob_match("a*b*c", "a-b-b-c"));
+        assert!(glob_match("**", ""));
+        assert!(!glob_match("amazon*", "paid amazon"));
+        assert!(!glob_match("uber ?ats", "uber  eats"));
+        assert!(!glob_match("a*b", "a-b-c"));
+    }
+
+    #[test]
+    fn globs_count_characters_not_bytes() {
+        assert!(glob_match("caf?", "café"));
+        assert!(glob_match("*é", "crème brûlé"));
+    }
+
+    #[test]
+    fn the_highest_priority_match_wins() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (name) VALUES ('Shopping'), ('Books');
+             INSERT INTO categorization_rules (match_field, pattern, category_id, priority)
+                 VALUES ('description', 'AMAZON', 1, 0), ('description', 'amazon*books', 2, 5);
+             INSERT INTO transactions (account_id, date, description, amount_minor) VALUES
+                 (1, '2024-03-01', 'Amazon Books', -1500),
+                 (1, '2024-03-02', 'Amazon.com', -900),
+                 (1, '2024-03-03', 'Bakery', -400);",
+        )
+        .unwrap();
+
+        assert_eq!(apply_rules(&mut conn, true).unwrap(), 2);
+        let mut stmt = conn.prepare("SELECT category_id FROM transactions ORDER BY id").unwrap();
+        let categories: Vec<Option<i64>> =
+            stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(categories, [Some(2), Some(1), None]);
+    }
+}
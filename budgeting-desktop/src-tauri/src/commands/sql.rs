@@ -0,0 +1,133 @@
+//! Statements from the frontend's store, run on the shared connection.
+//!
+//! tauri-plugin-sql opens its own pool on a fixed path, which never gets the
+//! SQLCipher passphrase and keeps reading the default profile after a
+//! switch. Going through these commands, the frontend reads whatever the
+//! Rust side has open. Results have the shape the plugin returns.
+
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection};
+use serde::Serialize;
+use serde_json::{Map, Number, Value as Json};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecuteResult {
+    pub rows_affected: u64,
+    pub last_insert_id: i64,
+}
+
+/// Runs one statement that returns no rows.
+#[tauri::command(async)]
+pub fn sql_execute(
+    app: AppHandle,
+    sql: String,
+    params: Vec<Json>,
+) -> Result<ExecuteResult, AppError> {
+    db::with_conn(&app, |conn| execute(conn, &sql, &params))
+}
+
+/// Runs a query, one JSON object per row keyed by column name.
+#[tauri::command(async)]
+pub fn sql_select(
+    app: AppHandle,
+    sql: String,
+    params: Vec<Json>,
+) -> Result<Vec<Map<String, Json>>, AppError> {
+    db::with_conn(&app, |conn| select(conn, &sql, &params))
+}
+
+pub fn execute(conn: &Connection, sql: &str, params: &[Json]) -> Result<ExecuteResult, AppError> {
+    let rows_affected = conn.execute(sql, params_from_iter(params.iter().map(to_sql)))?;
+    // Every command shares the connection, so a transaction can't stay
+    // open between calls.
+    if !conn.is_autocommit() {
+        conn.execute_batch("ROLLBACK")?;
+        return Err(AppError::Validation(
+            "a transaction can't span several statements".to_string(),
+        ));
+    }
+    Ok(ExecuteResult {
+        rows_affected: rows_affected as u64,
+        last_insert_id: conn.last_insert_rowid(),
+    })
+}
+
+pub fn select(
+    conn: &Connection,
+    sql: &str,
+    params: &[Json],
+) -> Result<Vec<Map<String, Json>>, AppError> {
+    let mut stmt = conn.prepare(sql)?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(str::to_string).collect();
+    let mut rows = stmt.query(params_from_iter(params.iter().map(to_sql)))?;
+    let mut out = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut object = Map::new();
+        for (i, name) in names.iter().enumerate() {
+            object.insert(name.clone(), to_json(row.get_ref(i)?));
+        }
+        out.push(object);
+    }
+    Ok(out)
+}
+
+/// Binds a JSON parameter the way the plugin does: booleans as 0 or 1,
+/// arrays and objects as their JSON text.
+fn to_sql(value: &Json) -> Value {
+    match value {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Integer(i64::from(*b)),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => n.as_f64().map_or(Value::Null, Value::Real),
+        },
+        Json::String(s) => Value::Text(s.clone()),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+fn to_json(value: ValueRef<'_>) -> Json {
+    match value {
+        ValueRef::Null => Json::Null,
+        ValueRef::Integer(i) => Json::from(i),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Json::Null, Json::Number),
+        ValueRef::Text(t) => Json::String(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => Json::from(b.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn rows_come_back_keyed_by_column() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (id TEXT, amount REAL, flag INTEGER, note TEXT)")
+            .unwrap();
+        let params = [json!("a"), json!(1.5), json!(true), Json::Null];
+        let result = execute(&conn, "INSERT INTO t VALUES (?, ?, ?, ?)", &params).unwrap();
+        assert_eq!(result.rows_affected, 1);
+        assert_eq!(result.last_insert_id, 1);
+
+        let rows = select(&conn, "SELECT * FROM t WHERE id = ?", &[json!("a")]).unwrap();
+        assert_eq!(
+            Json::Object(rows[0].clone()),
+            json!({ "id": "a", "amount": 1.5, "flag": 1, "note": null })
+        );
+    }
+
+    #[test]
+    fn an_open_transaction_is_rolled_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(matches!(execute(&conn, "BEGIN", &[]), Err(AppError::Validation(_))));
+        assert!(conn.is_autocommit());
+    }
+}
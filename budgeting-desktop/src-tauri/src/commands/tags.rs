@@ -0,0 +1,131 @@
+//! Free-form tags such as "vacation2024" or "reimbursable".
+//!
+//! Tag names are stored normalized, so "Vacation2024 " and "vacation2024"
+//! are the same tag.
+
+use rusqlite::{params, Connection};
+use tauri::AppHandle;
+
+use crate::commands::transactions::{Transaction, TRANSACTION_COLUMNS};
+use crate::db;
+use crate::error::AppError;
+
+/// Tags a transaction, creating the tag on first use. Tagging twice is a
+/// no-op.
+#[tauri::command(async)]
+pub fn add_tag(app: AppHandle, transaction_id: i64, tag: String) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| tag_transaction(conn, transaction_id, &tag))
+}
+
+/// Removes a tag from a transaction. The tag itself is kept for reuse.
+#[tauri::command(async)]
+pub fn remove_tag(app: AppHandle, transaction_id: i64, tag: String) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| untag_transaction(conn, transaction_id, &tag))
+}
+
+/// Live transactions carrying `tag`, newest first.
+#[tauri::command(async)]
+pub fn transactions_by_tag(app: AppHandle, tag: String) -> Result<Vec<Transaction>, AppError> {
+    db::with_conn(&app, |conn| tagged(conn, &tag))
+}
+
+pub fn tag_transaction(
+    conn: &mut Connection,
+    transaction_id: i64,
+    tag: &str,
+) -> Result<(), AppError> {
+    let name = normalize_tag(tag)?;
+    let tx = conn.transaction()?;
+    let exists: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM transactions WHERE id = ?1 AND deleted_at IS NULL)",
+        [transaction_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(AppError::NotFound(format!("transaction {transaction_id} not found")));
+    }
+    let tag_id = ensure_tag(&tx, &name)?;
+    tx.execute(
+        "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag_id) VALUES (?1, ?2)",
+        params![transaction_id, tag_id],
+    )?;
+    tx.commit().map_err(AppError::from)
+}
+
+pub fn untag_transaction(
+    conn: &Connection,
+    transaction_id: i64,
+    tag: &str,
+) -> Result<(), AppError> {
+    let name = normalize_tag(tag)?;
+    conn.execute(
+        "DELETE FROM transaction_tags
+         WHERE transaction_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+        params![transaction_id, name],
+    )?;
+    Ok(())
+}
+
+pub fn tagged(conn: &Connection, tag: &str) -> Result<Vec<Transaction>, AppError> {
+    let name = normalize_tag(tag)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+         JOIN transaction_tags tt ON tt.transaction_id = t.id
+         JOIN tags g ON g.id = tt.tag_id
+         WHERE g.name = ?1 AND t.deleted_at IS NULL
+         ORDER BY t.date DESC, t.id DESC"
+    ))?;
+    let rows = stmt.query_map([name], Transaction::from_row)?.collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Trims and lowercases; an empty result is an error.
+pub fn normalize_tag(tag: &str) -> Result<String, AppError> {
+    let name = tag.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(AppError::Validation("tag must not be empty".into()));
+    }
+    Ok(name)
+}
+
+/// ID of the tag called `name` (already normalized), inserting it if new.
+pub fn ensure_tag(conn: &Connection, name: &str) -> Result<i64, AppError> {
+    conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [name])?;
+    conn.query_row("SELECT id FROM tags WHERE name = ?1", [name], |row| row.get(0))
+        .map_err(AppError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn tags_are_normalized_and_reused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (account_id, date, description, amount_minor, deleted_at)
+             VALUES (1, '2024-03-01', 'Hotel', -30000, NULL),
+                    (1, '2024-03-02', 'Flight', -50000, NULL),
+                    (1, '2024-03-03', 'Trashed', -100, '2024-03-04');",
+        )
+        .unwrap();
+
+        tag_transaction(&mut conn, 1, "Vacation2024 ").unwrap();
+        tag_transaction(&mut conn, 2, "vacation2024").unwrap();
+        tag_transaction(&mut conn, 2, "VACATION2024").unwrap();
+        let tags: i64 = conn.query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0)).unwrap();
+        assert_eq!(tags, 1);
+        assert!(matches!(tag_transaction(&mut conn, 3, "x"), Err(AppError::NotFound(_))));
+        assert!(matches!(tag_transaction(&mut conn, 1, "  "), Err(AppError::Validation(_))));
+
+        let names = |conn: &Connection| -> Vec<String> {
+            tagged(conn, " Vacation2024").unwrap().into_iter().map(|t| t.description).collect()
+        };
+        assert_eq!(names(&conn), ["Flight", "Hotel"]);
+        untag_transaction(&conn, 2, "vacation2024").unwrap();
+        assert_eq!(names(&conn), ["Hotel"]);
+    }
+}
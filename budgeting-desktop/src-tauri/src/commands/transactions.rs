@@ -0,0 +1,1026 @@
+//! Reading and editing individual transactions.
+
+use std::collections::BTreeSet;
+
+use chrono::NaiveDate;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::accounts::{check_overdraft, OverdraftGuard};
+use crate::commands::budgets::{self, BUDGET_THRESHOLD_CROSSED};
+use crate::commands::receipts;
+use crate::db;
+use crate::error::AppError;
+use crate::journal::{self, MutationKind, RowChange};
+use crate::recurring::parse_date;
+
+/// Largest page [`list_transactions_page`] returns.
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    pub id: i64,
+    pub account_id: i64,
+    pub date: String,
+    pub description: String,
+    pub amount_minor: i64,
+    pub category_id: Option<i64>,
+    pub notes: Option<String>,
+    pub transfer_id: Option<i64>,
+    /// Set while the transaction sits in the trash.
+    pub deleted_at: Option<String>,
+    /// Whether the transaction has shown up on a bank statement.
+    pub cleared: bool,
+    pub cleared_date: Option<String>,
+}
+
+/// Column list matching [`Transaction::from_row`], for tables aliased `t`.
+pub const TRANSACTION_COLUMNS: &str =
+    "t.id, t.account_id, t.date, t.description, t.amount_minor, t.category_id, t.notes, t.transfer_id, \
+     t.deleted_at, t.cleared, t.cleared_date";
+
+impl Transaction {
+    pub fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            account_id: row.get(1)?,
+            date: row.get(2)?,
+            description: row.get(3)?,
+            amount_minor: row.get(4)?,
+            category_id: row.get(5)?,
+            notes: row.get(6)?,
+            transfer_id: row.get(7)?,
+            deleted_at: row.get(8)?,
+            cleared: row.get(9)?,
+            cleared_date: row.get(10)?,
+        })
+    }
+}
+
+/// Fields of a transaction entered by hand.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTransaction {
+    pub account_id: i64,
+    pub date: String,
+    #[serde(default)]
+    pub description: String,
+    pub amount_minor: i64,
+    #[serde(default)]
+    pub category_id: Option<i64>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// Inserts a transaction and returns its ID. Accounts that don't allow an
+/// overdraft reject expenses they can't cover. Can be undone.
+///
+/// Emits [`BUDGET_THRESHOLD_CROSSED`] when the expense takes its category
+/// to 90% or 100% of the month's limit; see [`budgets::threshold_crossed`].
+#[tauri::command(async)]
+pub fn create_transaction(app: AppHandle, transaction: NewTransaction) -> Result<i64, AppError> {
+    let (id, crossed) = db::with_conn(&app, |conn| {
+        // Checked first so nothing can fail once the insert is committed.
+        let crossed = match (transaction.category_id, parse_date(&transaction.date)) {
+            (Some(category_id), Ok(date)) => {
+                budgets::threshold_crossed(conn, category_id, date, transaction.amount_minor)?
+            }
+            _ => None,
+        };
+        Ok((insert_transaction(conn, &transaction)?, crossed))
+    })?;
+    if let Some(crossed) = crossed {
+        // The transaction is saved either way; a missed toast isn't worth failing over.
+        if let Err(e) = app.emit(BUDGET_THRESHOLD_CROSSED, crossed) {
+            eprintln!("failed to emit {BUDGET_THRESHOLD_CROSSED}: {e}");
+        }
+    }
+    Ok(id)
+}
+
+pub fn insert_transaction(conn: &mut Connection, new: &NewTransaction) -> Result<i64, AppError> {
+    NaiveDate::parse_from_str(&new.date, "%Y-%m-%d")
+        .map_err(|e| AppError::Validation(format!("invalid date {:?}: {e}", new.date)))?;
+    let tx = conn.transaction()?;
+    check_overdraft(&tx, new.account_id, &new.date, new.amount_minor)?;
+    tx.execute(
+        "INSERT INTO transactions (account_id, date, description, amount_minor, category_id, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            new.account_id,
+            new.date,
+            new.description,
+            new.amount_minor,
+            new.category_id,
+            new.notes
+        ],
+    )?;
+    let id = tx.last_insert_rowid();
+    let after = journal::snapshot(&tx, id)?;
+    journal::record(
+        &tx,
+        MutationKind::Create,
+        &[RowChange {
+            transaction_id: id,
+            before: None,
+            after,
+        }],
+    )?;
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Replaces the hand-entered fields of a live transaction. The account,
+/// amount and date of a transfer leg can't change, since the other leg must
+/// match, and neither can the amount of a split transaction until its
+/// splits are cleared. Accounts that don't allow an overdraft, the old one
+/// as well as the new one, refuse an edit that overdraws them. Can be
+/// undone.
+#[tauri::command(async)]
+pub fn update_transaction(app: AppHandle, id: i64, transaction: NewTransaction) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| edit_transaction(conn, id, &transaction))
+}
+
+pub fn edit_transaction(conn: &mut Connection, id: i64, new: &NewTransaction) -> Result<(), AppError> {
+    NaiveDate::parse_from_str(&new.date, "%Y-%m-%d")
+        .map_err(|e| AppError::Validation(format!("invalid date {:?}: {e}", new.date)))?;
+    let tx = conn.transaction()?;
+    let current: (i64, String, i64, Option<i64>, bool) = tx
+        .query_row(
+            "SELECT account_id, date, amount_minor, transfer_id,
+                    EXISTS (SELECT 1 FROM transaction_splits WHERE transaction_id = ?1)
+             FROM transactions
+             WHERE id = ?1 AND deleted_at IS NULL",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("transaction {id} not found")))?;
+    let (account_id, date, amount_minor, transfer_id, split) = current;
+    if transfer_id.is_some()
+        && (account_id != new.account_id || amount_minor != new.amount_minor || date != new.date)
+    {
+        return Err(AppError::Validation(format!(
+            "transaction {id} is part of a transfer; its account, amount and date can't be edited"
+        )));
+    }
+    if split && amount_minor != new.amount_minor {
+        return Err(AppError::Validation(format!(
+            "transaction {id} is split; clear its splits before changing the amount"
+        )));
+    }
+    let before = journal::snapshot(&tx, id)?;
+
+    // Both accounts are checked from the earlier date on: the old one
+    // loses the previous amount, the new one gains the new amount.
+    let guard =
+        OverdraftGuard::new(&tx, [account_id, new.account_id], date.as_str().min(&new.date))?;
+    tx.execute(
+        "UPDATE transactions SET
+            account_id = ?2, date = ?3, description = ?4, amount_minor = ?5,
+            category_id = ?6, notes = ?7
+         WHERE id = ?1",
+        params![
+            id,
+            new.account_id,
+            new.date,
+            new.description,
+            new.amount_minor,
+            new.category_id,
+            new.notes
+        ],
+    )?;
+    guard.check(&tx)?;
+
+    let after = journal::snapshot(&tx, id)?;
+    journal::record(
+        &tx,
+        MutationKind::Update,
+        &[RowChange {
+            transaction_id: id,
+            before,
+            after,
+        }],
+    )?;
+    tx.commit().map_err(AppError::from)
+}
+
+/// Fields [`bulk_update`] sets on every selected transaction; missing ones
+/// are left alone.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TransactionPatch {
+    pub category_id: Option<i64>,
+    pub account_id: Option<i64>,
+    pub cleared: Option<bool>,
+    pub notes: Option<String>,
+}
+
+/// Applies `changes` to every transaction in `ids` and returns how many
+/// were updated. Nothing changes unless every ID is a live transaction.
+#[tauri::command(async)]
+pub fn bulk_update(app: AppHandle, ids: Vec<i64>, changes: TransactionPatch) -> Result<usize, AppError> {
+    db::with_conn(&app, |conn| patch_transactions(conn, &ids, &changes))
+}
+
+pub fn patch_transactions(
+    conn: &mut Connection,
+    ids: &[i64],
+    changes: &TransactionPatch,
+) -> Result<usize, AppError> {
+    let ids: BTreeSet<i64> = ids.iter().copied().collect();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let mut sets = Vec::new();
+    let mut args: Vec<Value> = Vec::new();
+    if let Some(category_id) = changes.category_id {
+        sets.push("category_id = ?");
+        args.push(Value::Integer(category_id));
+    }
+    if let Some(account_id) = changes.account_id {
+        sets.push("account_id = ?");
+        args.push(Value::Integer(account_id));
+    }
+    if let Some(cleared) = changes.cleared {
+        // Rows that were already cleared keep their original date.
+        sets.push(
+            "cleared_date = CASE WHEN ? THEN COALESCE(CASE WHEN cleared THEN cleared_date END, date('now')) END, \
+             cleared = ?",
+        );
+        args.extend([Value::Integer(cleared.into()), Value::Integer(cleared.into())]);
+    }
+    if let Some(notes) = &changes.notes {
+        sets.push("notes = ?");
+        args.push(Value::Text(notes.clone()));
+    }
+    if sets.is_empty() {
+        return Err(AppError::Validation("no changes given".into()));
+    }
+
+    let tx = conn.transaction()?;
+    let marks = vec!["?"; ids.len()].join(", ");
+    let found: BTreeSet<i64> = tx
+        .prepare(&format!(
+            "SELECT id FROM transactions WHERE deleted_at IS NULL AND id IN ({marks})"
+        ))
+        .and_then(|mut stmt| {
+            stmt.query_map(params_from_iter(&ids), |row| row.get(0))?
+                .collect::<Result<_, _>>()
+        })?;
+    let missing: Vec<String> = ids.difference(&found).map(i64::to_string).collect();
+    if !missing.is_empty() {
+        return Err(AppError::NotFound(format!("transactions not found: {}", missing.join(", "))));
+    }
+    if let Some(account_id) = changes.account_id {
+        let exists: bool = tx
+            .query_row("SELECT EXISTS (SELECT 1 FROM accounts WHERE id = ?1)", [account_id], |row| {
+                row.get(0)
+            })?;
+        if !exists {
+            return Err(AppError::NotFound(format!("account {account_id} not found")));
+        }
+        // Both legs of a transfer must stay on their own accounts.
+        let in_transfer: bool = tx
+            .query_row(
+                &format!(
+                    "SELECT EXISTS (SELECT 1 FROM transactions
+                     WHERE transfer_id IS NOT NULL AND id IN ({marks}))"
+                ),
+                params_from_iter(&ids),
+                |row| row.get(0),
+            )?;
+        if in_transfer {
+            return Err(AppError::Validation("the account of a transfer can't be changed".into()));
+        }
+    }
+    if let Some(category_id) = changes.category_id {
+        let exists: bool = tx
+            .query_row("SELECT EXISTS (SELECT 1 FROM categories WHERE id = ?1)", [category_id], |row| {
+                row.get(0)
+            })?;
+        if !exists {
+            return Err(AppError::NotFound(format!("category {category_id} not found")));
+        }
+    }
+
+    args.extend(ids.iter().map(|&id| Value::Integer(id)));
+    let updated = tx
+        .execute(
+            &format!("UPDATE transactions SET {} WHERE id IN ({marks})", sets.join(", ")),
+            params_from_iter(args),
+        )?;
+    tx.commit()?;
+    Ok(updated)
+}
+
+/// Filters for [`search_transactions`]. Every field is optional and an
+/// empty or missing one doesn't constrain the results.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SearchQuery {
+    /// Substring of the description. `%` and `_` match themselves. Case is
+    /// ignored for ASCII letters only, as SQLite's `LOWER` does, so "café"
+    /// finds "CAFé" but not "CAFÉ".
+    pub text: Option<String>,
+    /// Inclusive ISO dates.
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    /// Inclusive bounds on the signed amount in minor units.
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    pub category_ids: Vec<i64>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[tauri::command(async)]
+pub fn search_transactions(app: AppHandle, query: SearchQuery) -> Result<Vec<Transaction>, AppError> {
+    db::with_conn(&app, |conn| search(conn, &query))
+}
+
+/// Runs `query` newest first. The SQL is assembled from fixed fragments and
+/// every user value goes through a bound parameter.
+pub fn search(conn: &Connection, query: &SearchQuery) -> Result<Vec<Transaction>, AppError> {
+    let mut sql = format!("SELECT {TRANSACTION_COLUMNS} FROM transactions t WHERE t.deleted_at IS NULL");
+    let mut args: Vec<Value> = Vec::new();
+
+    if let Some(text) = query.text.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        sql.push_str(" AND LOWER(t.description) LIKE ? ESCAPE '\\'");
+        args.push(Value::Text(format!("%{}%", escape_like(&text.to_ascii_lowercase()))));
+    }
+    if let Some(from) = query.date_from.as_deref().filter(|s| !s.is_empty()) {
+        sql.push_str(" AND t.date >= ?");
+        args.push(Value::Text(from.to_string()));
+    }
+    if let Some(to) = query.date_to.as_deref().filter(|s| !s.is_empty()) {
+        sql.push_str(" AND t.date <= ?");
+        args.push(Value::Text(to.to_string()));
+    }
+    if let Some(min) = query.min_amount {
+        sql.push_str(" AND t.amount_minor >= ?");
+        args.push(Value::Integer(min));
+    }
+    if let Some(max) = query.max_amount {
+        sql.push_str(" AND t.amount_minor <= ?");
+        args.push(Value::Integer(max));
+    }
+    if !query.category_ids.is_empty() {
+        // A split transaction matches if any of its splits does.
+        let marks = vec!["?"; query.category_ids.len()].join(", ");
+        sql.push_str(&format!(
+            " AND (t.category_id IN ({marks}) OR EXISTS (SELECT 1 FROM transaction_splits s
+                WHERE s.transaction_id = t.id AND s.category_id IN ({marks})))"
+        ));
+        for _ in 0..2 {
+            args.extend(query.category_ids.iter().map(|&id| Value::Integer(id)));
+        }
+    }
+
+    sql.push_str(" ORDER BY t.date DESC, t.id DESC LIMIT ? OFFSET ?");
+    args.push(Value::Integer(query.limit.map_or(-1, i64::from)));
+    args.push(Value::Integer(query.offset.map_or(0, i64::from)));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params_from_iter(args), Transaction::from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Notes of live transactions that start with `prefix`, for autocompleting
+/// the notes field. Each distinct note appears once with its original
+/// casing, most used first and then most recently dated. Case is ignored
+/// for ASCII letters only, as SQLite's `LOWER` does, and `%` and `_` in the
+/// prefix match themselves.
+#[tauri::command(async)]
+pub fn note_suggestions(app: AppHandle, prefix: String, limit: u32) -> Result<Vec<String>, AppError> {
+    db::with_conn(&app, |conn| notes_starting_with(conn, &prefix, limit))
+}
+
+pub fn notes_starting_with(
+    conn: &Connection,
+    prefix: &str,
+    limit: u32,
+) -> Result<Vec<String>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT notes FROM transactions
+         WHERE deleted_at IS NULL AND TRIM(notes) != ''
+           AND LOWER(notes) LIKE ?1 ESCAPE '\\'
+         GROUP BY notes
+         ORDER BY COUNT(*) DESC, MAX(date) DESC, MAX(id) DESC
+         LIMIT ?2",
+    )?;
+    let notes = stmt
+        .query_map(
+            params![format!("{}%", escape_like(&prefix.to_ascii_lowercase())), limit],
+            |row| row.get(0),
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(notes)
+}
+
+/// Where a page of [`list_transactions_page`] ended. The frontend treats it
+/// as opaque and passes it back unchanged to get the following page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    date: String,
+    id: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    /// Pass to the next call; `None` on the last page.
+    pub next_cursor: Option<Cursor>,
+    pub has_more: bool,
+}
+
+/// Live transactions newest first, `limit` at a time, starting after
+/// `cursor` or from the newest when it is `None`. Pages are keyed on
+/// `(date, id)` rather than an offset, so a deep page costs the same as the
+/// first and rows added meanwhile don't shift later pages.
+#[tauri::command(async)]
+pub fn list_transactions_page(
+    app: AppHandle,
+    cursor: Option<Cursor>,
+    limit: u32,
+) -> Result<TransactionPage, AppError> {
+    db::with_conn(&app, |conn| transactions_page(conn, cursor.as_ref(), limit))
+}
+
+pub fn transactions_page(
+    conn: &Connection,
+    cursor: Option<&Cursor>,
+    limit: u32,
+) -> Result<TransactionPage, AppError> {
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::Validation(format!("limit must be between 1 and {MAX_PAGE_SIZE}")));
+    }
+    // One extra row tells whether another page follows.
+    let fetch = i64::from(limit) + 1;
+    // Separate statements rather than an `?1 IS NULL OR ...` filter, which
+    // would keep SQLite from seeking to the cursor in idx_transactions_date.
+    let mut transactions = match cursor {
+        None => conn
+            .prepare(&format!(
+                "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+                 WHERE t.deleted_at IS NULL
+                 ORDER BY t.date DESC, t.id DESC
+                 LIMIT ?1"
+            ))?
+            .query_map([fetch], Transaction::from_row)?
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(cursor) => conn
+            .prepare(&format!(
+                "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+                 WHERE t.deleted_at IS NULL AND (t.date, t.id) < (?1, ?2)
+                 ORDER BY t.date DESC, t.id DESC
+                 LIMIT ?3"
+            ))?
+            .query_map(params![cursor.date, cursor.id, fetch], Transaction::from_row)?
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let has_more = transactions.len() > limit as usize;
+    transactions.truncate(limit as usize);
+    let next_cursor = has_more
+        .then(|| transactions.last())
+        .flatten()
+        .map(|t| Cursor { date: t.date.clone(), id: t.id });
+    Ok(TransactionPage { transactions, next_cursor, has_more })
+}
+
+/// Moves a transaction to the trash. Both legs of a transfer go together.
+/// Can be undone.
+#[tauri::command(async)]
+pub fn delete_transaction(app: AppHandle, id: i64) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| trash_transaction(conn, id))
+}
+
+pub fn trash_transaction(conn: &mut Connection, id: i64) -> Result<(), AppError> {
+    let tx = conn.transaction()?;
+    let ids = tx
+        .prepare(
+            "SELECT id FROM transactions
+             WHERE deleted_at IS NULL
+               AND (id = ?1 OR transfer_id = (SELECT transfer_id FROM transactions WHERE id = ?1))
+             ORDER BY id",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([id], |row| row.get::<_, i64>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+    if ids.is_empty() {
+        return Err(AppError::NotFound(format!("transaction {id} not found")));
+    }
+    let mut changes = Vec::with_capacity(ids.len());
+    for &row_id in &ids {
+        let before = journal::snapshot(&tx, row_id)?;
+        tx.execute(
+            "UPDATE transactions SET deleted_at = datetime('now') WHERE id = ?1",
+            [row_id],
+        )?;
+        changes.push(RowChange {
+            transaction_id: row_id,
+            before,
+            after: journal::snapshot(&tx, row_id)?,
+        });
+    }
+    journal::record(&tx, MutationKind::Delete, &changes)?;
+    tx.commit().map_err(AppError::from)
+}
+
+/// Trashed transactions, most recently deleted first.
+#[tauri::command(async)]
+pub fn list_trash(app: AppHandle) -> Result<Vec<Transaction>, AppError> {
+    db::with_conn(&app, |conn| trash(conn))
+}
+
+pub fn trash(conn: &Connection) -> Result<Vec<Transaction>, AppError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TRANSACTION_COLUMNS} FROM transactions t
+         WHERE t.deleted_at IS NOT NULL
+         ORDER BY t.deleted_at DESC, t.id DESC"
+    ))?;
+    let rows = stmt.query_map([], Transaction::from_row)?.collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Takes a transaction (and its transfer partner) back out of the trash.
+#[tauri::command(async)]
+pub fn restore_transaction(app: AppHandle, id: i64) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| restore(conn, id))
+}
+
+pub fn restore(conn: &Connection, id: i64) -> Result<(), AppError> {
+    let changed = conn.execute(
+        "UPDATE transactions SET deleted_at = NULL
+         WHERE deleted_at IS NOT NULL
+           AND (id = ?1 OR transfer_id = (SELECT transfer_id FROM transactions WHERE id = ?1))",
+        [id],
+    )?;
+    if changed == 0 {
+        return Err(AppError::Validation(format!("transaction {id} is not in the trash")));
+    }
+    Ok(())
+}
+
+/// Permanently removes transactions trashed more than `older_than_days`
+/// days ago, along with their receipts, and returns how many were removed.
+#[tauri::command(async)]
+pub fn purge_trash(app: AppHandle, older_than_days: u32) -> Result<usize, AppError> {
+    db::with_conn(&app, |conn| {
+        let (purged, unused_files) = purge(conn, older_than_days)?;
+        receipts::remove_files(&app, &unused_files);
+        Ok(purged)
+    })
+}
+
+/// Deletes the rows [`purge_trash`] removes and returns how many, along
+/// with the receipt files nothing refers to any more.
+pub fn purge(
+    conn: &mut Connection,
+    older_than_days: u32,
+) -> Result<(usize, Vec<String>), AppError> {
+    let tx = conn.transaction()?;
+    let cutoff = format!("-{older_than_days} days");
+    let ids = tx
+        .prepare(
+            "SELECT id FROM transactions
+             WHERE deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1)",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([&cutoff], |row| row.get::<_, i64>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+    let unused_files = receipts::delete_receipt_rows(&tx, &ids)?;
+    tx.execute(
+        "DELETE FROM transaction_splits WHERE transaction_id IN (
+            SELECT id FROM transactions
+            WHERE deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1))",
+        [&cutoff],
+    )?;
+    tx.execute(
+        "DELETE FROM transaction_tags WHERE transaction_id IN (
+            SELECT id FROM transactions
+            WHERE deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1))",
+        [&cutoff],
+    )?;
+    tx.execute(
+        "UPDATE transactions SET refund_of_id = NULL WHERE refund_of_id IN (
+            SELECT id FROM transactions
+            WHERE deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1))",
+        [&cutoff],
+    )?;
+    let purged = tx.execute(
+        "DELETE FROM transactions
+         WHERE deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1)",
+        [&cutoff],
+    )?;
+    tx.commit()?;
+    Ok((purged, unused_files))
+}
+
+/// One category's share of a split transaction.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Split {
+    pub category_id: i64,
+    pub amount_minor: i64,
+}
+
+/// Replaces a transaction's splits. The amounts must add up exactly to the
+/// transaction's amount and every category must exist; an empty list
+/// removes the splits so the transaction counts under its own category
+/// again. Transfer legs can't be split.
+#[tauri::command(async)]
+pub fn set_transaction_splits(
+    app: AppHandle,
+    transaction_id: i64,
+    splits: Vec<Split>,
+) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| replace_splits(conn, transaction_id, &splits))
+}
+
+pub fn replace_splits(
+    conn: &mut Connection,
+    transaction_id: i64,
+    splits: &[Split],
+) -> Result<(), AppError> {
+    let tx = conn.transaction()?;
+    let (amount, is_transfer): (i64, bool) = tx
+        .query_row(
+            "SELECT amount_minor, transfer_id IS NOT NULL FROM transactions
+             WHERE id = ?1 AND deleted_at IS NULL",
+            [transaction_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("transaction {transaction_id} not found")))?;
+    if is_transfer {
+        return Err(AppError::Validation("a transfer cannot be split".into()));
+    }
+
+    if !splits.is_empty() {
+        let total = splits
+            .iter()
+            .try_fold(0i64, |total, s| total.checked_add(s.amount_minor))
+            .ok_or_else(|| AppError::Validation("splits total is out of range".into()))?;
+        if total != amount {
+            return Err(AppError::Validation(format!(
+                "splits total {total} but the transaction is {amount} (off by {})",
+                amount - total
+            )));
+        }
+    }
+    for category_id in splits.iter().map(|s| s.category_id).collect::<BTreeSet<_>>() {
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM categories WHERE id = ?1)",
+            [category_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(AppError::NotFound(format!("category {category_id} not found")));
+        }
+    }
+
+    tx.execute("DELETE FROM transaction_splits WHERE transaction_id = ?1", [transaction_id])?;
+    for split in splits {
+        tx.execute(
+            "INSERT INTO transaction_splits (transaction_id, category_id, amount_minor)
+             VALUES (?1, ?2, ?3)",
+            params![transaction_id, split.category_id, split.amount_minor],
+        )?;
+    }
+    tx.commit().map_err(AppError::from)
+}
+
+/// Escapes LIKE wildcards so the search term matches literally.
+fn escape_like(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn expense(date: &str, amount_minor: i64) -> NewTransaction {
+        NewTransaction {
+            account_id: 1,
+            date: date.to_string(),
+            description: "Groceries".to_string(),
+            amount_minor,
+            category_id: None,
+            notes: None,
+        }
+    }
+
+    /// Two accounts that can't be overdrawn, Checking holding an income of
+    /// 10.00 on 1 March and an expense of 8.00 on 5 March.
+    fn guarded() -> (Connection, i64, i64) {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name, allow_overdraft) VALUES ('Checking', 0), ('Savings', 0)",
+        )
+        .unwrap();
+        let income = insert_transaction(&mut conn, &expense("2024-03-01", 1000)).unwrap();
+        let spent = insert_transaction(&mut conn, &expense("2024-03-05", -800)).unwrap();
+        (conn, income, spent)
+    }
+
+    #[test]
+    fn lowering_an_income_cannot_overdraw() {
+        let (mut conn, income, _) = guarded();
+        let err = edit_transaction(&mut conn, income, &expense("2024-03-01", 500)).unwrap_err();
+        assert!(matches!(err, AppError::InsufficientFunds(_)));
+        edit_transaction(&mut conn, income, &expense("2024-03-01", 800)).unwrap();
+    }
+
+    #[test]
+    fn moving_an_income_away_cannot_overdraw_the_old_account() {
+        let (mut conn, income, _) = guarded();
+        let moved = NewTransaction { account_id: 2, ..expense("2024-03-01", 1000) };
+        let err = edit_transaction(&mut conn, income, &moved).unwrap_err();
+        assert!(matches!(err, AppError::InsufficientFunds(_)));
+    }
+
+    #[test]
+    fn moving_an_expense_cannot_overdraw_the_new_account_or_date() {
+        let (mut conn, _, spent) = guarded();
+        let moved = NewTransaction { account_id: 2, ..expense("2024-03-05", -800) };
+        let err = edit_transaction(&mut conn, spent, &moved).unwrap_err();
+        assert!(matches!(err, AppError::InsufficientFunds(_)));
+        let err = edit_transaction(&mut conn, spent, &expense("2024-02-28", -800)).unwrap_err();
+        assert!(matches!(err, AppError::InsufficientFunds(_)));
+    }
+
+    #[test]
+    fn an_edit_that_leaves_an_overdraft_no_worse_is_allowed() {
+        let (mut conn, _, spent) = guarded();
+        // Overdrawn before the account stopped allowing it.
+        conn.execute(
+            "INSERT INTO transactions (account_id, date, amount_minor)
+             VALUES (1, '2024-03-06', -500)",
+            [],
+        )
+        .unwrap();
+        let renamed = NewTransaction {
+            notes: Some("weekly shop".into()),
+            ..expense("2024-03-05", -800)
+        };
+        edit_transaction(&mut conn, spent, &renamed).unwrap();
+        edit_transaction(&mut conn, spent, &expense("2024-03-05", -700)).unwrap();
+        let err = edit_transaction(&mut conn, spent, &expense("2024-03-05", -900)).unwrap_err();
+        assert!(matches!(err, AppError::InsufficientFunds(_)));
+    }
+
+    #[test]
+    fn a_split_amount_needs_the_splits_cleared_first() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (name) VALUES ('Food'), ('Home');",
+        )
+        .unwrap();
+        let id = insert_transaction(&mut conn, &expense("2024-03-01", -900)).unwrap();
+        let splits = [
+            Split { category_id: 1, amount_minor: -600 },
+            Split { category_id: 2, amount_minor: -300 },
+        ];
+        replace_splits(&mut conn, id, &splits).unwrap();
+
+        let err = edit_transaction(&mut conn, id, &expense("2024-03-01", -1000)).unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)));
+        edit_transaction(&mut conn, id, &expense("2024-03-02", -900)).unwrap();
+        replace_splits(&mut conn, id, &[]).unwrap();
+        edit_transaction(&mut conn, id, &expense("2024-03-02", -1000)).unwrap();
+    }
+
+    #[test]
+    fn splits_must_add_up_to_known_categories() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (name) VALUES ('Food'), ('Home');",
+        )
+        .unwrap();
+        let id = insert_transaction(&mut conn, &expense("2024-03-01", -900)).unwrap();
+        let split = |category_id, amount_minor| Split { category_id, amount_minor };
+
+        let short = replace_splits(&mut conn, id, &[split(1, -600), split(2, -200)]);
+        assert!(matches!(short, Err(AppError::Validation(_))));
+        let overflow = replace_splits(&mut conn, id, &[split(1, i64::MIN), split(2, -1)]);
+        assert!(matches!(overflow, Err(AppError::Validation(_))));
+        let unknown = replace_splits(&mut conn, id, &[split(1, -600), split(7, -300)]);
+        assert!(matches!(unknown, Err(AppError::NotFound(_))));
+        let stored: i64 = conn
+            .query_row("SELECT COUNT(*) FROM transaction_splits", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, 0);
+
+        conn.execute("INSERT INTO accounts (name) VALUES ('Savings')", []).unwrap();
+        let leg = crate::commands::transfers::insert_transfer(&mut conn, 1, 2, 900, "2024-03-01")
+            .unwrap();
+        let transfer = replace_splits(&mut conn, leg, &[split(1, -900)]);
+        assert!(matches!(transfer, Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn a_transfer_leg_keeps_its_date() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch("INSERT INTO accounts (name) VALUES ('Checking'), ('Savings')").unwrap();
+        crate::commands::transfers::insert_transfer(&mut conn, 1, 2, 500, "2024-03-01").unwrap();
+        let (id, amount_minor): (i64, i64) = conn
+            .query_row(
+                "SELECT id, amount_minor FROM transactions WHERE account_id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+
+        let moved = expense("2024-03-05", amount_minor);
+        assert!(matches!(edit_transaction(&mut conn, id, &moved), Err(AppError::Validation(_))));
+        edit_transaction(&mut conn, id, &expense("2024-03-01", amount_minor)).unwrap();
+    }
+
+    #[test]
+    fn search_ignores_ascii_case_and_matches_wildcards_literally() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (account_id, date, description, amount_minor) VALUES
+                 (1, '2024-01-01', 'CAFé Central', -450), (1, '2024-01-02', '100% Juice', -300),
+                 (1, '2024-01-03', '1000 Juices', -200), (1, '2024-01-04', 'cafe_bar', -100);",
+        )
+        .unwrap();
+        let descriptions = |text: &str| {
+            let query = SearchQuery { text: Some(text.to_string()), ..SearchQuery::default() };
+            search(&conn, &query)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.description)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(descriptions("café"), ["CAFé Central"]);
+        assert_eq!(descriptions("0% j"), ["100% Juice"]);
+        assert_eq!(descriptions("E_B"), ["cafe_bar"]);
+    }
+
+    #[test]
+    fn note_suggestions_keep_their_casing_and_rank_by_use() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (account_id, date, amount_minor, notes, deleted_at) VALUES
+                 (1, '2024-01-01', -1, 'Gift for Ann', NULL),
+                 (1, '2024-01-05', -1, 'gift card', NULL),
+                 (1, '2024-01-06', -1, 'gift card', NULL),
+                 (1, '2024-01-07', -1, 'Gift wrap', '2024-01-08'),
+                 (1, '2024-01-08', -1, 'Gifts_2024', NULL),
+                 (1, '2024-01-09', -1, '   ', NULL);",
+        )
+        .unwrap();
+        assert_eq!(
+            notes_starting_with(&conn, "GIFT", 10).unwrap(),
+            ["gift card", "Gifts_2024", "Gift for Ann"]
+        );
+        assert_eq!(notes_starting_with(&conn, "gifts_", 10).unwrap(), ["Gifts_2024"]);
+        assert_eq!(notes_starting_with(&conn, "gift_", 10).unwrap(), Vec::<String>::new());
+        assert_eq!(notes_starting_with(&conn, "gift", 1).unwrap(), ["gift card"]);
+    }
+
+    #[test]
+    fn pages_follow_the_cursor_newest_first() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO transactions (account_id, date, description, amount_minor) VALUES
+                 (1, '2024-01-02', 'a', -1), (1, '2024-01-01', 'b', -1),
+                 (1, '2024-01-02', 'c', -1), (1, '2024-01-03', 'd', -1);",
+        )
+        .unwrap();
+        let ids =
+            |page: &TransactionPage| page.transactions.iter().map(|t| t.id).collect::<Vec<_>>();
+
+        let first = transactions_page(&conn, None, 3).unwrap();
+        assert_eq!(ids(&first), [4, 3, 1]);
+        assert!(first.has_more);
+        let second = transactions_page(&conn, first.next_cursor.as_ref(), 3).unwrap();
+        assert_eq!(ids(&second), [2]);
+        assert!(!second.has_more);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[test]
+    fn trashed_rows_can_be_restored_until_purged() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO accounts (name) VALUES ('Checking')", []).unwrap();
+        let old = insert_transaction(&mut conn, &expense("2024-03-01", -100)).unwrap();
+        let recent = insert_transaction(&mut conn, &expense("2024-03-02", -200)).unwrap();
+        trash_transaction(&mut conn, old).unwrap();
+        trash_transaction(&mut conn, recent).unwrap();
+        conn.execute(
+            "UPDATE transactions SET deleted_at = datetime('now', '-40 days') WHERE id = ?1",
+            [old],
+        )
+        .unwrap();
+
+        let trashed: Vec<i64> = trash(&conn).unwrap().iter().map(|t| t.id).collect();
+        assert_eq!(trashed, [recent, old]);
+        assert!(matches!(trash_transaction(&mut conn, old), Err(AppError::NotFound(_))));
+
+        assert_eq!(purge(&mut conn, 30).unwrap().0, 1);
+        assert!(matches!(restore(&conn, old), Err(AppError::Validation(_))));
+        restore(&conn, recent).unwrap();
+        assert!(trash(&conn).unwrap().is_empty());
+        let live: i64 = conn
+            .query_row("SELECT COUNT(*) FROM transactions WHERE deleted_at IS NULL", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(live, 1);
+    }
+
+    #[test]
+    fn an_expense_cannot_overdraw_where_it_lands_in_the_ledger() {
+        let (mut conn, _, _) = guarded();
+        insert_transaction(&mut conn, &expense("2024-03-20", 5000)).unwrap();
+
+        // 200 is left between the 5th and the 20th, whatever the end balance.
+        let err = insert_transaction(&mut conn, &expense("2024-03-10", -500)).unwrap_err();
+        assert!(matches!(&err, AppError::InsufficientFunds(m) if m.contains("overdrawn by 300")));
+        let err = insert_transaction(&mut conn, &expense("2024-03-02", -300)).unwrap_err();
+        assert!(matches!(&err, AppError::InsufficientFunds(m) if m.contains("overdrawn by 100")));
+        insert_transaction(&mut conn, &expense("2024-03-10", -200)).unwrap();
+        insert_transaction(&mut conn, &expense("2024-03-20", -5000)).unwrap();
+
+        conn.execute("UPDATE accounts SET allow_overdraft = 1 WHERE id = 1", []).unwrap();
+        insert_transaction(&mut conn, &expense("2024-03-02", -300)).unwrap();
+    }
+
+    #[test]
+    fn a_bulk_update_sets_only_the_given_fields_or_nothing() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO categories (name) VALUES ('Food');",
+        )
+        .unwrap();
+        let first = insert_transaction(&mut conn, &expense("2024-03-01", -100)).unwrap();
+        let second = insert_transaction(&mut conn, &expense("2024-03-02", -200)).unwrap();
+        conn.execute(
+            "UPDATE transactions SET notes = 'keep', cleared = 1, cleared_date = '2024-03-03'
+             WHERE id = ?1",
+            [first],
+        )
+        .unwrap();
+        let patch = TransactionPatch {
+            category_id: Some(1),
+            cleared: Some(true),
+            ..Default::default()
+        };
+
+        let err = patch_transactions(&mut conn, &[first, 98, second, 99], &patch).unwrap_err();
+        assert!(matches!(&err, AppError::NotFound(m) if m.ends_with("98, 99")));
+        let untouched: i64 = conn
+            .query_row("SELECT COUNT(*) FROM transactions WHERE category_id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(untouched, 0);
+
+        assert_eq!(patch_transactions(&mut conn, &[first, second, first], &patch).unwrap(), 2);
+        let (notes, cleared_date): (Option<String>, String) = conn
+            .query_row(
+                "SELECT notes, cleared_date FROM transactions WHERE id = ?1",
+                [first],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((notes.as_deref(), cleared_date.as_str()), (Some("keep"), "2024-03-03"));
+        let empty = patch_transactions(&mut conn, &[first], &TransactionPatch::default());
+        assert!(matches!(empty, Err(AppError::Validation(_))));
+    }
+}
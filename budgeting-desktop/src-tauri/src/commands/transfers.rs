@@ -0,0 +1,196 @@
+//! Moves between the family's own accounts.
+//!
+//! A transfer is a debit/credit pair of transactions sharing a
+//! `transfer_id`, which is the ID of the debit leg. Reports skip any row
+//! with a `transfer_id` so the move counts as neither income nor expense.
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::AppHandle;
+
+use crate::commands::accounts::check_overdraft;
+use crate::db;
+use crate::error::AppError;
+use crate::journal::{self, MutationKind, RowChange};
+
+#[tauri::command(async)]
+pub fn create_transfer(
+    app: AppHandle,
+    from_account: i64,
+    to_account: i64,
+    amount_minor: i64,
+    date: String,
+) -> Result<i64, AppError> {
+    db::with_conn(&app, |conn| insert_transfer(conn, from_account, to_account, amount_minor, &date))
+}
+
+/// Moves both legs of a transfer to the trash.
+#[tauri::command(async)]
+pub fn delete_transfer(app: AppHandle, transfer_id: i64) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| trash_transfer(conn, transfer_id))
+}
+
+/// Trashes both legs as one journal entry, so a single undo brings the
+/// whole transfer back.
+pub fn trash_transfer(conn: &mut Connection, transfer_id: i64) -> Result<(), AppError> {
+    let tx = conn.transaction()?;
+    let ids = tx
+        .prepare(
+            "SELECT id FROM transactions
+             WHERE transfer_id = ?1 AND deleted_at IS NULL
+             ORDER BY id",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([transfer_id], |row| row.get::<_, i64>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+    if ids.is_empty() {
+        return Err(AppError::NotFound(format!("transfer {transfer_id} not found")));
+    }
+    let mut changes = Vec::with_capacity(ids.len());
+    for &row_id in &ids {
+        let before = journal::snapshot(&tx, row_id)?;
+        tx.execute(
+            "UPDATE transactions SET deleted_at = datetime('now') WHERE id = ?1",
+            [row_id],
+        )?;
+        changes.push(RowChange {
+            transaction_id: row_id,
+            before,
+            after: journal::snapshot(&tx, row_id)?,
+        });
+    }
+    journal::record(&tx, MutationKind::Delete, &changes)?;
+    tx.commit().map_err(AppError::from)
+}
+
+/// Writes both legs atomically and returns the transfer ID. Both legs book
+/// the same minor amount, so the two accounts must share a currency.
+pub fn insert_transfer(
+    conn: &mut Connection,
+    from_account: i64,
+    to_account: i64,
+    amount_minor: i64,
+    date: &str,
+) -> Result<i64, AppError> {
+    if from_account == to_account {
+        return Err(AppError::Validation("cannot transfer from an account to itself".into()));
+    }
+    if amount_minor <= 0 {
+        return Err(AppError::Validation("transfer amount must be positive".into()));
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| AppError::Validation(format!("invalid date {date:?}: {e}")))?;
+
+    let tx = conn.transaction()?;
+    let mut currencies = Vec::with_capacity(2);
+    for account in [from_account, to_account] {
+        let currency: Option<String> = tx
+            .query_row("SELECT currency FROM accounts WHERE id = ?1", [account], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        match currency {
+            Some(currency) => currencies.push(currency),
+            None => return Err(AppError::NotFound(format!("account {account} not found"))),
+        }
+    }
+    if currencies[0] != currencies[1] {
+        return Err(AppError::Validation(format!(
+            "cannot transfer between {} and {} accounts",
+            currencies[0], currencies[1]
+        )));
+    }
+    check_overdraft(&tx, from_account, date, -amount_minor)?;
+
+    tx.execute(
+        "INSERT INTO transactions (account_id, date, description, amount_minor)
+         VALUES (?1, ?2, 'Transfer', ?3)",
+        params![from_account, date, -amount_minor],
+    )?;
+    let transfer_id = tx.last_insert_rowid();
+    tx.execute(
+        "UPDATE transactions SET transfer_id = ?1 WHERE id = ?1",
+        [transfer_id],
+    )?;
+    tx.execute(
+        "INSERT INTO transactions (account_id, date, description, amount_minor, transfer_id)
+         VALUES (?1, ?2, 'Transfer', ?3, ?4)",
+        params![to_account, date, amount_minor, transfer_id],
+    )?;
+    tx.commit()?;
+    Ok(transfer_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO accounts (name) VALUES ('Savings');
+             INSERT INTO accounts (name, currency) VALUES ('Euro savings', 'EUR');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn live_legs(conn: &Connection, transfer_id: i64) -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM transactions WHERE transfer_id = ?1 AND deleted_at IS NULL",
+            [transfer_id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn a_transfer_books_both_legs() {
+        let mut conn = db();
+        let id = insert_transfer(&mut conn, 1, 2, 2500, "2024-03-01").unwrap();
+        let amounts: Vec<(i64, i64)> = conn
+            .prepare("SELECT account_id, amount_minor FROM transactions WHERE transfer_id = ?1")
+            .unwrap()
+            .query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(amounts, [(1, -2500), (2, 2500)]);
+    }
+
+    #[test]
+    fn a_transfer_needs_two_accounts_in_one_currency() {
+        let mut conn = db();
+        assert!(matches!(
+            insert_transfer(&mut conn, 1, 1, 100, "2024-03-01"),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            insert_transfer(&mut conn, 1, 3, 100, "2024-03-01"),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            insert_transfer(&mut conn, 1, 9, 100, "2024-03-01"),
+            Err(AppError::NotFound(_))
+        ));
+        let rows: i64 =
+            conn.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[test]
+    fn deleting_a_transfer_is_one_undo() {
+        let mut conn = db();
+        let id = insert_transfer(&mut conn, 1, 2, 2500, "2024-03-01").unwrap();
+        trash_transfer(&mut conn, id).unwrap();
+        assert_eq!(live_legs(&conn, id), 0);
+        assert!(matches!(trash_transfer(&mut conn, id), Err(AppError::NotFound(_))));
+
+        journal::undo(&mut conn).unwrap();
+        assert_eq!(live_legs(&conn, id), 2);
+    }
+}
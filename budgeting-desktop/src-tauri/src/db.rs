@@ -0,0 +1,186 @@
+//! Rust-side connection to the budget database.
+//!
+//! Every command, the frontend's store included (see [`crate::commands::sql`]),
+//! shares one rusqlite connection, reached through [`with_conn`]. Commands
+//! take turns on it, so the startup recurring-transaction run and a command
+//! can't lock each other out, and a command another process keeps waiting
+//! is retried.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use rusqlite::{Connection, ErrorCode};
+use tauri::{AppHandle, Manager};
+
+use crate::error::AppError;
+use crate::profiles::{self, ActiveProfile};
+
+/// How long a statement waits for another connection's lock before failing
+/// with [`AppError::Busy`].
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Further attempts [`with_conn`] makes after [`AppError::Busy`], waiting
+/// twice as long before each one.
+const BUSY_RETRIES: u32 = 3;
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// SQLCipher passphrase for this session. Never written to disk.
+#[derive(Default)]
+pub struct DbKey(pub Mutex<Option<String>>);
+
+/// The connection shared by every command, opened on first use.
+#[derive(Default)]
+pub struct SharedConnection(pub Mutex<Option<Connection>>);
+
+/// Directory holding every profile's database and the app-wide files.
+pub fn data_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let dir = app.path().app_data_dir()?;
+    Ok(dir.join("budgeting"))
+}
+
+/// Location of the active profile's database file.
+pub fn db_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    let profile = *app.state::<ActiveProfile>().0.lock().unwrap();
+    Ok(data_dir(app)?.join(profiles::db_file_name(profile)))
+}
+
+/// Runs `f` on the shared connection, opening it if needed, and runs it
+/// again if it fails with [`AppError::Busy`]. A failed attempt's
+/// transaction has rolled back by then, so `f` starts over cleanly.
+///
+/// Never call this from inside `f`: the connection is held for the whole
+/// call, and the second call would wait for it forever. Waits can last
+/// seconds, so commands are declared `#[tauri::command(async)]` to keep
+/// them off the main thread.
+pub fn with_conn<T>(
+    app: &AppHandle,
+    f: impl FnMut(&mut Connection) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let state = app.state::<SharedConnection>();
+    // A command that panicked can't have left a transaction open, since
+    // dropping it rolled back, so the connection is still usable.
+    let mut shared = state.0.lock().unwrap_or_else(PoisonError::into_inner);
+    let conn = match &mut *shared {
+        Some(conn) => conn,
+        empty => empty.insert(open(app)?),
+    };
+    retry_busy(conn, f)
+}
+
+/// Calls `f` up to [`BUSY_RETRIES`] more times while it fails with
+/// [`AppError::Busy`], backing off between attempts.
+fn retry_busy<T>(
+    conn: &mut Connection,
+    mut f: impl FnMut(&mut Connection) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let mut delay = FIRST_RETRY_DELAY;
+    for _ in 0..BUSY_RETRIES {
+        match f(conn) {
+            Err(AppError::Busy(_)) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    f(conn)
+}
+
+/// Closes the shared connection so the next command opens a fresh one, for
+/// when the active profile or the passphrase changes.
+pub fn reset(app: &AppHandle) {
+    let state = app.state::<SharedConnection>();
+    *state.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Opens a new connection to the database, creating the file if needed.
+/// Commands use [`with_conn`] instead. The schema is brought up to date
+/// separately by `migrations::run_migrations`.
+pub fn open(app: &AppHandle) -> Result<Connection, AppError> {
+    let path = db_path(app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let key = app.state::<DbKey>().0.lock().unwrap().clone();
+    open_at(&path, key.as_deref())
+}
+
+/// Opens `path`, applying `key` first when given, and checks the key by
+/// reading the schema before anything is written.
+pub fn open_at(path: &Path, key: Option<&str>) -> Result<Connection, AppError> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key)?;
+    }
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| match e.sqlite_error_code() {
+            Some(ErrorCode::NotADatabase) => AppError::WrongPassphrase,
+            _ => e.into(),
+        })?;
+    Ok(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_attempts_are_retried_a_few_times() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let mut calls = 0;
+        let result = retry_busy(&mut conn, |_| {
+            calls += 1;
+            if calls < 3 {
+                Err(AppError::Busy("locked".into()))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(3));
+
+        calls = 0;
+        let result: Result<(), _> = retry_busy(&mut conn, |_| {
+            calls += 1;
+            Err(AppError::Busy("locked".into()))
+        });
+        assert!(matches!(result, Err(AppError::Busy(_))));
+        assert_eq!(calls, BUSY_RETRIES + 1);
+
+        calls = 0;
+        let result: Result<(), _> = retry_busy(&mut conn, |_| {
+            calls += 1;
+            Err(AppError::Conflict("taken".into()))
+        });
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn a_second_connection_waits_for_the_first_ones_lock() {
+        let path = std::env::temp_dir().join(format!("busy-{}.db", std::process::id()));
+        let writer = open_at(&path, None).unwrap();
+        writer
+            .execute_batch("CREATE TABLE t (x); BEGIN IMMEDIATE; INSERT INTO t VALUES (1);")
+            .unwrap();
+        let waiting = thread::spawn({
+            let path = path.clone();
+            move || {
+                let conn = open_at(&path, None).unwrap();
+                conn.execute("INSERT INTO t VALUES (2)", []).map(|_| ())
+            }
+        });
+        thread::sleep(Duration::from_millis(200));
+        writer.execute_batch("COMMIT").unwrap();
+
+        let result = waiting.join().unwrap();
+        let rows: i64 = writer.query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0)).unwrap();
+        drop(writer);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(rows, 2);
+    }
+}
@@ -0,0 +1,151 @@
+//! The error type every command returns.
+//!
+//! An [`AppError`] reaches the frontend as `{ "code": "NotFound", "message":
+//! "transaction 12 not found" }`. The UI branches on `code`, for instance to
+//! show a localized message, and `message` is English detail for logs and
+//! as a fallback.
+
+use std::fmt;
+
+use rusqlite::ErrorCode;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// The row or file asked for doesn't exist.
+    NotFound(String),
+    /// The input was rejected before anything changed.
+    Validation(String),
+    /// The change clashes with existing data or with another operation in
+    /// progress.
+    Conflict(String),
+    /// An account that doesn't allow an overdraft would go below zero.
+    InsufficientFunds(String),
+    /// Another connection, such as the frontend's, kept the database locked
+    /// for longer than we were willing to wait.
+    Busy(String),
+    /// An encrypted database can't be opened with the passphrase we have, or
+    /// we have none yet.
+    WrongPassphrase,
+    Database(String),
+    Io(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "NotFound",
+            Self::Validation(_) => "Validation",
+            Self::Conflict(_) => "Conflict",
+            Self::InsufficientFunds(_) => "InsufficientFunds",
+            Self::Busy(_) => "Busy",
+            Self::WrongPassphrase => "WrongPassphrase",
+            Self::Database(_) => "Database",
+            Self::Io(_) => "Io",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(message)
+            | Self::Validation(message)
+            | Self::Conflict(message)
+            | Self::InsufficientFunds(message)
+            | Self::Busy(message)
+            | Self::Database(message)
+            | Self::Io(message) => f.write_str(message),
+            Self::WrongPassphrase => f.write_str("wrong or missing database passphrase"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// Constraint violations (a duplicate name, say) become [`AppError::Conflict`],
+/// a `query_row` that matched nothing [`AppError::NotFound`] and a locked
+/// database [`AppError::Busy`].
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        if let rusqlite::Error::QueryReturnedNoRows = e {
+            return Self::NotFound(e.to_string());
+        }
+        match e.sqlite_error_code() {
+            Some(ErrorCode::ConstraintViolation) => Self::Conflict(e.to_string()),
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => Self::Busy(e.to_string()),
+            _ => Self::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Validation(e.to_string())
+    }
+}
+
+impl From<csv::Error> for AppError {
+    fn from(e: csv::Error) -> Self {
+        if e.is_io_error() {
+            Self::Io(e.to_string())
+        } else {
+            Self::Validation(e.to_string())
+        }
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(e: tauri::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+
+    #[test]
+    fn errors_serialize_with_a_code_and_message() {
+        let json = serde_json::to_value(AppError::NotFound("transaction 12 not found".into()));
+        assert_eq!(
+            json.unwrap(),
+            serde_json::json!({ "code": "NotFound", "message": "transaction 12 not found" })
+        );
+        let json = serde_json::to_value(AppError::WrongPassphrase).unwrap();
+        assert_eq!(json["code"], "WrongPassphrase");
+    }
+
+    #[test]
+    fn sqlite_errors_map_to_conflict_and_not_found() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (name TEXT UNIQUE); INSERT INTO t VALUES ('a');")
+            .unwrap();
+
+        let duplicate = conn.execute("INSERT INTO t VALUES ('a')", []).unwrap_err();
+        assert!(matches!(AppError::from(duplicate), AppError::Conflict(_)));
+        let missing = conn
+            .query_row("SELECT name FROM t WHERE name = 'b'", [], |row| row.get::<_, String>(0))
+            .unwrap_err();
+        assert!(matches!(AppError::from(missing), AppError::NotFound(_)));
+        let broken = conn.execute("SELECT * FROM nowhere", []).unwrap_err();
+        assert!(matches!(AppError::from(broken), AppError::Database(_)));
+    }
+}
@@ -0,0 +1,441 @@
+//! Undo and redo for edits to transactions.
+//!
+//! Every undoable command stores the affected rows as they were before and
+//! after the change in `mutation_log`, inside the same database transaction
+//! as the change, so the journal can never disagree with the data. Undo
+//! writes the "before" state back and redo the "after" state, but only
+//! while a row still looks the way the entry left it; anything touched
+//! since by another command is refused rather than overwritten. A refused
+//! entry is dropped, along with everything still to redo after a refused
+//! redo, so the next undo reaches the change before it. A step that would
+//! overdraw an account that doesn't allow it fails with
+//! [`AppError::InsufficientFunds`] and stays in the journal.
+//!
+//! Only the newest [`MAX_ENTRIES`] entries are kept. Recording a new
+//! change drops everything that was undone, and imports clear the journal
+//! since the rows it describes may no longer exist.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::commands::accounts::OverdraftGuard;
+use crate::db;
+use crate::error::AppError;
+
+/// How many changes can be undone in a row.
+pub const MAX_ENTRIES: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MutationKind {
+    Create,
+    Update,
+    Delete,
+}
+
+impl MutationKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self, AppError> {
+        match s {
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "delete" => Ok(Self::Delete),
+            other => Err(AppError::Validation(format!("unknown mutation kind {other:?}"))),
+        }
+    }
+}
+
+/// The fields of a transaction that undo and redo put back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionState {
+    pub uuid: String,
+    pub account_id: i64,
+    pub date: String,
+    pub description: String,
+    pub amount_minor: i64,
+    pub category_id: Option<i64>,
+    pub notes: Option<String>,
+    pub deleted_at: Option<String>,
+}
+
+/// One row before and after a change; `None` means the row didn't exist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowChange {
+    pub transaction_id: i64,
+    pub before: Option<TransactionState>,
+    pub after: Option<TransactionState>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MutationDescription {
+    pub id: i64,
+    pub kind: MutationKind,
+    /// E.g. `Edit "Groceries"`.
+    pub summary: String,
+    pub transaction_ids: Vec<i64>,
+}
+
+/// Reverses the newest change that hasn't been undone and describes it.
+#[tauri::command(async)]
+pub fn undo_last(app: AppHandle) -> Result<MutationDescription, AppError> {
+    db::with_conn(&app, undo)
+}
+
+/// Re-applies the most recently undone change and describes it.
+#[tauri::command(async)]
+pub fn redo_last(app: AppHandle) -> Result<MutationDescription, AppError> {
+    db::with_conn(&app, redo)
+}
+
+pub fn undo(conn: &mut Connection) -> Result<MutationDescription, AppError> {
+    step(conn, false)
+}
+
+pub fn redo(conn: &mut Connection) -> Result<MutationDescription, AppError> {
+    step(conn, true)
+}
+
+fn step(conn: &mut Connection, redo: bool) -> Result<MutationDescription, AppError> {
+    let mut tx = conn.transaction()?;
+    let sql = if redo {
+        "SELECT id, kind, summary, changes FROM mutation_log WHERE undone = 1 ORDER BY id LIMIT 1"
+    } else {
+        "SELECT id, kind, summary, changes FROM mutation_log WHERE undone = 0 ORDER BY id DESC LIMIT 1"
+    };
+    let (id, kind, summary, changes): (i64, String, String, String) = tx
+        .query_row(sql, [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        .optional()?
+        .ok_or_else(|| {
+            AppError::NotFound(if redo { "nothing to redo" } else { "nothing to undo" }.to_string())
+        })?;
+    let changes: Vec<RowChange> = serde_json::from_str(&changes)?;
+
+    // Touched accounts that can't be overdrawn must not end up worse off.
+    let states = changes.iter().flat_map(|c| c.before.iter().chain(&c.after));
+    let from = states.clone().map(|s| s.date.as_str()).min().unwrap_or_default();
+    let guard = OverdraftGuard::new(&tx, states.map(|s| s.account_id), from)?;
+
+    let applied = {
+        let sp = tx.savepoint()?;
+        let applied = if redo {
+            changes.iter().try_for_each(|change| {
+                apply(&sp, change.transaction_id, change.before.as_ref(), change.after.as_ref())
+            })
+        } else {
+            changes.iter().rev().try_for_each(|change| {
+                apply(&sp, change.transaction_id, change.after.as_ref(), change.before.as_ref())
+            })
+        };
+        // Dropping the savepoint uncommitted undoes a partly applied entry.
+        applied
+            .and_then(|()| guard.check(&sp))
+            .and_then(|()| sp.commit().map_err(AppError::from))
+    };
+    if let Err(AppError::Conflict(message)) = applied {
+        if redo {
+            tx.execute("DELETE FROM mutation_log WHERE undone = 1", [])?;
+        } else {
+            tx.execute("DELETE FROM mutation_log WHERE id = ?1", [id])?;
+        }
+        tx.commit()?;
+        return Err(AppError::Conflict(format!("{message}; {summary} was dropped from the history")));
+    }
+    applied?;
+    tx.execute(
+        "UPDATE mutation_log SET undone = ?1 WHERE id = ?2",
+        params![!redo, id],
+    )?;
+    tx.commit()?;
+
+    Ok(MutationDescription {
+        id,
+        kind: MutationKind::parse(&kind)?,
+        summary,
+        transaction_ids: changes.iter().map(|c| c.transaction_id).collect(),
+    })
+}
+
+/// Moves one row from `from` to `to`, refusing if it is no longer `from`.
+fn apply(
+    conn: &Connection,
+    id: i64,
+    from: Option<&TransactionState>,
+    to: Option<&TransactionState>,
+) -> Result<(), AppError> {
+    if snapshot(conn, id)?.as_ref() != from {
+        return Err(AppError::Conflict(format!(
+            "transaction {id} has changed since; it can no longer be undone or redone"
+        )));
+    }
+    match to {
+        None => {
+            let attached: bool = conn
+                .query_row(
+                    "SELECT EXISTS (SELECT 1 FROM transaction_splits WHERE transaction_id = ?1)
+                         OR EXISTS (SELECT 1 FROM transaction_tags WHERE transaction_id = ?1)
+                         OR EXISTS (SELECT 1 FROM receipts WHERE transaction_id = ?1)
+                         OR EXISTS (SELECT 1 FROM transactions
+                                    WHERE (id = ?1 AND refund_of_id IS NOT NULL)
+                                       OR refund_of_id = ?1)",
+                    [id],
+                    |row| row.get(0),
+                )?;
+            if attached {
+                return Err(AppError::Conflict(format!(
+                    "transaction {id} has splits, tags, receipts or refund links now; delete it instead"
+                )));
+            }
+            conn.execute("DELETE FROM transactions WHERE id = ?1", [id])?;
+        }
+        Some(s) if from.is_none() => {
+            conn.execute(
+                "INSERT INTO transactions
+                    (id, uuid, account_id, date, description, amount_minor, category_id, notes,
+                     deleted_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    s.uuid,
+                    s.account_id,
+                    s.date,
+                    s.description,
+                    s.amount_minor,
+                    s.category_id,
+                    s.notes,
+                    s.deleted_at
+                ],
+            )?;
+        }
+        Some(s) => {
+            conn.execute(
+                "UPDATE transactions SET
+                    account_id = ?2, date = ?3, description = ?4, amount_minor = ?5,
+                    category_id = ?6, notes = ?7, deleted_at = ?8
+                 WHERE id = ?1",
+                params![
+                    id,
+                    s.account_id,
+                    s.date,
+                    s.description,
+                    s.amount_minor,
+                    s.category_id,
+                    s.notes,
+                    s.deleted_at
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The journaled fields of transaction `id`, or `None` if it doesn't exist.
+pub fn snapshot(conn: &Connection, id: i64) -> Result<Option<TransactionState>, AppError> {
+    conn.query_row(
+        "SELECT uuid, account_id, date, description, amount_minor, category_id, notes, deleted_at
+         FROM transactions WHERE id = ?1",
+        [id],
+        |row| {
+            Ok(TransactionState {
+                uuid: row.get(0)?,
+                account_id: row.get(1)?,
+                date: row.get(2)?,
+                description: row.get(3)?,
+                amount_minor: row.get(4)?,
+                category_id: row.get(5)?,
+                notes: row.get(6)?,
+                deleted_at: row.get(7)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+/// Adds an entry for a change the caller just made on `conn`, which should
+/// be the same transaction the change was made in.
+pub fn record(conn: &Connection, kind: MutationKind, changes: &[RowChange]) -> Result<(), AppError> {
+    let label = changes
+        .iter()
+        .find_map(|c| c.after.as_ref().or(c.before.as_ref()))
+        .map_or("", |s| s.description.as_str());
+    let verb = match kind {
+        MutationKind::Create => "Add",
+        MutationKind::Update => "Edit",
+        MutationKind::Delete => "Delete",
+    };
+    let summary = format!("{verb} {label:?}");
+    let changes = serde_json::to_string(changes)?;
+
+    conn.execute("DELETE FROM mutation_log WHERE undone = 1", [])?;
+    conn.execute(
+        "INSERT INTO mutation_log (kind, summary, changes) VALUES (?1, ?2, ?3)",
+        params![kind.as_str(), summary, changes],
+    )?;
+    conn.execute(
+        "DELETE FROM mutation_log
+         WHERE id NOT IN (SELECT id FROM mutation_log ORDER BY id DESC LIMIT ?1)",
+        [MAX_ENTRIES],
+    )?;
+    Ok(())
+}
+
+/// Forgets every entry, for operations that rewrite data wholesale.
+pub fn clear(conn: &Connection) -> Result<(), AppError> {
+    conn.execute("DELETE FROM mutation_log", [])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::transactions::{
+        edit_transaction, insert_transaction, trash_transaction, NewTransaction,
+    };
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO accounts (name) VALUES ('Checking')", []).unwrap();
+        conn
+    }
+
+    fn expense(description: &str, amount_minor: i64) -> NewTransaction {
+        NewTransaction {
+            account_id: 1,
+            date: "2024-03-01".to_string(),
+            description: description.to_string(),
+            amount_minor,
+            category_id: None,
+            notes: None,
+        }
+    }
+
+    fn description(conn: &Connection, id: i64) -> String {
+        conn.query_row("SELECT description FROM transactions WHERE id = ?1", [id], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn live(conn: &Connection) -> i64 {
+        conn.query_row("SELECT count(*) FROM transactions WHERE deleted_at IS NULL", [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn a_series_of_changes_undoes_and_redoes_in_order() {
+        let mut conn = db();
+        let id = insert_transaction(&mut conn, &expense("Coffee", -5)).unwrap();
+        edit_transaction(&mut conn, id, &expense("Coffee beans", -7)).unwrap();
+        trash_transaction(&mut conn, id).unwrap();
+        assert_eq!(live(&conn), 0);
+
+        let undone = undo(&mut conn).unwrap();
+        assert_eq!(undone.kind, MutationKind::Delete);
+        assert_eq!(undone.summary, "Delete \"Coffee beans\"");
+        assert_eq!(live(&conn), 1);
+        assert_eq!(undo(&mut conn).unwrap().kind, MutationKind::Update);
+        assert_eq!(description(&conn, id), "Coffee");
+        assert_eq!(undo(&mut conn).unwrap().kind, MutationKind::Create);
+        assert!(snapshot(&conn, id).unwrap().is_none());
+        assert!(matches!(undo(&mut conn), Err(AppError::NotFound(_))));
+
+        assert_eq!(redo(&mut conn).unwrap().kind, MutationKind::Create);
+        assert_eq!(description(&conn, id), "Coffee");
+        assert_eq!(redo(&mut conn).unwrap().kind, MutationKind::Update);
+        assert_eq!(description(&conn, id), "Coffee beans");
+        assert_eq!(redo(&mut conn).unwrap().kind, MutationKind::Delete);
+        assert_eq!(live(&conn), 0);
+        assert!(matches!(redo(&mut conn), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn a_new_change_drops_what_was_undone() {
+        let mut conn = db();
+        let id = insert_transaction(&mut conn, &expense("Rent", -1000)).unwrap();
+        edit_transaction(&mut conn, id, &expense("Rent, March", -1000)).unwrap();
+        undo(&mut conn).unwrap();
+        insert_transaction(&mut conn, &expense("Tea", -3)).unwrap();
+
+        assert!(matches!(redo(&mut conn), Err(AppError::NotFound(_))));
+        assert_eq!(description(&conn, id), "Rent");
+    }
+
+    #[test]
+    fn only_the_newest_entries_are_kept() {
+        let mut conn = db();
+        let id = insert_transaction(&mut conn, &expense("Tea", 0)).unwrap();
+        for amount in 1..=MAX_ENTRIES + 20 {
+            edit_transaction(&mut conn, id, &expense("Tea", -amount)).unwrap();
+        }
+        let entries: i64 =
+            conn.query_row("SELECT count(*) FROM mutation_log", [], |row| row.get(0)).unwrap();
+        assert_eq!(entries, MAX_ENTRIES);
+    }
+
+    #[test]
+    fn an_undo_that_would_overdraw_is_refused_and_kept() {
+        let mut conn = db();
+        conn.execute("UPDATE accounts SET allow_overdraft = 0", []).unwrap();
+        let income = insert_transaction(&mut conn, &expense("Salary", 1000)).unwrap();
+        conn.execute(
+            "INSERT INTO transactions (account_id, date, amount_minor)
+             VALUES (1, '2024-03-05', -800)",
+            [],
+        )
+        .unwrap();
+
+        assert!(matches!(undo(&mut conn), Err(AppError::InsufficientFunds(_))));
+        assert!(snapshot(&conn, income).unwrap().is_some());
+        conn.execute("UPDATE accounts SET allow_overdraft = 1", []).unwrap();
+        assert_eq!(undo(&mut conn).unwrap().transaction_ids, [income]);
+    }
+
+    #[test]
+    fn a_conflicting_entry_is_dropped_so_undo_moves_on() {
+        let mut conn = db();
+        let first = insert_transaction(&mut conn, &expense("Rent", -1000)).unwrap();
+        let second = insert_transaction(&mut conn, &expense("Coffee", -300)).unwrap();
+        // Edited behind the journal's back, so creating it can't be undone.
+        conn.execute("UPDATE transactions SET description = 'Tea' WHERE id = ?1", [second])
+            .unwrap();
+
+        assert!(matches!(undo(&mut conn), Err(AppError::Conflict(_))));
+        assert_eq!(description(&conn, second), "Tea");
+
+        let undone = undo(&mut conn).unwrap();
+        assert_eq!(undone.transaction_ids, [first]);
+        assert!(snapshot(&conn, first).unwrap().is_none());
+        assert!(matches!(undo(&mut conn), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn a_conflicting_redo_clears_what_is_left_to_redo() {
+        let mut conn = db();
+        let id = insert_transaction(&mut conn, &expense("Rent", -1000)).unwrap();
+        edit_transaction(&mut conn, id, &expense("Rent, March", -1000)).unwrap();
+        undo(&mut conn).unwrap();
+        undo(&mut conn).unwrap();
+        // Back where the first entry started, but under a new uuid.
+        conn.execute(
+            "INSERT INTO transactions (id, account_id, date, description, amount_minor)
+             VALUES (?1, 1, '2024-03-01', 'Other', 5)",
+            [id],
+        )
+        .unwrap();
+
+        assert!(matches!(redo(&mut conn), Err(AppError::Conflict(_))));
+        assert!(matches!(redo(&mut conn), Err(AppError::NotFound(_))));
+        assert_eq!(description(&conn, id), "Other");
+    }
+}
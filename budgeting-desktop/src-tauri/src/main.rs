@@ -1,10 +1,126 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod backup;
+mod commands;
+mod db;
+mod error;
+mod journal;
+mod maintenance;
+mod migrations;
+mod money;
+mod profiles;
+mod recurring;
+mod settings;
+mod time;
+
+use tauri::{Manager, RunEvent};
 use tauri_plugin_sql::Builder as SqlBuilder;
 
 fn main() {
   tauri::Builder::default()
     .plugin(SqlBuilder::new().build()) // v2 plugin init
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .manage(db::DbKey::default())
+    .manage(db::SharedConnection::default())
+    .manage(recurring::StartupRecurrences::default())
+    .setup(|app| {
+      app.manage(profiles::load_active(app.handle())?);
+      // An encrypted database stays locked until the frontend supplies the
+      // passphrase; set_database_passphrase runs the startup work then.
+      if !commands::encryption::is_encrypted(app.handle())? {
+        db::with_conn(app.handle(), |conn| migrations::run_migrations(conn))?;
+        recurring::materialize_on_startup(app.handle());
+      }
+      Ok(())
+    })
+    .invoke_handler(tauri::generate_handler![
+      backup::configure_auto_backup,
+      backup::export_backup,
+      backup::import_backup,
+      backup::validate_backup,
+      commands::accounts::account_balance,
+      commands::accounts::account_ledger,
+      commands::accounts::archive_account,
+      commands::accounts::list_accounts,
+      commands::accounts::mark_cleared,
+      commands::accounts::reconciliation_summary,
+      commands::accounts::set_allow_overdraft,
+      commands::accounts::set_statement_balance,
+      commands::accounts::unarchive_account,
+      commands::analytics::detect_anomalies,
+      commands::analytics::spending_by_day_of_month,
+      commands::analytics::spending_by_weekday,
+      commands::budgets::check_budget_status,
+      commands::budgets::compute_effective_budget,
+      commands::categories::category_tree,
+      commands::categories::reassign_category,
+      commands::categories::set_category_parent,
+      commands::duplicates::find_duplicate_candidates,
+      commands::duplicates::merge_duplicates,
+      commands::encryption::rekey_database,
+      commands::encryption::set_database_passphrase,
+      commands::export::export_transactions_csv,
+      commands::goals::goal_progress,
+      commands::import::import_csv,
+      commands::import::import_ofx,
+      commands::loans::loan_payoff_summary,
+      commands::loans::loan_schedule,
+      commands::payees::normalize_payees,
+      commands::payees::rename_payee,
+      commands::payees::spending_by_payee,
+      commands::receipts::attach_receipt,
+      commands::receipts::list_receipts,
+      commands::refunds::link_refund,
+      commands::refunds::unlink_refund,
+      commands::reminders::upcoming_reminders,
+      commands::reports::category_yoy,
+      commands::reports::dashboard_snapshot,
+      commands::reports::forecast_balance,
+      commands::reports::generate_statement,
+      commands::reports::monthly_summary,
+      commands::reports::net_worth_timeseries,
+      commands::rules::apply_categorization_rules,
+      commands::rules::test_rule,
+      commands::sql::sql_execute,
+      commands::sql::sql_select,
+      commands::tags::add_tag,
+      commands::tags::remove_tag,
+      commands::tags::transactions_by_tag,
+      commands::transactions::create_transaction,
+      commands::transactions::bulk_update,
+      commands::transactions::delete_transaction,
+      commands::transactions::list_transactions_page,
+      commands::transactions::list_trash,
+      commands::transactions::note_suggestions,
+      commands::transactions::purge_trash,
+      commands::transactions::restore_transaction,
+      commands::transactions::search_transactions,
+      commands::transactions::set_transaction_splits,
+      commands::transactions::update_transaction,
+      commands::transfers::create_transfer,
+      commands::transfers::delete_transfer,
+      journal::redo_last,
+      journal::undo_last,
+      maintenance::integrity_check,
+      maintenance::vacuum_database,
+      money::fx::convert_amount,
+      profiles::create_profile,
+      profiles::list_profiles,
+      profiles::switch_profile,
+      recurring::startup_recurrences,
+      settings::get_all_settings,
+      settings::get_bool_setting,
+      settings::get_int_setting,
+      settings::get_setting,
+      settings::set_setting,
+      time::fiscal_period,
+    ])
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application")
+    .run(|app, event| {
+      if let RunEvent::ExitRequested { code, api, .. } = event {
+        if backup::backup_before_exit(app, code.unwrap_or(0)) {
+          api.prevent_exit();
+        }
+      }
+    });
 }
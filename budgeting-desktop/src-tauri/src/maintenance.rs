@@ -0,0 +1,243 @@
+//! Housekeeping on the database file itself, and checks that its rows
+//! still fit together.
+
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{Connection, ErrorCode};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum IntegrityIssueKind {
+    /// A live transaction's splits don't add up to its amount.
+    SplitsMismatch,
+    MissingAccount,
+    MissingCategory,
+    /// A transfer without exactly two live legs that cancel out.
+    BrokenTransfer,
+    /// An amount stored as something other than an integer.
+    InvalidAmount,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityIssue {
+    pub kind: IntegrityIssueKind,
+    /// `transactions` or `transaction_splits`.
+    pub table: &'static str,
+    /// The rows to fix, such as both legs of a transfer.
+    pub row_ids: Vec<i64>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VacuumStats {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Rebuilds the file to reclaim space left by deletes, then refreshes the
+/// query planner's statistics.
+#[tauri::command(async)]
+pub fn vacuum_database(app: AppHandle) -> Result<VacuumStats, AppError> {
+    let path = db::db_path(&app)?;
+    db::with_conn(&app, |conn| vacuum(conn, &path))
+}
+
+/// VACUUM refuses to run inside a transaction. The shared connection is
+/// back in autocommit between commands, so this only fails if a caller is
+/// midway through one. Busy errors aren't retried: by the time VACUUM gives
+/// up it has already waited out [`db::BUSY_TIMEOUT`].
+pub fn vacuum(conn: &Connection, path: &Path) -> Result<VacuumStats, AppError> {
+    if !conn.is_autocommit() {
+        return Err(AppError::Conflict(
+            "cannot vacuum while a transaction is open on this connection".into(),
+        ));
+    }
+    let bytes_before = file_size(path)?;
+    conn.execute_batch("VACUUM; ANALYZE;").map_err(|e| match e.sqlite_error_code() {
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => {
+            AppError::Conflict(
+                "the database is in use by another operation; try again once it finishes".to_string(),
+            )
+        }
+        _ => e.into(),
+    })?;
+    Ok(VacuumStats {
+        bytes_before,
+        bytes_after: file_size(path)?,
+    })
+}
+
+/// Looks for rows that the commands would never write but a manual edit
+/// could have, and lists every one found. Changes nothing.
+#[tauri::command(async)]
+pub fn integrity_check(app: AppHandle) -> Result<Vec<IntegrityIssue>, AppError> {
+    db::with_conn(&app, |conn| integrity_issues(conn))
+}
+
+pub fn integrity_issues(conn: &Connection) -> Result<Vec<IntegrityIssue>, AppError> {
+    let mut issues = Vec::new();
+    // Each query yields a row ID and a detail, as text, for the message.
+    let mut check = |kind, table, sql: &str, message: &dyn Fn(i64, String) -> String| {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (id, detail) in rows {
+            issues.push(IntegrityIssue {
+                kind,
+                table,
+                row_ids: vec![id],
+                message: message(id, detail),
+            });
+        }
+        Ok::<_, AppError>(())
+    };
+
+    check(
+        IntegrityIssueKind::InvalidAmount,
+        "transactions",
+        "SELECT id, typeof(amount_minor) FROM transactions WHERE typeof(amount_minor) != 'integer'",
+        &|id, kind| format!("transaction {id} has a {kind} amount"),
+    )?;
+    check(
+        IntegrityIssueKind::InvalidAmount,
+        "transaction_splits",
+        "SELECT id, typeof(amount_minor) FROM transaction_splits
+         WHERE typeof(amount_minor) != 'integer'",
+        &|id, kind| format!("split {id} has a {kind} amount"),
+    )?;
+    check(
+        IntegrityIssueKind::SplitsMismatch,
+        "transactions",
+        "SELECT t.id, t.amount_minor || ' but its splits total ' || SUM(s.amount_minor)
+         FROM transactions t JOIN transaction_splits s ON s.transaction_id = t.id
+         WHERE t.deleted_at IS NULL
+         GROUP BY t.id
+         HAVING SUM(s.amount_minor) != t.amount_minor",
+        &|id, detail| format!("transaction {id} is {detail}"),
+    )?;
+    check(
+        IntegrityIssueKind::MissingAccount,
+        "transactions",
+        "SELECT id, CAST(account_id AS TEXT) FROM transactions
+         WHERE account_id NOT IN (SELECT id FROM accounts)",
+        &|id, account| format!("transaction {id} belongs to missing account {account}"),
+    )?;
+    check(
+        IntegrityIssueKind::MissingCategory,
+        "transactions",
+        "SELECT id, CAST(category_id AS TEXT) FROM transactions
+         WHERE category_id IS NOT NULL AND category_id NOT IN (SELECT id FROM categories)",
+        &|id, category| format!("transaction {id} is filed under missing category {category}"),
+    )?;
+    check(
+        IntegrityIssueKind::MissingCategory,
+        "transaction_splits",
+        "SELECT id, CAST(category_id AS TEXT) FROM transaction_splits
+         WHERE category_id NOT IN (SELECT id FROM categories)",
+        &|id, category| format!("split {id} is filed under missing category {category}"),
+    )?;
+
+    // Both legs are soft-deleted together, so only live ones are counted.
+    // A transfer with a leg that isn't an integer is left to the amount
+    // check above, since its total can't be read as one.
+    let mut stmt = conn.prepare(
+        "SELECT transfer_id, group_concat(id), count(*), SUM(amount_minor)
+         FROM transactions
+         WHERE transfer_id IS NOT NULL AND deleted_at IS NULL
+         GROUP BY transfer_id
+         HAVING (count(*) != 2 OR SUM(amount_minor) != 0)
+            AND count(*) = count(CASE WHEN typeof(amount_minor) = 'integer' THEN 1 END)
+         ORDER BY transfer_id",
+    )?;
+    let transfers = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (transfer_id, ids, legs, total) in transfers {
+        let mut row_ids = ids
+            .split(',')
+            .map(|id| id.parse())
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(|e| AppError::Database(format!("transfer {transfer_id} legs: {e}")))?;
+        row_ids.sort_unstable();
+        let message = if legs != 2 {
+            format!("transfer {transfer_id} has {legs} live legs instead of 2")
+        } else {
+            format!("the legs of transfer {transfer_id} leave {total} unaccounted for")
+        };
+        issues.push(IntegrityIssue {
+            kind: IntegrityIssueKind::BrokenTransfer,
+            table: "transactions",
+            row_ids,
+            message,
+        });
+    }
+    Ok(issues)
+}
+
+fn file_size(path: &Path) -> Result<u64, AppError> {
+    fs::metadata(path)
+        .map(|m| m.len())
+        .map_err(|e| AppError::Io(format!("{}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    #[test]
+    fn a_transfer_leg_with_a_real_amount_is_only_an_invalid_amount() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking'), ('Savings');
+             INSERT INTO transactions (account_id, date, description, amount_minor, transfer_id)
+             VALUES (1, '2024-03-01', 'Transfer', -500, 7),
+                    (2, '2024-03-01', 'Transfer', 499.5, 7);",
+        )
+        .unwrap();
+
+        let issues = integrity_issues(&conn).unwrap();
+        let kinds: Vec<_> = issues.iter().map(|i| (i.kind, i.row_ids.clone())).collect();
+        assert_eq!(kinds, [(IntegrityIssueKind::InvalidAmount, vec![2])]);
+    }
+
+    #[test]
+    fn vacuum_shrinks_the_file_but_not_inside_a_transaction() {
+        let path = std::env::temp_dir().join(format!("vacuum-{}.db", std::process::id()));
+        let mut conn = Connection::open(&path).unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 5000)
+             INSERT INTO transactions (account_id, date, description, amount_minor)
+             SELECT 1, '2024-03-01', printf('%.200d', i), -i FROM n;
+             DELETE FROM transactions;",
+        )
+        .unwrap();
+
+        let tx = conn.transaction().unwrap();
+        assert!(matches!(vacuum(&tx, &path), Err(AppError::Conflict(_))));
+        drop(tx);
+        let stats = vacuum(&conn, &path);
+        drop(conn);
+        fs::remove_file(&path).unwrap();
+        let stats = stats.unwrap();
+        assert!(stats.bytes_after < stats.bytes_before, "{stats:?}");
+    }
+}
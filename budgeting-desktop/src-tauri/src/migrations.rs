@@ -0,0 +1,379 @@
+//! Versioned schema for the tables owned by the Rust commands.
+//!
+//! `MIGRATIONS[n]` takes the database from version `n` to `n + 1`. Steps are
+//! append-only: once released, a step is never edited, and every schema
+//! change ships as a new one. The current version lives in `_meta`.
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::error::AppError;
+
+const MIGRATIONS: &[&str] = &[
+    // 1: baseline. The frontend used to create its own `accounts` table,
+    // with text ids, a `type` and a REAL `balance`; it is set aside as
+    // `legacy_accounts` (empty on a new database) and its rows are moved
+    // over by step 20. The `uuid` columns give rows an identity that
+    // survives moving between machines (see backup.rs).
+    "
+    CREATE TABLE IF NOT EXISTS accounts (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        type TEXT NOT NULL,
+        balance REAL NOT NULL DEFAULT 0,
+        currency TEXT NOT NULL
+    );
+    ALTER TABLE accounts RENAME TO legacy_accounts;
+
+    CREATE TABLE accounts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        name TEXT NOT NULL,
+        currency TEXT NOT NULL DEFAULT 'USD'
+    );
+
+    CREATE TABLE categories (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        name TEXT NOT NULL
+    );
+
+    CREATE TABLE transactions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        account_id INTEGER NOT NULL,
+        date TEXT NOT NULL,
+        description TEXT NOT NULL DEFAULT '',
+        amount_minor INTEGER NOT NULL,
+        category_id INTEGER,
+        notes TEXT,
+        import_hash TEXT,
+        transfer_id INTEGER,
+        deleted_at TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+
+    CREATE UNIQUE INDEX idx_transactions_import_hash
+        ON transactions (account_id, import_hash);
+
+    CREATE TABLE fx_rates (
+        base TEXT NOT NULL,
+        quote TEXT NOT NULL,
+        date TEXT NOT NULL,
+        rate_micros INTEGER NOT NULL,
+        PRIMARY KEY (base, quote, date)
+    );
+
+    CREATE TABLE recurring_rules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        account_id INTEGER NOT NULL,
+        description TEXT NOT NULL DEFAULT '',
+        amount_minor INTEGER NOT NULL,
+        category_id INTEGER,
+        interval TEXT NOT NULL CHECK (interval IN ('daily', 'weekly', 'monthly', 'yearly')),
+        start_date TEXT NOT NULL,
+        next_run TEXT NOT NULL
+    );
+
+    CREATE TABLE budgets (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        category_id INTEGER NOT NULL,
+        period TEXT NOT NULL,
+        limit_minor INTEGER NOT NULL,
+        UNIQUE (category_id, period)
+    );
+    ",
+    // 2: split transactions across several categories.
+    "
+    CREATE TABLE transaction_splits (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        transaction_id INTEGER NOT NULL,
+        category_id INTEGER NOT NULL,
+        amount_minor INTEGER NOT NULL
+    );
+
+    CREATE INDEX idx_transaction_splits_transaction ON transaction_splits (transaction_id);
+    ",
+    // 3: app-wide key/value preferences.
+    "
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    ",
+    // 4: starting balance for history that predates the first transaction.
+    "
+    ALTER TABLE accounts ADD COLUMN opening_balance_minor INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE accounts ADD COLUMN opening_date TEXT;
+    ",
+    // 5: free-form tags, many per transaction.
+    "
+    CREATE TABLE tags (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE transaction_tags (
+        transaction_id INTEGER NOT NULL,
+        tag_id INTEGER NOT NULL,
+        PRIMARY KEY (transaction_id, tag_id)
+    );
+
+    CREATE INDEX idx_transaction_tags_tag ON transaction_tags (tag_id);
+    ",
+    // 6: rules that fill in categories after an import.
+    "
+    CREATE TABLE categorization_rules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        match_field TEXT NOT NULL CHECK (match_field IN ('description', 'notes')),
+        pattern TEXT NOT NULL,
+        category_id INTEGER NOT NULL,
+        priority INTEGER NOT NULL DEFAULT 0
+    );
+    ",
+    // 7: envelope-style carry-over of unspent (or overspent) budget.
+    "
+    ALTER TABLE budgets ADD COLUMN rollover_enabled INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 8: receipt files attached to transactions.
+    "
+    CREATE TABLE receipts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        transaction_id INTEGER NOT NULL,
+        file_name TEXT NOT NULL,
+        original_name TEXT NOT NULL,
+        hash TEXT NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+
+    CREATE INDEX idx_receipts_transaction ON receipts (transaction_id);
+    CREATE INDEX idx_receipts_hash ON receipts (hash);
+    ",
+    // 9: reconciling against bank statements.
+    "
+    ALTER TABLE transactions ADD COLUMN cleared INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE transactions ADD COLUMN cleared_date TEXT;
+    ALTER TABLE accounts ADD COLUMN statement_balance_minor INTEGER;
+    ALTER TABLE accounts ADD COLUMN statement_date TEXT;
+    ",
+    // 10: nested categories, e.g. Food > Groceries.
+    "
+    ALTER TABLE categories ADD COLUMN parent_id INTEGER;
+    ",
+    // 11: accounts that must never go below zero.
+    "
+    ALTER TABLE accounts ADD COLUMN allow_overdraft INTEGER NOT NULL DEFAULT 1;
+    ",
+    // 12: fixed-rate installment loans.
+    "
+    CREATE TABLE loans (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        name TEXT NOT NULL DEFAULT '',
+        principal_minor INTEGER NOT NULL,
+        apr_micros INTEGER NOT NULL,
+        term_months INTEGER NOT NULL,
+        start_date TEXT NOT NULL
+    );
+    ",
+    // 13: merchants recognized from transaction descriptions.
+    "
+    CREATE TABLE payees (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        name TEXT NOT NULL,
+        match_key TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE payee_rules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        pattern TEXT NOT NULL UNIQUE,
+        payee_name TEXT
+    );
+
+    INSERT INTO payee_rules (pattern, payee_name) VALUES
+        ('sq *', NULL), ('tst* ', NULL), ('paypal *', NULL),
+        ('amzn', 'Amazon'), ('amazon', 'Amazon');
+
+    ALTER TABLE transactions ADD COLUMN payee_id INTEGER;
+    CREATE INDEX idx_transactions_payee ON transactions (payee_id);
+    ",
+    // 14: undo/redo journal; see journal.rs.
+    "
+    CREATE TABLE mutation_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        kind TEXT NOT NULL CHECK (kind IN ('create', 'update', 'delete')),
+        summary TEXT NOT NULL,
+        changes TEXT NOT NULL,
+        undone INTEGER NOT NULL DEFAULT 0,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    ",
+    // 15: savings goals, tracked by an account's balance or a tag.
+    "
+    CREATE TABLE goals (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        uuid TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+        name TEXT NOT NULL,
+        target_minor INTEGER NOT NULL,
+        target_date TEXT NOT NULL,
+        linked_account_id INTEGER,
+        linked_tag_id INTEGER
+    );
+    ",
+    // 16: refunds linked to the purchase they return; see refunds.rs.
+    "
+    ALTER TABLE transactions ADD COLUMN refund_of_id INTEGER;
+    CREATE INDEX idx_transactions_refund_of ON transactions (refund_of_id);
+    ",
+    // 17: newest-first paging by (date, id).
+    "
+    CREATE INDEX idx_transactions_date ON transactions (date, id);
+    ",
+    // 18: closed accounts kept for their history.
+    "
+    ALTER TABLE accounts ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 19: bill reminders generated from recurring rules; see reminders.rs.
+    "
+    CREATE TABLE reminders (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        rule_id INTEGER NOT NULL,
+        due_date TEXT NOT NULL,
+        paid_transaction_id INTEGER,
+        UNIQUE (rule_id, due_date)
+    );
+    ",
+    // 20: the frontend's accounts, set aside by step 1. Their text id
+    // becomes the uuid, which the frontend keeps using as the id, so its
+    // other tables still point at them. The old balance becomes the
+    // opening balance.
+    "
+    ALTER TABLE accounts ADD COLUMN kind TEXT NOT NULL DEFAULT 'Bank';
+
+    INSERT INTO accounts (uuid, name, kind, currency, opening_balance_minor)
+    SELECT id, name, type, currency, CAST(round(balance * 100) AS INTEGER)
+    FROM legacy_accounts
+    ORDER BY rowid;
+
+    DROP TABLE legacy_accounts;
+    ",
+];
+
+/// Schema version this build of the app expects.
+pub const LATEST_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Applies every pending step in one transaction and returns the version
+/// the database ends at. Refuses to touch a database from a newer build.
+pub fn run_migrations(conn: &Connection) -> Result<u32, AppError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS _meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+    )?;
+
+    let tx = conn.unchecked_transaction()?;
+    let current: u32 = tx
+        .query_row("SELECT value FROM _meta WHERE key = 'schema_version'", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?
+        .map(|v| {
+            v.parse()
+                .map_err(|_| AppError::Database(format!("corrupt schema_version {v:?}")))
+        })
+        .transpose()?
+        .unwrap_or(0);
+
+    if current > LATEST_VERSION {
+        return Err(AppError::Database(format!(
+            "database schema version {current} is newer than this app supports ({LATEST_VERSION}); \
+             please update the app"
+        )));
+    }
+
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = i as u32 + 1;
+        tx.execute_batch(sql)
+            .map_err(|e| AppError::Database(format!("migration {version} failed: {e}")))?;
+        tx.execute(
+            "INSERT INTO _meta (key, value) VALUES ('schema_version', ?1)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![version.to_string()],
+        )?;
+    }
+    tx.commit()?;
+    Ok(LATEST_VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_frontends_accounts_move_into_the_new_table() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE accounts (
+                 id TEXT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 type TEXT NOT NULL,
+                 balance REAL NOT NULL DEFAULT 0,
+                 currency TEXT NOT NULL
+             );
+             INSERT INTO accounts VALUES ('9b2e-wallet', 'Wallet', 'Wallet', 12.34, 'KSH');",
+        )
+        .unwrap();
+        assert_eq!(run_migrations(&conn).unwrap(), LATEST_VERSION);
+
+        let row: (i64, String, String, String, i64) = conn
+            .query_row(
+                "SELECT id, uuid, kind, currency, opening_balance_minor FROM accounts",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!(row, (1, "9b2e-wallet".into(), "Wallet".into(), "KSH".into(), 1234));
+        let legacy: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE name = 'legacy_accounts'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(legacy, 0);
+    }
+
+    #[test]
+    fn migrating_twice_changes_nothing() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO accounts (uuid, name, kind, opening_balance_minor, currency)
+             VALUES ('a1', 'Savings', 'Savings', 500, 'USD')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(run_migrations(&conn).unwrap(), LATEST_VERSION);
+        let count: i64 =
+            conn.query_row("SELECT count(*) FROM accounts", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn a_newer_schema_is_refused() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute(
+            "UPDATE _meta SET value = ?1 WHERE key = 'schema_version'",
+            [(LATEST_VERSION + 1).to_string()],
+        )
+        .unwrap();
+        assert!(matches!(run_migrations(&conn), Err(AppError::Database(_))));
+    }
+}
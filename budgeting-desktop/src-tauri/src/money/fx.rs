@@ -0,0 +1,123 @@
+//! Currency conversion using the daily rates stored in `fx_rates`.
+//!
+//! A row `(base, quote, date, rate_micros)` means one unit of `base` bought
+//! `rate_micros / 1_000_000` units of `quote` on that date. Rates are looked up
+//! in either direction, so storing USD→EUR is enough to convert EUR→USD too.
+//!
+//! Net worth is reported in the [`REPORTING_CURRENCY`] setting, falling back
+//! to [`DEFAULT_CURRENCY`].
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+use crate::money::div_round;
+use crate::settings::{self, DEFAULT_CURRENCY, REPORTING_CURRENCY};
+
+const MICROS: i128 = 1_000_000;
+
+/// Converts `amount` (minor units of `from`) into minor units of `to` using
+/// the rate for `on_date`, or the most recent earlier rate if that day has none.
+#[tauri::command(async)]
+pub fn convert_amount(
+    app: AppHandle,
+    amount: i64,
+    from: String,
+    to: String,
+    on_date: String,
+) -> Result<i64, AppError> {
+    let on_date = NaiveDate::parse_from_str(&on_date, "%Y-%m-%d")
+        .map_err(|e| AppError::Validation(format!("invalid date {on_date:?}: {e}")))?;
+    db::with_conn(&app, |conn| convert(conn, amount, &from, &to, on_date))
+}
+
+pub fn convert(
+    conn: &Connection,
+    amount: i64,
+    from: &str,
+    to: &str,
+    on_date: NaiveDate,
+) -> Result<i64, AppError> {
+    let from = currency_code(from)?;
+    let to = currency_code(to)?;
+    convert_with_rate_since(conn, amount, &from, &to, None, on_date)?
+        .ok_or_else(|| AppError::NotFound(format!("no {from}/{to} rate on or before {on_date}")))
+}
+
+/// Like [`convert`], but only a rate dated from `earliest` to `on_date`
+/// will do. `None` when there is no such rate, so a stale one is never used.
+pub fn convert_between(
+    conn: &Connection,
+    amount: i64,
+    from: &str,
+    to: &str,
+    earliest: NaiveDate,
+    on_date: NaiveDate,
+) -> Result<Option<i64>, AppError> {
+    convert_with_rate_since(conn, amount, from, to, Some(earliest), on_date)
+}
+
+/// The currency net worth is reported in.
+pub fn reporting_currency(conn: &Connection) -> Result<String, AppError> {
+    let code = match settings::get(conn, REPORTING_CURRENCY)? {
+        Some(code) => code,
+        None => settings::get(conn, DEFAULT_CURRENCY)?.unwrap_or_default(),
+    };
+    currency_code(&code)
+}
+
+fn convert_with_rate_since(
+    conn: &Connection,
+    amount: i64,
+    from: &str,
+    to: &str,
+    earliest: Option<NaiveDate>,
+    on_date: NaiveDate,
+) -> Result<Option<i64>, AppError> {
+    let from = currency_code(from)?;
+    let to = currency_code(to)?;
+    if from == to {
+        return Ok(Some(amount));
+    }
+
+    let day = |date: NaiveDate| date.format("%Y-%m-%d").to_string();
+    let rate: Option<(String, i64)> = conn
+        .query_row(
+            "SELECT base, rate_micros FROM fx_rates
+             WHERE ((base = ?1 AND quote = ?2) OR (base = ?2 AND quote = ?1))
+               AND date <= ?3 AND (?4 IS NULL OR date >= ?4)
+             ORDER BY date DESC
+             LIMIT 1",
+            params![from, to, day(on_date), earliest.map(day)],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let Some((base, rate_micros)) = rate else {
+        return Ok(None);
+    };
+    if rate_micros <= 0 {
+        return Err(AppError::Validation(format!("stored {from}/{to} rate is not positive")));
+    }
+
+    let converted = if base == from {
+        div_round(amount as i128 * rate_micros as i128, MICROS)
+    } else {
+        div_round(amount as i128 * MICROS, rate_micros as i128)
+    };
+    i64::try_from(converted)
+        .map(Some)
+        .map_err(|_| AppError::Validation("converted amount overflows".to_string()))
+}
+
+/// Normalizes and checks an ISO 4217 code.
+fn currency_code(code: &str) -> Result<String, AppError> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code)
+    } else {
+        Err(AppError::Validation(format!("invalid currency code {code:?}")))
+    }
+}
@@ -0,0 +1,20 @@
+pub mod fx;
+
+/// Formats minor units as a plain decimal with two places, e.g. `-1234`
+/// becomes `-12.34`.
+pub fn format_minor(amount_minor: i64) -> String {
+    let sign = if amount_minor < 0 { "-" } else { "" };
+    let abs = amount_minor.unsigned_abs();
+    format!("{sign}{}.{:02}", abs / 100, abs % 100)
+}
+
+/// Integer division rounding half away from zero.
+pub fn div_round(n: i128, d: i128) -> i128 {
+    let q = n / d;
+    let r = n % d;
+    if r.abs() * 2 >= d.abs() {
+        q + n.signum() * d.signum()
+    } else {
+        q
+    }
+}
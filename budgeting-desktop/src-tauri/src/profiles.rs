@@ -0,0 +1,234 @@
+//! Separate budgets for several people sharing one installation.
+//!
+//! Each profile has its own database file; the profile list itself lives in
+//! `profiles.db`, which is never encrypted, and the choice of active profile
+//! in `profile.json`, both directly under [`db::data_dir`]. The default
+//! profile keeps using `budget.db`, so data from before profiles existed
+//! stays where it was.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::db::{self, DbKey};
+use crate::error::AppError;
+use crate::{commands, migrations, recurring};
+
+/// ID of the profile seeded on first run, which owns `budget.db`.
+pub const DEFAULT_PROFILE: i64 = 1;
+
+/// Profile whose database every command works on.
+pub struct ActiveProfile(pub Mutex<i64>);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProfileConfig {
+    active_profile: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub id: i64,
+    pub name: String,
+    pub created_at: String,
+    pub active: bool,
+}
+
+pub fn db_file_name(profile_id: i64) -> String {
+    if profile_id == DEFAULT_PROFILE {
+        "budget.db".to_string()
+    } else {
+        format!("profile_{profile_id}.db")
+    }
+}
+
+fn config_path(app: &AppHandle) -> Result<PathBuf, AppError> {
+    Ok(db::data_dir(app)?.join("profile.json"))
+}
+
+/// The profile chosen on a previous run, or the default one.
+pub fn load_active(app: &AppHandle) -> Result<ActiveProfile, AppError> {
+    let path = config_path(app)?;
+    if !path.exists() {
+        return Ok(ActiveProfile(Mutex::new(DEFAULT_PROFILE)));
+    }
+    let text = fs::read_to_string(&path)?;
+    let config: ProfileConfig = serde_json::from_str(&text)?;
+    Ok(ActiveProfile(Mutex::new(config.active_profile.unwrap_or(DEFAULT_PROFILE))))
+}
+
+/// Saves the active profile as the one to open on the next launch. Called
+/// once its database has opened, so a profile that fails to open isn't
+/// picked again.
+pub fn save_active(app: &AppHandle) -> Result<(), AppError> {
+    let profile_id = *app.state::<ActiveProfile>().0.lock().unwrap();
+    let text = serde_json::to_string(&ProfileConfig {
+        active_profile: Some(profile_id),
+    })?;
+    fs::write(config_path(app)?, text).map_err(AppError::from)
+}
+
+/// Opens the profile list, creating it with the default profile if needed.
+fn open_registry(app: &AppHandle) -> Result<Connection, AppError> {
+    let dir = db::data_dir(app)?;
+    fs::create_dir_all(&dir)?;
+    let conn = Connection::open(dir.join("profiles.db"))?;
+    init_registry(&conn)?;
+    Ok(conn)
+}
+
+fn init_registry(conn: &Connection) -> Result<(), AppError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS profiles (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );",
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO profiles (id, name) VALUES (?1, 'Default')",
+        [DEFAULT_PROFILE],
+    )?;
+    Ok(())
+}
+
+/// Registers a new profile. Its database is created on first switch.
+#[tauri::command(async)]
+pub fn create_profile(
+    app: AppHandle,
+    active: State<'_, ActiveProfile>,
+    name: String,
+) -> Result<Profile, AppError> {
+    let conn = open_registry(&app)?;
+    let current = *active.0.lock().unwrap();
+    insert_profile(&conn, &name, current)
+}
+
+fn insert_profile(conn: &Connection, name: &str, current: i64) -> Result<Profile, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation("profile name must not be empty".into()));
+    }
+    let taken: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM profiles WHERE name = ?1)", [name], |row| row.get(0))?;
+    if taken {
+        return Err(AppError::Conflict(format!("a profile named {name:?} already exists")));
+    }
+    conn.execute("INSERT INTO profiles (name) VALUES (?1)", [name])?;
+    let id = conn.last_insert_rowid();
+    load_profile(conn, id, current)?
+        .ok_or_else(|| AppError::NotFound(format!("profile {id} not found")))
+}
+
+#[tauri::command(async)]
+pub fn list_profiles(app: AppHandle, active: State<'_, ActiveProfile>) -> Result<Vec<Profile>, AppError> {
+    let conn = open_registry(&app)?;
+    let current = *active.0.lock().unwrap();
+    profiles(&conn, current)
+}
+
+fn profiles(conn: &Connection, current: i64) -> Result<Vec<Profile>, AppError> {
+    let mut stmt = conn.prepare("SELECT id, name, created_at FROM profiles ORDER BY id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            Ok(Profile {
+                id,
+                name: row.get(1)?,
+                created_at: row.get(2)?,
+                active: id == current,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Points every command at `profile_id`'s database, the frontend's store
+/// included, and remembers the choice for the next launch once the database
+/// has opened. If it fails to open the previous profile stays active. The
+/// session passphrase is dropped since it belongs to the previous file; an
+/// encrypted profile stays locked until `set_database_passphrase` is called,
+/// as at startup, and is remembered then.
+#[tauri::command(async)]
+pub fn switch_profile(
+    app: AppHandle,
+    active: State<'_, ActiveProfile>,
+    key: State<'_, DbKey>,
+    profile_id: i64,
+) -> Result<(), AppError> {
+    let conn = open_registry(&app)?;
+    if load_profile(&conn, profile_id, profile_id)?.is_none() {
+        return Err(AppError::NotFound(format!("profile {profile_id} not found")));
+    }
+    let previous = std::mem::replace(&mut *active.0.lock().unwrap(), profile_id);
+    let previous_key = key.0.lock().unwrap().take();
+    db::reset(&app);
+
+    let locked = commands::encryption::is_encrypted(&app).and_then(|locked| {
+        if !locked {
+            db::with_conn(&app, |conn| migrations::run_migrations(conn))?;
+        }
+        Ok(locked)
+    });
+    match locked {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            save_active(&app)?;
+            recurring::materialize_on_startup(&app);
+            Ok(())
+        }
+        Err(e) => {
+            *active.0.lock().unwrap() = previous;
+            *key.0.lock().unwrap() = previous_key;
+            db::reset(&app);
+            Err(e)
+        }
+    }
+}
+
+fn load_profile(conn: &Connection, id: i64, active: i64) -> Result<Option<Profile>, AppError> {
+    conn.query_row(
+        "SELECT id, name, created_at FROM profiles WHERE id = ?1",
+        params![id],
+        |row| {
+            Ok(Profile {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                created_at: row.get(2)?,
+                active: id == active,
+            })
+        },
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_get_their_own_file_and_unique_names() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_registry(&conn).unwrap();
+        init_registry(&conn).unwrap();
+
+        let kids = insert_profile(&conn, "  Kids ", DEFAULT_PROFILE).unwrap();
+        assert_eq!((kids.name.as_str(), kids.active), ("Kids", false));
+        assert!(matches!(insert_profile(&conn, "Kids", 1), Err(AppError::Conflict(_))));
+        assert!(matches!(insert_profile(&conn, " ", 1), Err(AppError::Validation(_))));
+
+        let listed: Vec<_> = profiles(&conn, kids.id)
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.name, p.active))
+            .collect();
+        assert_eq!(listed, [("Default".to_string(), false), ("Kids".to_string(), true)]);
+        assert_eq!(db_file_name(DEFAULT_PROFILE), "budget.db");
+        assert_eq!(db_file_name(kids.id), format!("profile_{}.db", kids.id));
+    }
+}
@@ -0,0 +1,211 @@
+//! Recurring transactions (rent, salary, subscriptions).
+//!
+//! Each rule in `recurring_rules` carries the date of its next occurrence.
+//! On startup every rule that has fallen due is turned into real
+//! transactions and its `next_run` moved past today.
+
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rusqlite::{params, Connection};
+use tauri::{AppHandle, Manager, State};
+
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl FromStr for Interval {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            "monthly" => Ok(Self::Monthly),
+            "yearly" => Ok(Self::Yearly),
+            other => Err(AppError::Validation(format!("unknown recurrence interval {other:?}"))),
+        }
+    }
+}
+
+impl Interval {
+    /// Occurrence after `current`. Monthly and yearly rules keep to the day
+    /// of `start`, clamped to the end of shorter months, so a rule started
+    /// on Jan 31 runs Feb 28/29 and then Mar 31 again.
+    pub fn advance(self, current: NaiveDate, start: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Daily => current + Duration::days(1),
+            Self::Weekly => current + Duration::weeks(1),
+            Self::Monthly => add_months(current, 1, start.day()),
+            Self::Yearly => add_months(current, 12, start.day()),
+        }
+    }
+}
+
+/// IDs of the transactions created when the app started, kept so the
+/// frontend can ask for them once its listeners are up.
+#[derive(Default)]
+pub struct StartupRecurrences(pub Mutex<Vec<i64>>);
+
+#[tauri::command(async)]
+pub fn startup_recurrences(created: State<'_, StartupRecurrences>) -> Vec<i64> {
+    created.0.lock().unwrap().clone()
+}
+
+/// Catches up on rules that fell due while the app was closed. Failures are
+/// logged rather than returned so they never stop the app from starting.
+pub fn materialize_on_startup(app: &AppHandle) {
+    let today = chrono::Local::now().date_naive();
+    match db::with_conn(app, |conn| materialize_due_recurrences(conn, today)) {
+        Ok(ids) => app.state::<StartupRecurrences>().0.lock().unwrap().extend(ids),
+        Err(e) => eprintln!("failed to materialize recurring transactions: {e}"),
+    }
+}
+
+/// Inserts a transaction for every occurrence on or before `today` and
+/// advances each rule's `next_run`. Returns the new transaction IDs.
+pub fn materialize_due_recurrences(
+    conn: &mut Connection,
+    today: NaiveDate,
+) -> Result<Vec<i64>, AppError> {
+    let tx = conn.transaction()?;
+    let today_s = today.format("%Y-%m-%d").to_string();
+    let mut created = Vec::new();
+    {
+        let mut due = tx
+            .prepare(
+                "SELECT id, account_id, description, amount_minor, category_id,
+                        interval, start_date, next_run
+                 FROM recurring_rules WHERE next_run <= ?1",
+            )?;
+        let rules = due
+            .query_map([&today_s], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, String>(7)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut insert = tx
+            .prepare(
+                "INSERT INTO transactions (account_id, date, description, amount_minor, category_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+        let mut advance = tx
+            .prepare("UPDATE recurring_rules SET next_run = ?1 WHERE id = ?2")?;
+
+        for (id, account_id, description, amount, category_id, interval, start, next) in rules {
+            let interval: Interval = interval.parse()?;
+            let start = parse_date(&start)?;
+            let mut next = parse_date(&next)?;
+            while next <= today {
+                insert
+                    .execute(params![
+                        account_id,
+                        next.format("%Y-%m-%d").to_string(),
+                        description,
+                        amount,
+                        category_id
+                    ])?;
+                created.push(tx.last_insert_rowid());
+                next = interval.advance(next, start);
+            }
+            advance
+                .execute(params![next.format("%Y-%m-%d").to_string(), id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(created)
+}
+
+/// Moves `date` forward by `months`, landing on `day` or the last day of the
+/// target month, whichever comes first.
+pub fn add_months(date: NaiveDate, months: u32, day: u32) -> NaiveDate {
+    let total = date.year() * 12 + date.month0() as i32 + months as i32;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+    let day = day.min(days_in_month(year, month));
+    NaiveDate::from_ymd_opt(year, month, day).expect("day clamped to month length")
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|d| d.pred_opt())
+        .map_or(31, |d| d.day())
+}
+
+pub fn parse_date(s: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|e| AppError::Validation(format!("invalid date {s:?}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn date(s: &str) -> NaiveDate {
+        parse_date(s).unwrap()
+    }
+
+    #[test]
+    fn monthly_rules_clamp_to_short_months_and_recover() {
+        let start = date("2024-01-31");
+        let mut next = start;
+        let mut runs = Vec::new();
+        for _ in 0..3 {
+            next = Interval::Monthly.advance(next, start);
+            runs.push(next.to_string());
+        }
+        assert_eq!(runs, ["2024-02-29", "2024-03-31", "2024-04-30"]);
+        let leap_day = date("2024-02-29");
+        assert_eq!(Interval::Yearly.advance(leap_day, leap_day), date("2025-02-28"));
+        assert_eq!(Interval::Weekly.advance(date("2024-12-30"), start), date("2025-01-06"));
+    }
+
+    #[test]
+    fn due_rules_catch_up_and_move_past_today() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO accounts (name) VALUES ('Checking');
+             INSERT INTO recurring_rules
+                 (account_id, description, amount_minor, interval, start_date, next_run)
+             VALUES (1, 'Rent', -90000, 'monthly', '2024-01-31', '2024-01-31'),
+                    (1, 'Gym', -3000, 'yearly', '2024-06-01', '2024-06-01');",
+        )
+        .unwrap();
+
+        let created = materialize_due_recurrences(&mut conn, date("2024-03-15")).unwrap();
+        assert_eq!(created.len(), 2);
+        let dates: Vec<String> = conn
+            .prepare("SELECT date FROM transactions WHERE description = 'Rent' ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(dates, ["2024-01-31", "2024-02-29"]);
+        let next_run: String = conn
+            .query_row("SELECT next_run FROM recurring_rules WHERE id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(next_run, "2024-03-31");
+
+        assert!(materialize_due_recurrences(&mut conn, date("2024-03-15")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+//! App preferences in the `settings` key/value table.
+//!
+//! Keys listed in [`DEFAULTS`] always read as a usable value, falling back
+//! to the default until someone sets them. Reads are a primary-key lookup,
+//! cheap enough to repeat on every window load.
+
+use std::collections::BTreeMap;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::AppHandle;
+
+use crate::backup::{AUTO_BACKUP_ENABLED, AUTO_BACKUP_KEEP};
+use crate::db;
+use crate::error::AppError;
+
+pub const DEFAULT_CURRENCY: &str = "currency.default";
+/// Currency net worth is reported in; [`DEFAULT_CURRENCY`] while unset.
+pub const REPORTING_CURRENCY: &str = "currency.reporting";
+pub const DATE_FORMAT: &str = "display.date_format";
+pub const WEEK_START: &str = "display.week_start";
+/// Day of the month fiscal months start on, 1 to 31.
+pub const FISCAL_MONTH_START_DAY: &str = "fiscal_month_start_day";
+
+/// Known keys and the value they read as while unset.
+pub const DEFAULTS: &[(&str, &str)] = &[
+    (DEFAULT_CURRENCY, "USD"),
+    (DATE_FORMAT, "%Y-%m-%d"),
+    (WEEK_START, "monday"),
+    (FISCAL_MONTH_START_DAY, "1"),
+    (AUTO_BACKUP_ENABLED, "false"),
+    (AUTO_BACKUP_KEEP, "1"),
+];
+
+#[tauri::command(async)]
+pub fn get_setting(app: AppHandle, key: String) -> Result<Option<String>, AppError> {
+    db::with_conn(&app, |conn| get(conn, &key))
+}
+
+#[tauri::command(async)]
+pub fn set_setting(app: AppHandle, key: String, value: String) -> Result<(), AppError> {
+    db::with_conn(&app, |conn| set(conn, &key, &value))
+}
+
+#[tauri::command(async)]
+pub fn get_bool_setting(app: AppHandle, key: String) -> Result<Option<bool>, AppError> {
+    db::with_conn(&app, |conn| get_bool(conn, &key))
+}
+
+#[tauri::command(async)]
+pub fn get_int_setting(app: AppHandle, key: String) -> Result<Option<i64>, AppError> {
+    db::with_conn(&app, |conn| get_int(conn, &key))
+}
+
+/// Every stored setting plus the defaults of known keys not yet set, so a
+/// window can load its preferences in one call.
+#[tauri::command(async)]
+pub fn get_all_settings(app: AppHandle) -> Result<BTreeMap<String, String>, AppError> {
+    db::with_conn(&app, |conn| get_all(conn))
+}
+
+pub fn get_all(conn: &Connection) -> Result<BTreeMap<String, String>, AppError> {
+    let mut all: BTreeMap<String, String> =
+        DEFAULTS.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    let mut stmt = conn.prepare("SELECT key, value FROM settings")?;
+    let stored =
+        stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+    for row in stored {
+        let (key, value) = row?;
+        all.insert(key, value);
+    }
+    Ok(all)
+}
+
+/// The stored value, or the default for a known key.
+pub fn get(conn: &Connection, key: &str) -> Result<Option<String>, AppError> {
+    let stored: Option<String> = conn
+        .query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| row.get(0))
+        .optional()?;
+    Ok(stored.or_else(|| default_for(key).map(str::to_string)))
+}
+
+pub fn set(conn: &Connection, key: &str, value: &str) -> Result<(), AppError> {
+    if key.trim().is_empty() {
+        return Err(AppError::Validation("setting key must not be empty".into()));
+    }
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+pub fn get_bool(conn: &Connection, key: &str) -> Result<Option<bool>, AppError> {
+    get(conn, key)?
+        .map(|v| match v.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(AppError::Validation(format!(
+                "setting {key} is {other:?}, not true or false"
+            ))),
+        })
+        .transpose()
+}
+
+pub fn get_int(conn: &Connection, key: &str) -> Result<Option<i64>, AppError> {
+    get(conn, key)?
+        .map(|v| {
+            v.parse().map_err(|_| {
+                AppError::Validation(format!("setting {key} is {v:?}, not a whole number"))
+            })
+        })
+        .transpose()
+}
+
+pub fn default_for(key: &str) -> Option<&'static str> {
+    DEFAULTS.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn known_keys_read_as_their_default_until_set() {
+        let conn = db();
+        assert_eq!(get(&conn, WEEK_START).unwrap().as_deref(), Some("monday"));
+        assert_eq!(get(&conn, "unknown.key").unwrap(), None);
+
+        set(&conn, WEEK_START, "sunday").unwrap();
+        set(&conn, WEEK_START, "saturday").unwrap();
+        assert_eq!(get(&conn, WEEK_START).unwrap().as_deref(), Some("saturday"));
+        assert!(matches!(set(&conn, " ", "x"), Err(AppError::Validation(_))));
+
+        let all = get_all(&conn).unwrap();
+        assert_eq!(all[WEEK_START], "saturday");
+        assert_eq!(all[DEFAULT_CURRENCY], "USD");
+    }
+
+    #[test]
+    fn typed_getters_refuse_values_of_the_wrong_type() {
+        let conn = db();
+        assert_eq!(get_bool(&conn, AUTO_BACKUP_ENABLED).unwrap(), Some(false));
+        assert_eq!(get_int(&conn, FISCAL_MONTH_START_DAY).unwrap(), Some(1));
+
+        set(&conn, AUTO_BACKUP_ENABLED, "yes").unwrap();
+        set(&conn, FISCAL_MONTH_START_DAY, "15").unwrap();
+        assert!(matches!(get_bool(&conn, AUTO_BACKUP_ENABLED), Err(AppError::Validation(_))));
+        assert_eq!(get_int(&conn, FISCAL_MONTH_START_DAY).unwrap(), Some(15));
+        assert_eq!(get_int(&conn, "unknown.key").unwrap(), None);
+    }
+}
@@ -0,0 +1,155 @@
+//! Month boundaries for reports, optionally following a pay cycle.
+//!
+//! With a fiscal start day of 25, the fiscal month `2024-01` runs from
+//! 25 January to 24 February: a fiscal month is named after the calendar
+//! month it starts in. A start day past the end of a short month starts
+//! that month's period on its last day instead, so with 31 the February
+//! period begins on the 28th (or 29th) and ends on 30 March. A start day
+//! of 1 gives plain calendar months.
+
+use chrono::{Datelike, NaiveDate};
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::recurring::{days_in_month, parse_date};
+use crate::{db, settings};
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FiscalPeriod {
+    /// Calendar year and month the period starts in.
+    pub year: i32,
+    pub month: u32,
+    pub start: String,
+    /// Last day of the period, inclusive.
+    pub end: String,
+}
+
+/// The fiscal month containing `date`, using the `fiscal_month_start_day`
+/// setting.
+#[tauri::command(async)]
+pub fn fiscal_period(app: AppHandle, date: String) -> Result<FiscalPeriod, AppError> {
+    db::with_conn(&app, |conn| period_containing(parse_date(&date)?, month_start_day(conn, true)?))
+}
+
+/// Day months start on for a report: the configured fiscal start day when
+/// `align_to_fiscal` is set, otherwise 1.
+pub fn month_start_day(conn: &Connection, align_to_fiscal: bool) -> Result<u32, AppError> {
+    if !align_to_fiscal {
+        return Ok(1);
+    }
+    let day = settings::get_int(conn, settings::FISCAL_MONTH_START_DAY)?.unwrap_or(1);
+    if !(1..=31).contains(&day) {
+        return Err(AppError::Validation(format!(
+            "fiscal month start day {day} is not between 1 and 31"
+        )));
+    }
+    Ok(day as u32)
+}
+
+/// First day of the period named `year`-`month`.
+pub fn period_start(year: i32, month: u32, start_day: u32) -> Result<NaiveDate, AppError> {
+    if !(1..=12).contains(&month) {
+        return Err(AppError::Validation(format!("invalid month {year}-{month:02}")));
+    }
+    NaiveDate::from_ymd_opt(year, month, start_day.min(days_in_month(year, month)))
+        .ok_or_else(|| AppError::Validation(format!("invalid month {year}-{month:02}")))
+}
+
+/// Half-open `[first day, first day of the next period)` bounds as ISO
+/// dates.
+pub fn period_range(year: i32, month: u32, start_day: u32) -> Result<(String, String), AppError> {
+    let start = period_start(year, month, start_day)?;
+    let (next_year, next_month) = next_month(year, month);
+    let end = period_start(next_year, next_month, start_day)?;
+    Ok((
+        start.format("%Y-%m-%d").to_string(),
+        end.format("%Y-%m-%d").to_string(),
+    ))
+}
+
+pub fn period_containing(date: NaiveDate, start_day: u32) -> Result<FiscalPeriod, AppError> {
+    let (mut year, mut month) = (date.year(), date.month());
+    if date < period_start(year, month, start_day)? {
+        (year, month) = prev_month(year, month);
+    }
+    let start = period_start(year, month, start_day)?;
+    let (next_year, next_month) = next_month(year, month);
+    let end = period_start(next_year, next_month, start_day)?
+        .pred_opt()
+        .ok_or_else(|| AppError::Validation("date out of range".into()))?;
+    Ok(FiscalPeriod {
+        year,
+        month,
+        start: start.format("%Y-%m-%d").to_string(),
+        end: end.format("%Y-%m-%d").to_string(),
+    })
+}
+
+pub fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+pub fn prev_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 1 {
+        (year - 1, 12)
+    } else {
+        (year, month - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::run_migrations;
+
+    fn period(date: &str, start_day: u32) -> (i32, u32, String, String) {
+        let p = period_containing(parse_date(date).unwrap(), start_day).unwrap();
+        (p.year, p.month, p.start, p.end)
+    }
+
+    fn expect(year: i32, month: u32, start: &str, end: &str) -> (i32, u32, String, String) {
+        (year, month, start.to_string(), end.to_string())
+    }
+
+    #[test]
+    fn a_fiscal_month_is_named_after_the_month_it_starts_in() {
+        assert_eq!(period("2024-02-10", 25), expect(2024, 1, "2024-01-25", "2024-02-24"));
+        assert_eq!(period("2024-02-25", 25), expect(2024, 2, "2024-02-25", "2024-03-24"));
+        assert_eq!(period("2024-01-05", 25), expect(2023, 12, "2023-12-25", "2024-01-24"));
+    }
+
+    #[test]
+    fn a_late_start_day_falls_back_to_the_end_of_short_months() {
+        assert_eq!(period("2024-02-29", 31), expect(2024, 2, "2024-02-29", "2024-03-30"));
+        assert_eq!(period("2023-02-28", 30), expect(2023, 2, "2023-02-28", "2023-03-29"));
+        assert_eq!(period("2024-04-29", 31), expect(2024, 3, "2024-03-31", "2024-04-29"));
+        assert_eq!(period("2024-04-30", 31), expect(2024, 4, "2024-04-30", "2024-05-30"));
+    }
+
+    #[test]
+    fn a_start_day_of_one_gives_calendar_months() {
+        assert_eq!(period("2024-12-31", 1), expect(2024, 12, "2024-12-01", "2024-12-31"));
+        let range = period_range(2024, 12, 1).unwrap();
+        assert_eq!(range, ("2024-12-01".to_string(), "2025-01-01".to_string()));
+        assert!(matches!(period_range(2024, 13, 1), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn the_start_day_setting_is_validated() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        assert_eq!(month_start_day(&conn, true).unwrap(), 1);
+        settings::set(&conn, settings::FISCAL_MONTH_START_DAY, "25").unwrap();
+        assert_eq!(month_start_day(&conn, true).unwrap(), 25);
+        assert_eq!(month_start_day(&conn, false).unwrap(), 1);
+        settings::set(&conn, settings::FISCAL_MONTH_START_DAY, "32").unwrap();
+        assert!(matches!(month_start_day(&conn, true), Err(AppError::Validation(_))));
+    }
+}
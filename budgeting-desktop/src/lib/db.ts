@@ -1,13 +1,19 @@
-import Database from "@tauri-apps/plugin-sql";
-import { appDataDir, join } from "@tauri-apps/api/path";
+import { invoke } from "@tauri-apps/api/core";
 
-let dbPromise: Promise<any> | null = null;
+// Statements run on the Rust side's connection (see commands/sql.rs), which
+// holds the passphrase of an encrypted database and follows the active
+// profile. Results have the same shape as @tauri-apps/plugin-sql's.
+export type QueryResult = { rowsAffected: number; lastInsertId: number };
+
+const db = {
+  execute(sql: string, params: unknown[] = []): Promise<QueryResult> {
+    return invoke("sql_execute", { sql, params });
+  },
+  select(sql: string, params: unknown[] = []): Promise<any[]> {
+    return invoke("sql_select", { sql, params });
+  },
+};
 
 export async function getDb() {
-  if (!dbPromise) {
-    const dir = await appDataDir();
-    const dbPath = await join(dir, "budgeting", "budget.db");
-    dbPromise = Database.load(`sqlite:${dbPath}`);
-  }
-  return dbPromise!;
+  return db;
 }
@@ -1,14 +1,7 @@
 import { getDb } from "./db";
 
-const MIGRATIONS = [
-  `CREATE TABLE IF NOT EXISTS accounts(
-     id TEXT PRIMARY KEY,
-     name TEXT NOT NULL,
-     type TEXT NOT NULL,
-     balance REAL NOT NULL DEFAULT 0,
-     currency TEXT NOT NULL
-   );`
-];
+// accounts is created by the Rust migrations (src-tauri/src/migrations.rs).
+const MIGRATIONS: string[] = [];
 
 export async function runMigrations() {
   const db = await getDb();
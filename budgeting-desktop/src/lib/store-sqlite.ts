@@ -59,23 +59,34 @@ async function query<T>(sql: string, params: any[] = []): Promise<T[]> {
 /* ==========================================================
    Accounts
 ========================================================== */
+// The accounts table belongs to the Rust migrations (src-tauri/src/migrations.rs).
+// The frontend identifies an account by its uuid, and its balance is the
+// opening balance plus the live transactions since the opening date.
 export async function listAccounts(): Promise<Account[]> {
   return query<Account>(
-    "SELECT id, name, type, balance, currency FROM accounts ORDER BY name ASC"
+    `SELECT a.uuid AS id, a.name, a.kind AS type, a.currency,
+            (a.opening_balance_minor + COALESCE((
+              SELECT SUM(t.amount_minor) FROM transactions t
+              WHERE t.account_id = a.id AND t.deleted_at IS NULL
+                AND (a.opening_date IS NULL OR t.date >= a.opening_date)
+            ), 0)) / 100.0 AS balance
+     FROM accounts a
+     WHERE NOT a.archived
+     ORDER BY a.name ASC`
   );
 }
 
 export async function addAccount(a: Omit<Account, "id">): Promise<Account> {
   const id = uuid();
   await exec(
-    "INSERT INTO accounts (id, name, type, balance, currency) VALUES (?, ?, ?, ?, ?)",
-    [id, a.name, a.type, a.balance ?? 0, a.currency]
+    "INSERT INTO accounts (uuid, name, kind, opening_balance_minor, currency) VALUES (?, ?, ?, ?, ?)",
+    [id, a.name, a.type, Math.round((a.balance ?? 0) * 100), a.currency]
   );
   return { id, ...a };
 }
 
 export async function deleteAccount(id: string): Promise<void> {
-  await exec("DELETE FROM accounts WHERE id = ?", [id]);
+  await exec("DELETE FROM accounts WHERE uuid = ?", [id]);
 }
 
 /* ==========================================================
@@ -149,15 +160,8 @@ export async function deleteTransfer(id: string): Promise<void> {
 ========================================================== */
 export async function initTables() {
   const db = await getDb();
+  // accounts is created by the Rust migrations.
   const tables = [
-    `CREATE TABLE IF NOT EXISTS accounts (
-      id TEXT PRIMARY KEY,
-      name TEXT NOT NULL,
-      type TEXT NOT NULL,
-      balance REAL NOT NULL DEFAULT 0,
-      currency TEXT NOT NULL
-    );`,
-
     `CREATE TABLE IF NOT EXISTS incomes (
       id TEXT PRIMARY KEY,
       date TEXT,